@@ -0,0 +1,200 @@
+#!/bin/sh
+# Recreates the small databases used by the tests. Requires the sqlite3 CLI.
+set -e
+cd "$(dirname "$0")"
+
+create() {
+    rm -f "$1"
+    sqlite3 "$1"
+}
+
+# An empty table alongside one spread over several pages
+create tables.db <<'SQL'
+PRAGMA page_size = 1024;
+CREATE TABLE empty (id integer primary key, name text);
+CREATE TABLE numbers (id integer primary key, name text);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 500)
+INSERT INTO numbers SELECT i, 'number ' || i FROM n;
+SQL
+
+# One schema object of each type
+create schema.db <<'SQL'
+CREATE TABLE people (id integer primary key, name text, age integer);
+CREATE INDEX idx_people_name ON people (name);
+CREATE VIEW adults AS SELECT name FROM people WHERE age >= 18;
+CREATE TRIGGER people_insert AFTER INSERT ON people BEGIN SELECT 1; END;
+INSERT INTO people (name, age) VALUES ('Alice', 30), ('Bob', 12);
+SQL
+
+# Indexes spread over two tables
+create shop.db <<'SQL'
+CREATE TABLE customers (id integer primary key, name text, city text);
+CREATE TABLE orders (id integer primary key, customer_id integer, total real);
+CREATE INDEX idx_customers_name ON customers (name);
+CREATE INDEX idx_orders_customer ON orders (customer_id);
+CREATE INDEX idx_orders_total ON orders (total);
+INSERT INTO customers (name, city) VALUES ('Ada', 'London'), ('Grace', 'New York');
+INSERT INTO orders (customer_id, total) VALUES (1, 9.5), (2, 20), (1, 3.25);
+SQL
+
+# A rowid alias that isn't the first column, and a table without one
+create rowids.db <<'SQL'
+CREATE TABLE notes (body text, id integer primary key, tag text);
+CREATE TABLE plain (a integer, b text);
+CREATE INDEX idx_plain_b ON plain (b);
+INSERT INTO notes (body, tag) VALUES ('first', NULL), ('second', 'x');
+INSERT INTO plain VALUES (NULL, 'x'), (5, 'y'), (NULL, 'y');
+SQL
+
+# Indexes SQLite creates itself for UNIQUE and PRIMARY KEY constraints, which have no SQL
+create unique.db <<'SQL'
+CREATE TABLE users (id integer primary key, email text UNIQUE, name text, team text, badge integer,
+    UNIQUE (team, badge));
+CREATE TABLE codes (code text PRIMARY KEY, label text);
+CREATE INDEX idx_users_name ON users (name);
+INSERT INTO users (email, name, team, badge) VALUES
+    ('ada@example.com', 'Ada', 'red', 7),
+    ('grace@example.com', 'Grace', 'red', 3),
+    ('alan@example.com', 'Alan', 'blue', 7);
+INSERT INTO codes VALUES ('a', 'first'), ('b', 'second');
+SQL
+
+# A two column index deep enough to need interior pages, with its second column descending,
+# and an index on just its first column to be passed over when both columns are compared
+create composite.db <<'SQL'
+PRAGMA page_size = 512;
+CREATE TABLE events (id integer primary key, kind text, day integer, note text);
+CREATE INDEX idx_events_kind ON events (kind);
+CREATE INDEX idx_events_kind_day ON events (kind, day DESC);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+INSERT INTO events SELECT i, 'kind' || (i % 10), i % 37, 'note ' || i FROM n;
+SQL
+
+# A descending index deep enough to need interior pages
+create indexes.db <<'SQL'
+PRAGMA page_size = 512;
+CREATE TABLE words (id integer primary key, word text);
+CREATE INDEX idx_words_desc ON words (word DESC);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+INSERT INTO words SELECT i, printf('word%04d', i) FROM n;
+SQL
+
+# Indexes that only cover some rows or index an expression, which can't be used for plain lookups
+create partial_indexes.db <<'SQL'
+CREATE TABLE tags (id integer primary key, tag text);
+CREATE INDEX idx_tags_partial ON tags (tag) WHERE id > 2;
+CREATE INDEX idx_tags_lower ON tags (lower(tag));
+INSERT INTO tags (tag) VALUES ('a'), ('a'), ('a'), ('B');
+SQL
+
+# Rows to group and aggregate
+create companies.db <<'SQL'
+CREATE TABLE companies (id integer primary key, name text, country text, industry text, employees integer);
+INSERT INTO companies (name, country, industry, employees) VALUES
+    ('Acme', 'US', 'tech', 100),
+    ('Globex', 'US', 'tech', 250),
+    ('Initech', 'US', 'finance', 50),
+    ('Umbrella', 'UK', 'pharma', 800),
+    ('Hooli', 'UK', 'tech', NULL),
+    ('Soylent', 'FR', 'food', 300),
+    ('Vehement', 'FR', 'food', 120);
+SQL
+
+# Columns with non-default collating sequences and indexes ordered by them, including a NOCASE
+# index deep enough to need interior pages and a second index on its column ordered byte-wise
+create collations.db <<'SQL'
+CREATE TABLE words (id integer primary key, word text COLLATE RTRIM);
+CREATE INDEX idx_words_word ON words (word);
+INSERT INTO words (word) VALUES ('abc'), ('abc  '), ('ABC'), ('abd');
+CREATE TABLE tags (id integer primary key, tag text COLLATE NOCASE);
+CREATE INDEX idx_tags_tag ON tags (tag);
+CREATE INDEX idx_tags_tag_binary ON tags (tag COLLATE BINARY);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+INSERT INTO tags SELECT i, CASE i % 3 WHEN 0 THEN 'tag' WHEN 1 THEN 'Tag' ELSE 'TAG' END || (i % 10) FROM n;
+SQL
+
+# Rows written before a column was added, whose records are shorter than the table
+create altered.db <<'SQL'
+CREATE TABLE items (id integer primary key, name text);
+INSERT INTO items (name) VALUES ('old');
+ALTER TABLE items ADD COLUMN note text;
+INSERT INTO items (name, note) VALUES ('new', 'added');
+ALTER TABLE items ADD COLUMN quantity integer NOT NULL DEFAULT -1;
+ALTER TABLE items ADD COLUMN unit text DEFAULT ('each');
+ALTER TABLE items ADD COLUMN price real DEFAULT 0.5;
+INSERT INTO items (name, note, quantity, unit, price) VALUES ('newest', NULL, 3, 'box', 2.5);
+CREATE TABLE labels (item_id integer, label text);
+INSERT INTO labels VALUES (1, 'first'), (2, 'second');
+SQL
+
+# Text stored as UTF-16
+create utf16.db <<'SQL'
+PRAGMA encoding = 'UTF-16le';
+CREATE TABLE greetings (id integer primary key, greeting text);
+INSERT INTO greetings (greeting) VALUES ('hello'), ('héllo wörld'), ('こんにちは');
+SQL
+
+# Indexed columns whose values are compared with literals of another type. The small pages give
+# the indexes interior pages.
+create affinity.db <<'SQL'
+PRAGMA page_size = 512;
+CREATE TABLE codes (id integer primary key, code text, quantity integer);
+CREATE INDEX idx_codes_code ON codes (code);
+CREATE INDEX idx_codes_quantity ON codes (quantity);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 300)
+INSERT INTO codes SELECT i, i, i * 2 FROM n;
+SQL
+
+# Enough tables that the schema table's rows don't fit on the first page
+{
+    echo "PRAGMA page_size = 512;"
+    for i in $(seq 1 40); do
+        echo "CREATE TABLE table_$i (id integer primary key, value text);"
+    done
+    echo "INSERT INTO table_40 (value) VALUES ('last');"
+} | create many_tables.db
+
+# An indexed column to look rows up by, alongside text to match with LIKE and GLOB
+create books.db <<'SQL'
+PRAGMA page_size = 512;
+CREATE TABLE books (id integer primary key, title text, category text);
+CREATE INDEX idx_books_category ON books (category);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200)
+INSERT INTO books (title, category) SELECT 'Volume ' || i, 'category ' || (i % 20) FROM n;
+INSERT INTO books (title, category) VALUES
+    ('The Hobbit', 'books'),
+    ('the road', 'books'),
+    ('Dune', 'books'),
+    ('The Wire', 'tv');
+SQL
+
+# Table and column names that have to be quoted
+create quoted.db <<'SQL'
+CREATE TABLE "My Table" (id integer primary key, "first name" text, [last-name] text);
+INSERT INTO "My Table" ("first name", [last-name]) VALUES ('Ada', 'Lovelace'), ('Alan', 'Turing');
+SQL
+
+# Blobs alongside values of every other storage class, to compare and sort them
+create blobs.db <<'SQL'
+CREATE TABLE files (id integer primary key, name text, data blob);
+INSERT INTO files (name, data) VALUES
+    ('a', X'00'),
+    ('b', X'0001'),
+    ('c', X'FF'),
+    ('d', X''),
+    ('e', 'text'),
+    ('f', NULL),
+    ('g', 5);
+SQL
+
+# Rowids spread over the whole signed range, including both extremes, found through an index
+create large_rowids.db <<'SQL'
+PRAGMA page_size = 512;
+CREATE TABLE items (id integer primary key, label text);
+CREATE INDEX idx_items_label ON items (label);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 300)
+INSERT INTO items (id, label) SELECT i * 30000000000000000 - 4500000000000000000, 'label ' || (i % 5) FROM n;
+INSERT INTO items (id, label) VALUES
+    (9223372036854775807, 'label 3'),
+    (-9223372036854775808, 'label 3');
+SQL
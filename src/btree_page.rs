@@ -1,3 +1,4 @@
+use std::fmt::{Display, Formatter};
 use std::io::{Cursor, Read, Seek, SeekFrom};
 
 use anyhow::{anyhow, bail, Context, Result};
@@ -5,7 +6,7 @@ use byteorder::{BigEndian, ReadBytesExt};
 
 use crate::db_header::DBHeader;
 use crate::schema_object::SchemaObject;
-use crate::serial_value::SerialValue;
+use crate::serial_value::{SerialValue, TextEncoding};
 use crate::ReadVarint;
 
 #[derive(Debug)]
@@ -35,6 +36,17 @@ impl PageType {
     }
 }
 
+impl Display for PageType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InteriorIndex => write!(f, "interior index"),
+            Self::InteriorTable => write!(f, "interior table"),
+            Self::LeafIndex => write!(f, "leaf index"),
+            Self::LeafTable => write!(f, "leaf table"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum InteriorCell {
     Table(InteriorTableCell),
@@ -63,6 +75,8 @@ pub struct BTreePage {
 
     // Only populated for the first page
     db_header: Option<DBHeader>,
+    /// How the text values in the page's records are encoded
+    text_encoding: TextEncoding,
 
     // Page header content
     pub page_type: PageType,
@@ -99,9 +113,29 @@ impl BTreePage {
             cell_pointers.push(cursor.read_u16::<BigEndian>()?)
         }
 
+        let content_start = content_area_start(cell_content_start);
+        if content_start < cursor.position() as usize {
+            bail!(
+                "cell content area starts at {}, inside the page header",
+                content_start
+            );
+        }
+        for (ind, &cp) in cell_pointers.iter().enumerate() {
+            if !(content_start..data.len()).contains(&(cp as usize)) {
+                bail!(
+                    "cell pointer {} is out of bounds: {} is outside the cell content area {}..{}",
+                    ind,
+                    cp,
+                    content_start,
+                    data.len()
+                );
+            }
+        }
+
         Ok(Self {
             page_data: data.to_owned(),
             db_header,
+            text_encoding: TextEncoding::default(),
             page_type,
             first_freeblock,
             num_cells,
@@ -112,6 +146,49 @@ impl BTreePage {
         })
     }
 
+    /// The offset of the cell content area, where cells are stored from the end of the page
+    /// backwards.
+    pub fn cell_content_start(&self) -> usize {
+        content_area_start(self.cell_content_start)
+    }
+
+    /// The number of unused bytes on the page: the gap between the cell pointer array and the
+    /// cell content area, every block on the freeblock chain and the fragmented free bytes.
+    pub fn free_space(&self) -> Result<usize> {
+        let header_start = match self.db_header {
+            Some(_) => DBHeader::SIZE,
+            None => 0,
+        };
+        let header_size = if self.page_type.is_interior() { 12 } else { 8 };
+        let pointers_end = header_start + header_size + 2 * self.cell_pointers.len();
+        let content_start = self.cell_content_start();
+        let mut free = content_start.saturating_sub(pointers_end);
+
+        // Each freeblock starts with the offset of the next one, or 0 at the end of the chain,
+        // followed by its own size. Offsets must increase so the chain can't loop.
+        let mut offset = self.first_freeblock as usize;
+        let mut min_offset = content_start;
+        while offset != 0 {
+            if offset < min_offset || offset + 4 > self.page_data.len() {
+                bail!("freeblock at offset {} is out of bounds", offset);
+            }
+            let next = u16::from_be_bytes([self.page_data[offset], self.page_data[offset + 1]]);
+            let size = u16::from_be_bytes([self.page_data[offset + 2], self.page_data[offset + 3]]);
+            if offset + size as usize > self.page_data.len() {
+                bail!(
+                    "freeblock at offset {} with size {} runs past the end of the page",
+                    offset,
+                    size
+                );
+            }
+            free += size as usize;
+            min_offset = offset + size as usize;
+            offset = next as usize;
+        }
+
+        Ok(free + self.num_fragmented_free_bytes as usize)
+    }
+
     pub fn read_interior_cell(&self, data: &[u8]) -> Result<InteriorCell> {
         let mut reader = Cursor::new(data);
         let left_child_page = reader
@@ -125,7 +202,7 @@ impl BTreePage {
             })),
             PageType::InteriorIndex => {
                 let _payload_bytes = reader.read_varint().context("read payload bytes")?;
-                let payload = read_payload(&mut reader)?;
+                let payload = read_payload(&mut reader, self.text_encoding)?;
 
                 let Some((rowid, columns)) = payload.split_last() else {
                     bail!("interior index cell should have at least two values")
@@ -160,7 +237,11 @@ impl BTreePage {
         Ok(result)
     }
 
-    pub fn read_cell(&self, data: &[u8]) -> Result<Vec<SerialValue>> {
+    /// Reads the record in a cell. `rowid_column` is the position of the table's rowid alias
+    /// column, if it has one: the record only stores NULL for that column, so it's replaced with
+    /// the cell's rowid. An integer stored there instead must equal the rowid, or the record is
+    /// rejected. It's ignored for index pages, which have no rowid outside the record.
+    pub fn read_cell(&self, data: &[u8], rowid_column: Option<usize>) -> Result<Vec<SerialValue>> {
         let mut reader = Cursor::new(data);
 
         let _payload_size = reader.read_varint().context("read payload size")?;
@@ -170,33 +251,141 @@ impl BTreePage {
             _ => None,
         };
 
-        let mut values = read_payload(&mut reader)?;
-
-        // FIXME: This is a terrible hack. I should actually figure out when it's appropriate to
-        // substitute the rowid value for the ID column.
-        if let SerialValue::Null = values[0] {
-            if let Some(id) = row_id {
-                values[0] = SerialValue::Int64(id as i64);
+        let mut values = read_payload(&mut reader, self.text_encoding)?;
+
+        if let (Some(id), Some(value)) = (row_id, rowid_column.and_then(|i| values.get_mut(i))) {
+            let id = id as i64;
+            match value.as_i64() {
+                // SQLite always writes NULL here, but a stored integer is consistent as long as
+                // it's the rowid itself
+                Some(stored) if stored != id => bail!(
+                    "rowid alias column stores {} but the row's rowid is {}",
+                    stored,
+                    id
+                ),
+                Some(_) => *value = SerialValue::Int64(id),
+                None if value.is_null() => *value = SerialValue::Int64(id),
+                None => {}
             }
         }
 
         Ok(values)
     }
 
-    pub fn read_cells(&self) -> Result<Vec<Vec<SerialValue>>> {
-        let num_ptrs = self.cell_pointers.len();
-        let mut result = Vec::with_capacity(num_ptrs);
+    /// Reads the record in a leaf cell exactly as it's stored: each value along with the serial
+    /// type code from the record header that says how it's encoded. Unlike `read_cell`, the NULL
+    /// stored for a rowid alias column is left as it is.
+    pub fn read_cell_serial_types(&self, data: &[u8]) -> Result<Vec<(u64, SerialValue)>> {
+        let mut reader = Cursor::new(data);
+        let _payload_size = reader.read_varint().context("read payload size")?;
+        match self.page_type {
+            PageType::LeafTable => {
+                reader.read_varint().context("read row ID")?;
+            }
+            PageType::LeafIndex => {}
+            _ => bail!("can only read records from leaf pages"),
+        }
+        read_typed_payload(&mut reader, self.text_encoding)
+    }
+
+    /// Finds the row with `rowid` on a leaf table page. Cells are in rowid order, so only the
+    /// rowids of the cells before it are read. `rowid_column` is as for `read_cell`.
+    pub fn find_row(
+        &self,
+        rowid: u64,
+        rowid_column: Option<usize>,
+    ) -> Result<Option<Vec<SerialValue>>> {
+        if !matches!(self.page_type, PageType::LeafTable) {
+            bail!("can only find rows on leaf table pages");
+        }
         for &cp in &self.cell_pointers {
             let cell_data = &self.page_data[cp as usize..];
-            let cell = self.read_cell(cell_data).context("reading cell data")?;
-            result.push(cell);
+            let mut reader = Cursor::new(cell_data);
+            let _payload_size = reader.read_varint().context("read payload size")?;
+            let cell_rowid = reader.read_varint().context("read row ID")?;
+            if cell_rowid == rowid {
+                let row = self
+                    .read_cell(cell_data, rowid_column)
+                    .with_context(|| format!("reading cell at offset {}", cp))?;
+                return Ok(Some(row));
+            }
+            // Rowids are signed, so compare them that way to know when the row has been passed
+            if cell_rowid as i64 > rowid as i64 {
+                break;
+            }
         }
-        Ok(result)
+        Ok(None)
+    }
+
+    /// Sets the encoding text is decoded with, which is UTF-8 unless set otherwise.
+    pub fn with_text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.text_encoding = encoding;
+        self
     }
 
+    /// The number of columns in the record stored in the first cell of a leaf page. Only the
+    /// record header is read, none of the values are decoded.
+    pub fn first_record_column_count(&self) -> Result<usize> {
+        let &cp = self
+            .cell_pointers
+            .first()
+            .context("page has no cells to count columns in")?;
+        let mut reader = Cursor::new(&self.page_data[cp as usize..]);
+
+        let _payload_size = reader.read_varint().context("read payload size")?;
+        match self.page_type {
+            PageType::LeafTable => {
+                reader.read_varint().context("read row ID")?;
+            }
+            PageType::LeafIndex => {}
+            _ => bail!("can only count record columns on leaf pages"),
+        }
+
+        Ok(read_serial_types(&mut reader)?.len())
+    }
+
+    /// Reads the record in every cell on the page, in cell order. `rowid_column` is as for
+    /// `read_cell`.
+    pub fn read_cells(&self, rowid_column: Option<usize>) -> Result<Vec<Vec<SerialValue>>> {
+        self.cells_iter(rowid_column).collect()
+    }
+
+    /// Reads the records in the cells on the page one at a time, in cell order, so the cells after
+    /// the last one taken are never decoded. `rowid_column` is as for `read_cell`.
+    pub fn cells_iter(
+        &self,
+        rowid_column: Option<usize>,
+    ) -> impl Iterator<Item = Result<Vec<SerialValue>>> + '_ {
+        (0..self.cell_pointers.len()).map(move |index| self.read_cell_at(index, rowid_column))
+    }
+
+    /// Reads the record in the cell at `index` in the page's cell pointer array. `rowid_column`
+    /// is as for `read_cell`.
+    pub fn read_cell_at(
+        &self,
+        index: usize,
+        rowid_column: Option<usize>,
+    ) -> Result<Vec<SerialValue>> {
+        let &cp = self.cell_pointers.get(index).with_context(|| {
+            format!(
+                "cell {} is out of range: the page has {} cells",
+                index, self.num_cells
+            )
+        })?;
+        self.read_cell(&self.page_data[cp as usize..], rowid_column)
+            .with_context(|| format!("reading cell at offset {}", cp))
+    }
+
+    /// Reads the schema objects stored on a leaf page of the schema table.
     pub fn load_schemas(&self) -> Result<Vec<SchemaObject>> {
+        if self.page_type.is_interior() {
+            bail!(
+                "schema objects are stored on leaf pages, not {} pages",
+                self.page_type
+            );
+        }
         let mut result = Vec::with_capacity(self.cell_pointers.len());
-        let cells = self.read_cells().context("reading schema cells")?;
+        let cells = self.read_cells(None).context("reading schema cells")?;
         for cell in cells {
             result.push(SchemaObject::from(cell).context("construct schema object")?);
         }
@@ -204,7 +393,48 @@ impl BTreePage {
     }
 }
 
-fn read_payload<T>(reader: &mut T) -> Result<Vec<SerialValue>>
+/// A cell content start of 0 stands for 65536, which only occurs on empty 64KiB pages.
+fn content_area_start(cell_content_start: u16) -> usize {
+    match cell_content_start {
+        0 => 65536,
+        start => start as usize,
+    }
+}
+
+fn read_payload<T>(reader: &mut T, encoding: TextEncoding) -> Result<Vec<SerialValue>>
+where
+    T: Read + Seek,
+{
+    Ok(read_typed_payload(reader, encoding)?
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect())
+}
+
+/// Reads a record, keeping the serial type each value was stored with.
+fn read_typed_payload<T>(reader: &mut T, encoding: TextEncoding) -> Result<Vec<(u64, SerialValue)>>
+where
+    T: Read + Seek,
+{
+    let column_serial_types = read_serial_types(reader)?;
+
+    let mut values = Vec::with_capacity(column_serial_types.len());
+    for (column, st) in column_serial_types.into_iter().enumerate() {
+        let position = reader.stream_position()?;
+        let value = SerialValue::read(st, encoding, reader).with_context(|| {
+            format!(
+                "reading serial value for column {} at byte {} of the cell",
+                column, position
+            )
+        })?;
+        values.push((st, value));
+    }
+
+    Ok(values)
+}
+
+/// Reads a record header, leaving the reader at the start of the record's values.
+fn read_serial_types<T>(reader: &mut T) -> Result<Vec<u64>>
 where
     T: Read + Seek,
 {
@@ -219,11 +449,264 @@ where
             .context("read column serial type varint")?;
         column_serial_types.push(column_type);
     }
+    Ok(column_serial_types)
+}
 
-    let mut values = Vec::with_capacity(column_serial_types.len());
-    for st in column_serial_types {
-        values.push(SerialValue::read(st, reader).context("reading serial value")?)
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::db_file::DBFile;
+    use crate::testing::{record, table_leaf_cell};
+
+    /// A leaf table page with a single cell pointer and its cell content area starting at 500.
+    fn leaf_page(cell_pointer: u16) -> Vec<u8> {
+        let mut page = vec![0; 512];
+        page[0] = 0x0d;
+        page[3..5].copy_from_slice(&1u16.to_be_bytes());
+        page[5..7].copy_from_slice(&500u16.to_be_bytes());
+        page[8..10].copy_from_slice(&cell_pointer.to_be_bytes());
+        page
     }
 
-    Ok(values)
+    /// Adds a freeblock to a page built by `leaf_page`, linking it to `next`.
+    fn add_freeblock(page: &mut [u8], offset: usize, size: u16, next: u16) {
+        page[offset..offset + 2].copy_from_slice(&next.to_be_bytes());
+        page[offset + 2..offset + 4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    #[test]
+    fn free_space() {
+        let mut page = leaf_page(500);
+        page[5..7].copy_from_slice(&400u16.to_be_bytes());
+        page[1..3].copy_from_slice(&420u16.to_be_bytes());
+        add_freeblock(&mut page, 420, 30, 460);
+        add_freeblock(&mut page, 460, 20, 0);
+        page[7] = 3;
+        // 390 bytes between the cell pointer and the content area, 50 bytes in freeblocks and 3
+        // fragmented bytes
+        assert_eq!(
+            BTreePage::new(&page, None).unwrap().free_space().unwrap(),
+            443
+        );
+
+        add_freeblock(&mut page, 460, 20, 440);
+        let err = BTreePage::new(&page, None)
+            .unwrap()
+            .free_space()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "freeblock at offset 440 is out of bounds");
+    }
+
+    #[test]
+    fn cell_pointer_bounds() {
+        assert!(BTreePage::new(&leaf_page(500), None).is_ok());
+        for cell_pointer in [600, 499, 10] {
+            let err = BTreePage::new(&leaf_page(cell_pointer), None).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "cell pointer 0 is out of bounds: {} is outside the cell content area 500..512",
+                    cell_pointer
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn built_records() {
+        let values = vec![
+            SerialValue::Null,
+            SerialValue::Text(String::from("apple")),
+            SerialValue::Int16(-300),
+            SerialValue::One,
+            SerialValue::Float64(2.5),
+            SerialValue::Blob(vec![1, 2, 3]),
+        ];
+        let data = record(&values);
+        assert_eq!(data[0], 7);
+        let mut reader = Cursor::new(data.as_slice());
+        assert_eq!(
+            read_payload(&mut reader, TextEncoding::Utf8).unwrap(),
+            values
+        );
+        assert_eq!(reader.position() as usize, data.len());
+
+        // The rowid only stands in for the rowid alias column, and only when it's NULL
+        let page = BTreePage::new(&leaf_page(500), None).unwrap();
+        let cell = table_leaf_cell(300, &values);
+        let mut expected = values.clone();
+        expected[0] = SerialValue::Int64(300);
+        assert_eq!(page.read_cell(&cell, Some(0)).unwrap(), expected);
+        assert_eq!(page.read_cell(&cell, None).unwrap(), values);
+        assert_eq!(page.read_cell(&cell, Some(1)).unwrap(), values);
+
+        // The alias doesn't have to be the first column
+        let values = [
+            SerialValue::Text(String::from("a")),
+            SerialValue::Null,
+            SerialValue::Null,
+        ];
+        let cell = table_leaf_cell(7, &values);
+        assert_eq!(
+            page.read_cell(&cell, Some(1)).unwrap(),
+            [
+                SerialValue::Text(String::from("a")),
+                SerialValue::Int64(7),
+                SerialValue::Null
+            ]
+        );
+        assert_eq!(page.read_cell(&cell, None).unwrap(), values);
+
+        // An integer stored for the alias has to be the rowid
+        let values = [
+            SerialValue::Int16(300),
+            SerialValue::Text(String::from("a")),
+        ];
+        let cell = table_leaf_cell(300, &values);
+        assert_eq!(
+            page.read_cell(&cell, Some(0)).unwrap(),
+            [
+                SerialValue::Int64(300),
+                SerialValue::Text(String::from("a"))
+            ]
+        );
+        let cell = table_leaf_cell(301, &values);
+        assert_eq!(
+            page.read_cell(&cell, Some(0)).unwrap_err().to_string(),
+            "rowid alias column stores 300 but the row's rowid is 301"
+        );
+        assert_eq!(page.read_cell(&cell, None).unwrap(), values);
+
+        // Enough columns that the header size needs a two byte varint
+        let values = vec![SerialValue::Zero; 200];
+        let data = record(&values);
+        assert_eq!(&data[..2], [0x81, 0x4a]);
+        let mut reader = Cursor::new(data.as_slice());
+        assert_eq!(
+            read_payload(&mut reader, TextEncoding::Utf8).unwrap(),
+            values
+        );
+    }
+
+    #[test]
+    fn serial_types() {
+        let page = BTreePage::new(&leaf_page(500), None).unwrap();
+        let values = [
+            SerialValue::Null,
+            SerialValue::Zero,
+            SerialValue::One,
+            SerialValue::Int8(-5),
+            SerialValue::Int16(1000),
+            SerialValue::Float64(0.5),
+            SerialValue::Text(String::from("abc")),
+            SerialValue::Blob(vec![1, 2]),
+        ];
+        let cell = table_leaf_cell(1, &values);
+        assert_eq!(
+            page.read_cell_serial_types(&cell).unwrap(),
+            [0, 8, 9, 1, 2, 7, 19, 16]
+                .into_iter()
+                .zip(values)
+                .collect::<Vec<_>>()
+        );
+
+        // The rowid alias column is stored as NULL
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let page = db_file.load_page_at(2).unwrap();
+        let first = page.cell_pointers[0] as usize;
+        assert_eq!(
+            page.read_cell_serial_types(&page.page_data[first..])
+                .unwrap(),
+            [
+                (0, SerialValue::Null),
+                (37, SerialValue::Text(String::from("Granny Smith"))),
+                (35, SerialValue::Text(String::from("Light Green"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn lazy_cells() {
+        let mut page = leaf_page(400);
+        page[3..5].copy_from_slice(&3u16.to_be_bytes());
+        page[5..7].copy_from_slice(&400u16.to_be_bytes());
+        page[10..12].copy_from_slice(&450u16.to_be_bytes());
+        page[12..14].copy_from_slice(&506u16.to_be_bytes());
+        let first = table_leaf_cell(1, &[SerialValue::Text(String::from("one"))]);
+        let second = table_leaf_cell(2, &[SerialValue::Text(String::from("two"))]);
+        page[400..400 + first.len()].copy_from_slice(&first);
+        page[450..450 + second.len()].copy_from_slice(&second);
+        // The last cell's record claims 94 bytes of text, running off the end of the page
+        page[506..511].copy_from_slice(&[5, 3, 3, 0x81, 0x49]);
+        let page = BTreePage::new(&page, None).unwrap();
+
+        let rows = page
+            .cells_iter(None)
+            .take(2)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                [SerialValue::Text(String::from("one"))],
+                [SerialValue::Text(String::from("two"))]
+            ]
+        );
+        assert_eq!(page.cells_iter(None).count(), 3);
+        assert!(page.cells_iter(None).nth(2).unwrap().is_err());
+        assert!(page.read_cells(None).is_err());
+    }
+
+    #[test]
+    fn interior_first_page() {
+        // The page header of the first page follows the 100 byte database header, but cell
+        // pointers are still offsets from the start of the page
+        let mut page = std::fs::read("fixtures/tables.db").unwrap()[..512].to_vec();
+        let header = DBHeader::from_bytes(&page[..DBHeader::SIZE]).unwrap();
+        page[DBHeader::SIZE..].fill(0);
+        page[100] = 0x05;
+        page[103..105].copy_from_slice(&1u16.to_be_bytes());
+        page[105..107].copy_from_slice(&500u16.to_be_bytes());
+        page[108..112].copy_from_slice(&3u32.to_be_bytes());
+        page[112..114].copy_from_slice(&500u16.to_be_bytes());
+        page[500..504].copy_from_slice(&2u32.to_be_bytes());
+        page[504] = 7;
+
+        let page = BTreePage::new(&page, Some(header)).unwrap();
+        assert_eq!(page.num_cells, 1);
+        assert_eq!(page.right_most_pointer, Some(3));
+        assert_eq!(page.cell_pointers, [500]);
+        assert_eq!(page.free_space().unwrap(), 500 - 114);
+        let cells = page.read_interior_cells().unwrap();
+        let [InteriorCell::Table(cell)] = cells.as_slice() else {
+            panic!("expected a single interior table cell")
+        };
+        assert_eq!((cell.left_child_page, cell.row_id), (2, 7));
+    }
+
+    #[test]
+    fn reserved_serial_type() {
+        let mut page = leaf_page(500);
+        // Payload size, rowid, then a record header declaring an 8-bit integer and serial type 10
+        page[500..506].copy_from_slice(&[4, 1, 3, 1, 10, 7]);
+        let page = BTreePage::new(&page, None).unwrap();
+        let err = page.read_cells(None).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "reading cell at offset 500: reading serial value for column 1 at byte 6 of the cell: \
+             serial type 10 is reserved for internal use"
+        );
+    }
+
+    #[test]
+    fn first_record_column_count() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let (_, page) = db_file.load_table("apples").unwrap();
+        assert_eq!(page.first_record_column_count().unwrap(), 3);
+        assert_eq!(db_file.first_page.first_record_column_count().unwrap(), 5);
+    }
 }
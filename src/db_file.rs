@@ -1,90 +1,521 @@
-use crate::btree_page::BTreePage;
+use crate::btree_page::{BTreePage, InteriorCell, PageType};
 use crate::db_header::DBHeader;
+use crate::query::{
+    explain_select, for_each_result_row, pad_row, ColumnMeta, QueryPlan, QueryResult, QueryStats,
+    Row,
+};
 use crate::schema_object::{ObjectType, SchemaObject};
-use crate::sql::sql::sql_statement;
-use crate::sql::Statement;
-use anyhow::{anyhow, Context, Result};
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use crate::serial_value::{Collation, SerialValue, TextEncoding};
+use crate::sql::parse_statement;
+use crate::sql::{rowid_column, CreateIndexStatement, IndexColumn, Statement};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::RangeInclusive;
+use std::time::Instant;
 
-const SQLITE_TABLE_PREFIX: &str = "sqlite_";
+/// The start of the names of the indexes SQLite creates for `UNIQUE` and `PRIMARY KEY`
+/// constraints, which are followed by the table name and the constraint's number.
+const AUTOINDEX_PREFIX: &str = "sqlite_autoindex_";
 
-pub struct DBFile<'a> {
-    file: &'a mut File,
+/// The names the schema table can be queried by
+const SCHEMA_TABLE_NAMES: [&str; 2] = ["sqlite_schema", "sqlite_master"];
 
-    pub header: DBHeader,
-    pub first_page: BTreePage,
+/// https://www.sqlite.org/schematab.html
+const SCHEMA_TABLE_SQL: &str =
+    "CREATE TABLE sqlite_schema(type text, name text, tbl_name text, rootpage integer, sql text)";
+
+/// Anything a database can be read from, such as a `File` or an in-memory `Cursor`.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Records that a b-tree walk has reached `page`. A page can only appear once in a well formed
+/// b-tree, so reaching it again means the database is corrupt and the walk would never finish.
+pub fn mark_visited(visited: &mut HashSet<usize>, page: usize) -> Result<()> {
+    if !visited.insert(page) {
+        bail!("cycle detected: page {} is reached more than once", page);
+    }
+    Ok(())
 }
 
-impl<'a> DBFile<'a> {
-    pub fn new(file: &'a mut File) -> Result<Self> {
+/// The number of pages `DBFile::new` keeps cached.
+const DEFAULT_CACHE_SIZE: usize = 256;
+
+/// Settings for opening a database. Each setter returns the options so they can be chained,
+/// finishing with `open`:
+///
+/// ```no_run
+/// # use sqlite_starter_rust::db_file::DBFileOptions;
+/// let file = std::fs::File::open("sample.db")?;
+/// let db_file = DBFileOptions::new().cache_size(16).strict(false).open(file)?;
+/// # anyhow::Ok(())
+/// ```
+///
+/// Only the main database file is ever read, so there's no option to read a write-ahead log:
+/// changes still in a `-wal` file aren't seen.
+#[derive(Debug, Clone)]
+pub struct DBFileOptions {
+    cache_size: usize,
+    text_encoding: Option<TextEncoding>,
+    strict: bool,
+    max_pages: Option<usize>,
+    max_rows: Option<usize>,
+}
+
+impl Default for DBFileOptions {
+    fn default() -> Self {
+        Self {
+            cache_size: DEFAULT_CACHE_SIZE,
+            text_encoding: None,
+            strict: true,
+            max_pages: None,
+            max_rows: None,
+        }
+    }
+}
+
+impl DBFileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of pages to keep in memory once read. When the cache is full the page that was
+    /// read longest ago is evicted, however recently it was used. 0 turns the cache off.
+    pub fn cache_size(mut self, pages: usize) -> Self {
+        self.cache_size = pages;
+        self
+    }
+
+    /// Decodes text with this encoding instead of the one named in the database header.
+    pub fn text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.text_encoding = Some(encoding);
+        self
+    }
+
+    /// Whether a header that doesn't match the file is an error, which is the default. When
+    /// lenient, a file shorter than the header records is opened anyway and an unknown text
+    /// encoding is read as UTF-8. Reading a page past the end of the file is an error either way.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Fails any query, or scan with `DBFile::for_each_row`, that reads more than this many pages
+    /// from the file, so a query against an untrusted or very large database can't read all of
+    /// it. Pages served from the cache don't count.
+    pub fn max_pages(mut self, pages: usize) -> Self {
+        self.max_pages = Some(pages);
+        self
+    }
+
+    /// Fails any query, or scan with `DBFile::for_each_row`, that reads more than this many rows
+    /// from tables, or that makes more than this many rows by joining them. The count is checked
+    /// as the rows are read, so the query stops before holding them all in memory.
+    pub fn max_rows(mut self, rows: usize) -> Self {
+        self.max_rows = Some(rows);
+        self
+    }
+
+    pub fn open<'a>(&self, file: impl Read + Seek + 'a) -> Result<DBFile<'a>> {
+        let mut file = Box::new(file);
+        let file_size = file.seek(SeekFrom::End(0)).context("measuring file size")?;
+        let too_short = || {
+            anyhow!(
+                "file is not a valid SQLite database: too short ({} bytes)",
+                file_size
+            )
+        };
+        if file_size < DBHeader::SIZE as u64 {
+            return Err(too_short());
+        }
+        file.seek(SeekFrom::Start(0))?;
         let mut header = [0; DBHeader::SIZE];
-        file.read_exact(&mut header)?;
-        let db_header = DBHeader::from_bytes(&header).expect("should parse header");
+        file.read_exact(&mut header).context("reading header")?;
+        let db_header = DBHeader::from_bytes(&header)
+            .map_err(|err| anyhow!("file is not a valid SQLite database: {}", err))?;
+
+        let text_encoding = match self.text_encoding {
+            Some(encoding) => encoding,
+            None => match TextEncoding::from_header_value(db_header.db_text_encoding()) {
+                Ok(encoding) => encoding,
+                Err(_) if !self.strict => TextEncoding::Utf8,
+                Err(err) => return Err(err),
+            },
+        };
 
         // Seek back to the start of the file
         file.seek(SeekFrom::Start(0))?;
 
+        // Even an empty database has its whole first page
         let mut page = vec![0u8; db_header.page_size() as usize];
-        file.read_exact(&mut page)?;
-        let page = BTreePage::new(&page, Some(db_header)).expect("should construct BTree page");
+        if file_size < page.len() as u64 {
+            return Err(too_short());
+        }
+        file.read_exact(&mut page).context("reading first page")?;
+        let page = BTreePage::new(&page, Some(db_header))
+            .context("reading first page")?
+            .with_text_encoding(text_encoding);
 
-        Ok(Self {
+        let mut db_file = DBFile {
             file,
+            pages_loaded: 0,
+            rows_scanned: 0,
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+            cache_size: self.cache_size,
+            text_encoding,
+            limits: QueryLimits::default(),
+            page_count: file_size as usize / db_header.page_size() as usize,
             header: db_header,
             first_page: page,
-        })
+            schema_pages: vec![],
+        };
+
+        // A file shorter than the header claims has lost pages, either to corruption or because a
+        // hot journal still needs to be rolled back
+        if let Some(header_pages) = db_file.header_page_count().filter(|_| self.strict) {
+            let file_pages = db_file.page_count();
+            if header_pages > file_pages {
+                bail!(
+                    "database file is truncated: the header records {} pages but the file has {}",
+                    header_pages,
+                    file_pages
+                );
+            }
+        }
+
+        // The schema table is a b-tree rooted at the first page. Once it outgrows that page its
+        // rows move to leaf pages below it.
+        if let PageType::InteriorTable = db_file.first_page.page_type {
+            let mut leaves = vec![];
+            db_file
+                .for_each_leaf_page(1, |page| {
+                    leaves.push(page);
+                    Ok(())
+                })
+                .context("loading schema pages")?;
+            db_file.schema_pages = leaves;
+        }
+
+        // Only queries are limited, not reading the schema while opening
+        db_file.limits = QueryLimits {
+            max_pages: self.max_pages,
+            max_rows: self.max_rows,
+            ..QueryLimits::default()
+        };
+        Ok(db_file)
+    }
+}
+
+/// The caps set with `DBFileOptions::max_pages` and `max_rows`, and where the current query
+/// started counting pages from.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct QueryLimits {
+    max_pages: Option<usize>,
+    max_rows: Option<usize>,
+    /// The value of `pages_loaded` when the current query started
+    first_page_loaded: usize,
+}
+
+impl QueryLimits {
+    /// Fails once more rows than `max_rows` have been read or produced.
+    pub(crate) fn check_rows(&self, rows: usize) -> Result<()> {
+        match self.max_rows {
+            Some(max_rows) if rows > max_rows => {
+                bail!("query exceeded the limit of {} rows", max_rows)
+            }
+            _ => Ok(()),
+        }
     }
+}
+
+/// An index chosen to look up rows by the values of its leading columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexLookup {
+    pub name: String,
+    pub root_page: usize,
+    /// The leading columns of the index that the lookup is by, in index order
+    pub columns: Vec<IndexColumn>,
+    /// The collating sequence the entries of each of `columns` are ordered by
+    pub collations: Vec<Collation>,
+}
+
+pub struct DBFile<'a> {
+    file: Box<dyn ReadSeek + 'a>,
+    /// The number of pages read from the file so far, for measuring how much of the file a query
+    /// reads. Pages served from the cache aren't counted.
+    pages_loaded: usize,
+    /// The contents of recently read pages, by page number
+    cache: HashMap<usize, Vec<u8>>,
+    /// The cached page numbers from oldest to newest, to evict the oldest when the cache is full
+    cache_order: VecDeque<usize>,
+    cache_size: usize,
+    text_encoding: TextEncoding,
+    limits: QueryLimits,
+    /// The number of rows the current query has read from tables
+    rows_scanned: usize,
+    /// The number of whole pages in the file, measured when it was opened
+    page_count: usize,
 
+    pub header: DBHeader,
+    pub first_page: BTreePage,
+    /// The leaf pages of the schema table when the first page is an interior page, otherwise
+    /// empty as the schema rows are all on the first page
+    schema_pages: Vec<BTreePage>,
+}
+
+impl<'a> DBFile<'a> {
+    /// Opens a database with the default options.
+    pub fn new(file: impl Read + Seek + 'a) -> Result<Self> {
+        DBFileOptions::default().open(file)
+    }
+
+    /// Opens a database image held in memory.
+    pub fn from_bytes(data: Vec<u8>) -> Result<DBFile<'static>> {
+        DBFile::new(Cursor::new(data))
+    }
+
+    /// Every object in the schema in the order they were defined, including SQLite's internal
+    /// `sqlite_` tables and indexes.
+    pub fn all_schema_objects(&self) -> Result<impl Iterator<Item = SchemaObject>> {
+        let pages = match self.schema_pages.as_slice() {
+            [] => std::slice::from_ref(&self.first_page),
+            pages => pages,
+        };
+        let mut objects = vec![];
+        for page in pages {
+            objects.extend(page.load_schemas().context("load schema objects")?);
+        }
+        Ok(objects.into_iter())
+    }
+
+    /// The objects in the schema that were created by the user, leaving out SQLite's internal
+    /// ones. See `SchemaObject::is_internal`.
     pub fn schema_objects(&self) -> Result<impl Iterator<Item = SchemaObject>> {
+        Ok(self.all_schema_objects()?.filter(|obj| !obj.is_internal()))
+    }
+
+    /// The objects of one type, including internal ones.
+    pub fn objects_of_type(
+        &self,
+        object_type: ObjectType,
+    ) -> Result<impl Iterator<Item = SchemaObject>> {
         Ok(self
-            .first_page
-            .load_schemas()
-            .context("load schema objects")?
-            .into_iter())
+            .all_schema_objects()?
+            .filter(move |obj| obj.object_type == object_type))
     }
 
     pub fn table_objects(&self) -> Result<impl Iterator<Item = SchemaObject>> {
-        Ok(self.schema_objects()?.filter(|obj| {
-            obj.object_type == ObjectType::Table && !obj.table_name.starts_with(SQLITE_TABLE_PREFIX)
-        }))
+        Ok(self
+            .objects_of_type(ObjectType::Table)?
+            .filter(|obj| !obj.is_internal()))
+    }
+
+    /// The names of the user tables, leaving out SQLite's internal `sqlite_` tables.
+    pub fn table_names(&self) -> Result<Vec<String>> {
+        Ok(self.table_objects()?.map(|obj| obj.table_name).collect())
     }
 
     pub fn index_objects(&self) -> Result<impl Iterator<Item = SchemaObject>> {
+        self.objects_of_type(ObjectType::Index)
+    }
+
+    /// Each index on a table along with the names of the columns it indexes, in schema order.
+    /// Expressions in an index are given as written. The columns of the indexes SQLite creates
+    /// for `UNIQUE` and `PRIMARY KEY` constraints come from the constraints in the table's SQL.
+    pub fn table_indexes(&mut self, table_name: &str) -> Result<Vec<(String, Vec<String>)>> {
+        Ok(self
+            .index_definitions(table_name)?
+            .into_iter()
+            .map(|(_, index)| {
+                let columns = index.column_names().into_iter().map(String::from).collect();
+                (index.name, columns)
+            })
+            .collect())
+    }
+
+    /// Each index on a table along with its definition, in schema order. The indexes SQLite
+    /// creates for `UNIQUE` and `PRIMARY KEY` constraints have no SQL, so their definitions are
+    /// made up from the matching constraint of the table.
+    fn index_definitions(
+        &mut self,
+        table_name: &str,
+    ) -> Result<Vec<(SchemaObject, CreateIndexStatement)>> {
+        let table = self.schema_for_table(table_name)?;
+        let Statement::CreateTable(create_table) =
+            parse_statement(&table.sql).context("parsing create table statement")?
+        else {
+            bail!("invalid create statement for table '{}'", table_name);
+        };
+        let autoindex_prefix = format!("{}{}_", AUTOINDEX_PREFIX, table.table_name);
+
+        let mut indexes = vec![];
+        for index in self.index_objects()? {
+            if !index.table_name.eq_ignore_ascii_case(&table.table_name) {
+                continue;
+            }
+            let definition = match index.name.strip_prefix(&autoindex_prefix) {
+                Some(number) if index.sql.is_empty() => {
+                    let key = number
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|number| create_table.unique_keys.get(number.checked_sub(1)?))
+                        .with_context(|| {
+                            format!("no constraint on '{}' matches {}", table_name, index.name)
+                        })?;
+                    CreateIndexStatement {
+                        name: index.name.clone(),
+                        table_name: index.table_name.clone(),
+                        columns: key
+                            .iter()
+                            .map(|name| IndexColumn {
+                                name: name.clone(),
+                                expr: None,
+                                descending: false,
+                                collation: None,
+                            })
+                            .collect(),
+                        where_clause: None,
+                    }
+                }
+                _ => match parse_statement(&index.sql)
+                    .with_context(|| format!("parsing SQL of index {}", index.name))?
+                {
+                    Statement::CreateIndex(create_index) => create_index,
+                    _ => bail!("invalid create statement for index {}", index.name),
+                },
+            };
+            indexes.push((index, definition));
+        }
+        Ok(indexes)
+    }
+
+    /// The SQL that created every object in the schema, in the order the objects were defined,
+    /// with each statement ending in a semicolon and a newline. SQLite's internal `sqlite_`
+    /// objects are only included with `include_internal`, and objects without SQL never are.
+    pub fn schema_sql(&self, include_internal: bool) -> Result<String> {
         Ok(self
-            .schema_objects()?
-            .filter(|obj| obj.object_type == ObjectType::Index))
+            .all_schema_objects()?
+            .filter(|obj| include_internal || !obj.is_internal())
+            .filter(|obj| !obj.sql.is_empty())
+            .map(|obj| format!("{};\n", obj.sql))
+            .collect())
     }
 
+    /// Finds a table by name. Unlike `table_objects`, this includes SQLite's internal tables so
+    /// they can still be queried when named explicitly. The schema table itself isn't listed in
+    /// the schema, so it is described here with its fixed layout, rooted at the first page.
     pub fn schema_for_table(&mut self, table_name: &str) -> Result<SchemaObject> {
-        self.table_objects()?
-            .find(|t| t.table_name == table_name)
+        if SCHEMA_TABLE_NAMES
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(table_name))
+        {
+            return Ok(SchemaObject {
+                object_type: ObjectType::Table,
+                name: table_name.to_string(),
+                table_name: table_name.to_string(),
+                root_page: Some(1),
+                sql: String::from(SCHEMA_TABLE_SQL),
+            });
+        }
+
+        self.objects_of_type(ObjectType::Table)?
+            .find(|t| t.table_name.eq_ignore_ascii_case(table_name))
             .ok_or(anyhow!("table not found"))
     }
 
-    pub fn get_index_page(&mut self, table_name: &str, column_name: &str) -> Result<Option<usize>> {
-        let schema_obj = self
-            .index_objects()?
-            .filter(|s| s.table_name == table_name)
-            .find(|s| {
-                let statement = sql_statement(&s.sql).expect("parsing index SQL statement");
-                if let Statement::CreateIndex(create_index) = statement {
-                    return create_index
-                        .columns
-                        .into_iter()
-                        .any(|c| c.as_str() == column_name);
-                } else {
-                    false
-                }
-            });
+    /// Finds an index on `table_name` whose first column is `column_name`, returning its root page
+    /// and the definition of that column.
+    pub fn get_index_page(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+    ) -> Result<Option<(usize, IndexColumn)>> {
+        Ok(self
+            .find_index(table_name, &[(column_name, Collation::Binary)])?
+            .map(|lookup| (lookup.root_page, lookup.columns[0].clone())))
+    }
 
-        Ok(match schema_obj {
-            None => None,
-            Some(o) => o.root_page,
-        })
+    /// Finds the index that can look up rows by the most of `columns`, which are compared with
+    /// the given collations. That's the index with the longest run of leading columns that are all
+    /// in `columns`, each kept in the collation it's compared with: the index column's own
+    /// `COLLATE`, or else the table column's. The first such index in the schema wins a tie. Only
+    /// the leading columns it can be searched by are returned.
+    pub fn find_index(
+        &mut self,
+        table_name: &str,
+        columns: &[(&str, Collation)],
+    ) -> Result<Option<IndexLookup>> {
+        let table_columns = self.schema_for_table(table_name)?.columns()?;
+        let mut best: Option<IndexLookup> = None;
+        for (index, create_index) in self.index_definitions(table_name)? {
+            // TODO: A partial index could still be used when the query's condition implies the
+            // index's, but for now only full indexes on plain columns are considered
+            if create_index.is_partial_or_expression() {
+                continue;
+            }
+            let Some(root_page) = index.root_page else {
+                continue;
+            };
+            let (index_columns, collations): (Vec<_>, Vec<_>) = create_index
+                .columns
+                .into_iter()
+                .map_while(|column| {
+                    let collation = column.collation.clone().or_else(|| {
+                        table_columns
+                            .iter()
+                            .find(|c| c.name.eq_ignore_ascii_case(&column.name))
+                            .and_then(|c| c.collation.clone())
+                    });
+                    let collation = match collation {
+                        Some(name) => Collation::from_name(&name).ok()?,
+                        None => Collation::Binary,
+                    };
+                    columns
+                        .iter()
+                        .any(|&(name, compared)| {
+                            name.eq_ignore_ascii_case(&column.name) && compared == collation
+                        })
+                        .then_some((column, collation))
+                })
+                .unzip();
+            if !index_columns.is_empty()
+                && best
+                    .as_ref()
+                    .is_none_or(|best| index_columns.len() > best.columns.len())
+            {
+                best = Some(IndexLookup {
+                    name: index.name,
+                    root_page,
+                    columns: index_columns,
+                    collations,
+                });
+            }
+        }
+        Ok(best)
     }
 
+    /// The number of pages recorded in the database header, if it's valid.
+    pub fn header_page_count(&self) -> Option<usize> {
+        self.header.db_size().map(|size| size as usize)
+    }
+
+    /// The number of pages in the database, based on the size of the file when it was opened.
+    pub fn page_count(&self) -> usize {
+        self.page_count
+    }
+
+    /// Seeks to the start of a page after checking it exists in the database, so a corrupt page
+    /// number is reported rather than read past the end of the file. Page numbers start at 1.
     fn seek_to_page(&mut self, page: usize) -> Result<u64> {
+        if page == 0 || page > self.page_count {
+            bail!(
+                "page {} is out of range, the database has {} pages",
+                page,
+                self.page_count
+            );
+        }
         let page_offset = page - 1; // pages are 1-indexed
         self.file
             .seek(SeekFrom::Start(
@@ -93,17 +524,91 @@ impl<'a> DBFile<'a> {
             .context("seeking to root page offset")
     }
 
+    /// The number of pages read from the file since it was opened.
+    pub fn pages_loaded(&self) -> usize {
+        self.pages_loaded
+    }
+
     pub fn load_page_at(&mut self, page: usize) -> Result<BTreePage> {
-        // Seek to page start
-        self.seek_to_page(page)?;
+        let buf = match self.cache.get(&page) {
+            Some(buf) => buf.clone(),
+            None => {
+                if let Some(max_pages) = self.limits.max_pages {
+                    if self.pages_loaded - self.limits.first_page_loaded >= max_pages {
+                        bail!("query exceeded the limit of {} pages read", max_pages);
+                    }
+                }
 
-        // Load page
-        let mut buf = vec![0u8; self.header.page_size() as usize];
-        self.file
-            .read_exact(&mut buf)
-            .context("reading page into buffer")?;
+                // Seek to page start
+                self.seek_to_page(page)?;
+                self.pages_loaded += 1;
+
+                // Load page
+                let mut buf = vec![0u8; self.header.page_size() as usize];
+                self.file
+                    .read_exact(&mut buf)
+                    .context("reading page into buffer")?;
+                self.cache_page(page, &buf);
+                buf
+            }
+        };
+
+        // The first page has the database header before its b-tree page header
+        let header = (page == 1).then_some(self.header);
+        Ok(BTreePage::new(&buf, header)?.with_text_encoding(self.text_encoding))
+    }
+
+    fn cache_page(&mut self, page: usize, buf: &[u8]) {
+        if self.cache_size == 0 {
+            return;
+        }
+        if self.cache.len() >= self.cache_size {
+            if let Some(oldest) = self.cache_order.pop_front() {
+                self.cache.remove(&oldest);
+            }
+        }
+        self.cache.insert(page, buf.to_vec());
+        self.cache_order.push_back(page);
+    }
+
+    /// Starts counting pages read towards `DBFileOptions::max_pages` from zero.
+    fn start_query(&mut self) {
+        self.limits.first_page_loaded = self.pages_loaded;
+        self.rows_scanned = 0;
+    }
+
+    /// Records that the current query has read `rows` more rows from a table, failing once it has
+    /// read more than `DBFileOptions::max_rows`.
+    pub(crate) fn count_rows_scanned(&mut self, rows: usize) -> Result<()> {
+        self.rows_scanned += rows;
+        self.limits.check_rows(self.rows_scanned)
+    }
+
+    /// The caps on the current query, for checking them where the `DBFile` is borrowed, such as
+    /// while visiting leaf pages.
+    pub(crate) fn limits(&self) -> QueryLimits {
+        self.limits
+    }
+
+    /// The number of rows the current query has read from tables so far.
+    pub(crate) fn rows_scanned(&self) -> usize {
+        self.rows_scanned
+    }
 
-        BTreePage::new(&buf, None)
+    /// Decodes the record in a single cell of a leaf page, given the cell's position in the page's
+    /// cell pointer array. The value of a rowid alias column is left as the NULL that's stored.
+    pub fn read_cell_at(&mut self, page: usize, cell_index: usize) -> Result<Vec<SerialValue>> {
+        let btree_page = self.load_page_at(page)?;
+        if let PageType::InteriorIndex | PageType::InteriorTable = btree_page.page_type {
+            bail!(
+                "page {} is an {} page, which has no records to read",
+                page,
+                btree_page.page_type
+            );
+        }
+        btree_page
+            .read_cell_at(cell_index, None)
+            .with_context(|| format!("reading cell {} of page {}", cell_index, page))
     }
 
     pub fn load_table(&mut self, table_name: &str) -> Result<(SchemaObject, BTreePage)> {
@@ -117,4 +622,1104 @@ impl<'a> DBFile<'a> {
 
         Ok((schema, page))
     }
+
+    /// Counts the rows in a table by summing the number of cells on each of its leaf pages, so no
+    /// records need to be decoded.
+    pub fn row_count(&mut self, table_name: &str) -> Result<u64> {
+        let root_page = self
+            .schema_for_table(table_name)?
+            .root_page
+            .context("getting root page offset")?;
+        let mut count = 0;
+        self.for_each_leaf_page(root_page, |page| {
+            count += page.num_cells as u64;
+            Ok(())
+        })
+        .with_context(|| format!("counting rows in table '{}'", table_name))?;
+        Ok(count)
+    }
+
+    /// Calls `f` with each row of a table in rowid order. Only one page of rows is decoded at a
+    /// time, so this works for tables too large to collect. An error returned by `f` stops the
+    /// scan and is passed back to the caller.
+    pub fn for_each_row(
+        &mut self,
+        table_name: &str,
+        mut f: impl FnMut(&[SerialValue]) -> Result<()>,
+    ) -> Result<()> {
+        let schema = self.schema_for_table(table_name)?;
+        let columns = schema.columns().context("retrieving table columns")?;
+        let root_page = schema.root_page.context("getting root page offset")?;
+        let alias_column = rowid_column(&columns);
+        self.start_query();
+        let limits = self.limits;
+        let mut rows = 0;
+        self.for_each_leaf_page(root_page, |page| {
+            for mut row in page
+                .read_cells(alias_column)
+                .context("reading cells from leaf page")?
+            {
+                rows += 1;
+                limits.check_rows(rows)?;
+                pad_row(&mut row, &columns);
+                f(&row)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Looks up a single row of a table by its rowid, only reading the pages on the path to it.
+    pub fn row_by_rowid(
+        &mut self,
+        table_name: &str,
+        rowid: u64,
+    ) -> Result<Option<Vec<SerialValue>>> {
+        let schema = self.schema_for_table(table_name)?;
+        let columns = schema.columns().context("retrieving table columns")?;
+        let root_page = schema.root_page.context("getting root page offset")?;
+        let key = rowid as i64;
+        let mut row = None;
+        self.for_each_leaf_page_in_range(root_page, key..=key, |page| {
+            if row.is_none() {
+                row = page.find_row(rowid, rowid_column(&columns))?;
+            }
+            Ok(())
+        })
+        .with_context(|| format!("looking up rowid {} in table '{}'", rowid, table_name))?;
+        Ok(row.map(|mut row| {
+            pad_row(&mut row, &columns);
+            row
+        }))
+    }
+
+    /// Calls `f` with every row of every user table, along with the name of the table it's from.
+    /// Tables are visited in schema order and each table's rows in rowid order.
+    pub fn all_tables_rows(
+        &mut self,
+        mut f: impl FnMut(&str, &[SerialValue]) -> Result<()>,
+    ) -> Result<()> {
+        for table_name in self.table_names()? {
+            self.for_each_row(&table_name, |row| f(&table_name, row))
+                .with_context(|| format!("reading rows of table {}", table_name))?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether a table has any rows by descending to its leftmost leaf page, which only
+    /// has no cells when the whole table is empty.
+    pub fn table_is_empty(&mut self, table_name: &str) -> Result<bool> {
+        let (schema, mut page) = self.load_table(table_name)?;
+        let mut visited = HashSet::from([schema.root_page.unwrap_or_default()]);
+        loop {
+            match page.page_type {
+                PageType::LeafTable => return Ok(page.num_cells == 0),
+                PageType::InteriorTable => {
+                    let child = match page.read_interior_cells()?.first() {
+                        Some(InteriorCell::Table(cell)) => cell.left_child_page,
+                        Some(_) => bail!("invalid cell type - expected interior table cell"),
+                        None => page
+                            .right_most_pointer
+                            .context("interior page has no children")?,
+                    };
+                    mark_visited(&mut visited, child as usize)?;
+                    page = self.load_page_at(child as usize)?;
+                }
+                _ => bail!("unexpected page type in table b-tree"),
+            }
+        }
+    }
+
+    /// Calls `f` with each leaf page of the table b-tree rooted at `root_page`, from left to
+    /// right. Pages still to be visited are kept on an explicit stack rather than recursing, so a
+    /// very deep tree can't overflow the call stack.
+    pub fn for_each_leaf_page(
+        &mut self,
+        root_page: usize,
+        f: impl FnMut(BTreePage) -> Result<()>,
+    ) -> Result<()> {
+        self.for_each_leaf_page_in_range(root_page, i64::MIN..=i64::MAX, f)
+    }
+
+    /// Like `for_each_leaf_page`, but skips any subtree that can't contain a rowid in `rowids`.
+    /// The leaf pages passed to `f` may still hold rowids outside of the range.
+    pub fn for_each_leaf_page_in_range(
+        &mut self,
+        root_page: usize,
+        rowids: RangeInclusive<i64>,
+        mut f: impl FnMut(BTreePage) -> Result<()>,
+    ) -> Result<()> {
+        let mut pending = vec![root_page];
+        let mut visited = HashSet::new();
+        while let Some(page_number) = pending.pop() {
+            mark_visited(&mut visited, page_number)?;
+            let page = self.load_page_at(page_number).context("loading page")?;
+            match page.page_type {
+                PageType::LeafTable => f(page)?,
+                PageType::InteriorTable => {
+                    // Each child holds the rowids above the previous cell's key, up to and
+                    // including its own key. The right-most child holds everything above the
+                    // last key.
+                    let mut children = vec![];
+                    let mut previous_key = None;
+                    for interior_cell in page
+                        .read_interior_cells()
+                        .context("reading interior cells")?
+                    {
+                        let InteriorCell::Table(cell) = interior_cell else {
+                            bail!("invalid cell type - expected interior table cell")
+                        };
+                        let key = cell.row_id as i64;
+                        if previous_key.is_some_and(|previous| previous >= *rowids.end()) {
+                            break;
+                        }
+                        if key >= *rowids.start() {
+                            children.push(cell.left_child_page);
+                        }
+                        previous_key = Some(key);
+                    }
+                    if let Some(right_ptr) = page.right_most_pointer {
+                        if previous_key.is_none_or(|previous| previous < *rowids.end()) {
+                            children.push(right_ptr);
+                        }
+                    }
+
+                    // Children are pushed right to left so that they're visited left to right
+                    pending.extend(children.into_iter().rev().map(|page| page as usize));
+                }
+                _ => bail!("unexpected page type in table b-tree"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses and runs a single SQL statement. Only SELECT statements are supported: the database
+    /// is always read-only, so any statement that would change it is rejected.
+    pub fn query(&mut self, sql: &str) -> Result<QueryResult> {
+        let mut rows = vec![];
+        let columns = self.query_each(sql, |_, row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        Ok(QueryResult { columns, rows })
+    }
+
+    /// Runs a single SQL statement like `query`, but passes each result row to `f` as soon as it's
+    /// produced instead of collecting them. `f` is also given the description of the result's
+    /// columns, which are returned too. An error returned by `f` stops the query.
+    pub fn query_each(
+        &mut self,
+        sql: &str,
+        mut f: impl FnMut(&[ColumnMeta], Row) -> Result<()>,
+    ) -> Result<Vec<ColumnMeta>> {
+        match parse_statement(sql)? {
+            Statement::Select(select_statement) => {
+                self.start_query();
+                for_each_result_row(self, &select_statement, f)
+            }
+            Statement::CreateTable(_)
+            | Statement::CreateIndex(_)
+            | Statement::CreateView(_)
+            | Statement::Drop(_)
+            | Statement::Insert(_)
+            | Statement::Update(_)
+            | Statement::Delete(_) => bail!("attempt to write a readonly database"),
+            Statement::ExplainQueryPlan(select_statement) => {
+                let plan = explain_select(self, &select_statement)?;
+                let columns = vec![ColumnMeta {
+                    name: String::from("detail"),
+                    declared_type: None,
+                }];
+                for detail in plan.details() {
+                    f(&columns, vec![SerialValue::Text(detail)])?;
+                }
+                Ok(columns)
+            }
+        }
+    }
+
+    /// Runs a single SQL statement like `query`, and also reports how much work it took.
+    pub fn execute_with_stats(&mut self, sql: &str) -> Result<(QueryResult, QueryStats)> {
+        let start = Instant::now();
+        let pages_loaded = self.pages_loaded;
+        let result = self.query(sql)?;
+        let stats = QueryStats {
+            pages_read: self.pages_loaded - pages_loaded,
+            rows_scanned: self.rows_scanned,
+            elapsed: start.elapsed(),
+        };
+        Ok((result, stats))
+    }
+
+    /// Parses a SELECT statement, optionally written with `EXPLAIN QUERY PLAN`, and describes how
+    /// it would be run, without running it.
+    pub fn explain_query_plan(&mut self, sql: &str) -> Result<QueryPlan> {
+        match parse_statement(sql)? {
+            Statement::Select(select_statement) | Statement::ExplainQueryPlan(select_statement) => {
+                explain_select(self, &select_statement)
+            }
+            _ => bail!("only SELECT statements have a query plan"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::query::AccessPath;
+    use crate::sql::{BinaryOperator, Expr};
+
+    #[test]
+    fn table_names() {
+        let mut file = File::open("sample.db").unwrap();
+        let db_file = DBFile::new(&mut file).unwrap();
+        assert!(db_file
+            .all_schema_objects()
+            .unwrap()
+            .any(|obj| obj.table_name == "sqlite_sequence"));
+        assert_eq!(db_file.table_names().unwrap(), ["apples", "oranges"]);
+    }
+
+    #[test]
+    fn internal_objects() {
+        let db_file = DBFile::new(File::open("fixtures/unique.db").unwrap()).unwrap();
+        assert_eq!(
+            db_file
+                .all_schema_objects()
+                .unwrap()
+                .map(|obj| obj.name)
+                .collect::<Vec<_>>(),
+            [
+                "users",
+                "sqlite_autoindex_users_1",
+                "sqlite_autoindex_users_2",
+                "codes",
+                "sqlite_autoindex_codes_1",
+                "idx_users_name"
+            ]
+        );
+        assert_eq!(
+            db_file
+                .schema_objects()
+                .unwrap()
+                .map(|obj| obj.name)
+                .collect::<Vec<_>>(),
+            ["users", "codes", "idx_users_name"]
+        );
+
+        let db_file = DBFile::new(File::open("sample.db").unwrap()).unwrap();
+        assert!(db_file
+            .schema_objects()
+            .unwrap()
+            .all(|obj| obj.name != "sqlite_sequence"));
+        assert_eq!(db_file.all_schema_objects().unwrap().count(), 3);
+    }
+
+    #[test]
+    fn objects_of_type() {
+        let mut file = File::open("fixtures/schema.db").unwrap();
+        let db_file = DBFile::new(&mut file).unwrap();
+        let names = |object_type| {
+            db_file
+                .objects_of_type(object_type)
+                .unwrap()
+                .map(|obj| obj.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(ObjectType::Table), ["people"]);
+        assert_eq!(names(ObjectType::Index), ["idx_people_name"]);
+        assert_eq!(names(ObjectType::View), ["adults"]);
+        assert_eq!(names(ObjectType::Trigger), ["people_insert"]);
+    }
+
+    #[test]
+    fn row_count() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for table in ["apples", "oranges"] {
+            let scanned = db_file
+                .query(&format!("SELECT id FROM {}", table))
+                .unwrap()
+                .rows
+                .len();
+            assert_eq!(db_file.row_count(table).unwrap(), scanned as u64);
+        }
+    }
+
+    #[test]
+    fn for_each_row() {
+        let mut file = File::open("fixtures/tables.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut sum = 0;
+        db_file
+            .for_each_row("numbers", |row| {
+                sum += row[0].as_i64().unwrap();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(sum, (1..=500).sum::<i64>());
+
+        let mut seen = 0;
+        let err = db_file
+            .for_each_row("numbers", |_| {
+                seen += 1;
+                if seen == 3 {
+                    bail!("stop");
+                }
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "stop");
+        assert_eq!(seen, 3);
+    }
+
+    #[test]
+    fn all_tables_rows() {
+        let mut file = File::open("fixtures/tables.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut counts = HashMap::new();
+        db_file
+            .all_tables_rows(|table_name, _| {
+                *counts.entry(table_name.to_string()).or_insert(0) += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(counts, HashMap::from([(String::from("numbers"), 500)]));
+
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut total = 0;
+        db_file
+            .all_tables_rows(|_, _| {
+                total += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(total, 4 + 6);
+    }
+
+    #[test]
+    fn table_is_empty() {
+        let mut file = File::open("fixtures/tables.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert!(db_file.table_is_empty("empty").unwrap());
+        assert!(!db_file.table_is_empty("numbers").unwrap());
+        assert_eq!(db_file.row_count("numbers").unwrap(), 500);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let data = std::fs::read("sample.db").unwrap();
+        let mut db_file = DBFile::from_bytes(data).unwrap();
+        let result = db_file
+            .query("SELECT name FROM apples WHERE color = 'Yellow'")
+            .unwrap();
+        assert_eq!(
+            result.rows,
+            [vec![SerialValue::Text(String::from("Golden Delicious"))]]
+        );
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut data = std::fs::read("fixtures/tables.db").unwrap();
+        let pages = DBFile::from_bytes(data.clone())
+            .unwrap()
+            .header_page_count()
+            .unwrap();
+        data[28..32].copy_from_slice(&(pages as u32 + 10).to_be_bytes());
+        let Err(err) = DBFile::from_bytes(data) else {
+            panic!("expected a truncated file to be rejected")
+        };
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "database file is truncated: the header records {} pages but the file has {}",
+                pages + 10,
+                pages
+            )
+        );
+    }
+
+    #[test]
+    fn schema_sql() {
+        let mut file = File::open("fixtures/schema.db").unwrap();
+        let db_file = DBFile::new(&mut file).unwrap();
+        let schema = db_file.schema_sql(false).unwrap();
+        for sql in [
+            "CREATE TABLE people (id integer primary key, name text, age integer);\n",
+            "CREATE INDEX idx_people_name ON people (name);\n",
+            "CREATE VIEW adults AS SELECT name FROM people WHERE age >= 18;\n",
+            "CREATE TRIGGER people_insert AFTER INSERT ON people BEGIN SELECT 1; END;\n",
+        ] {
+            assert!(schema.contains(sql), "{}", sql);
+        }
+        assert_eq!(schema.lines().count(), 4);
+
+        let mut file = File::open("sample.db").unwrap();
+        let db_file = DBFile::new(&mut file).unwrap();
+        assert!(!db_file
+            .schema_sql(false)
+            .unwrap()
+            .contains("sqlite_sequence"));
+        assert!(db_file
+            .schema_sql(true)
+            .unwrap()
+            .contains("CREATE TABLE sqlite_sequence(name,seq);\n"));
+    }
+
+    #[test]
+    fn interior_schema_root() {
+        let mut file = File::open("fixtures/many_tables.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert!(matches!(
+            db_file.first_page.page_type,
+            PageType::InteriorTable
+        ));
+        let names = db_file.table_names().unwrap();
+        assert_eq!(names.len(), 40);
+        assert_eq!(names[0], "table_1");
+        assert_eq!(names[39], "table_40");
+        let result = db_file.query("SELECT value FROM table_40").unwrap();
+        assert_eq!(result.rows, [[SerialValue::Text(String::from("last"))]]);
+    }
+
+    #[test]
+    fn read_only() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for sql in [
+            "INSERT INTO apples (name, color) VALUES ('Fuji', 'Red')",
+            "UPDATE apples SET color = 'Green' WHERE id = 1",
+            "DELETE FROM apples",
+            "CREATE TABLE pears (id integer primary key, name text)",
+            "DROP TABLE apples",
+        ] {
+            assert_eq!(
+                db_file.query(sql).unwrap_err().to_string(),
+                "attempt to write a readonly database",
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(
+            db_file
+                .query("SELECT count(*) FROM apples")
+                .unwrap()
+                .rows
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn explain_query_plan() {
+        let mut db_file = DBFile::new(File::open("fixtures/shop.db").unwrap()).unwrap();
+        let pages_loaded = db_file.pages_loaded();
+        let plan = db_file
+            .explain_query_plan("SELECT id FROM customers WHERE name = 'Ada' AND city LIKE 'L%'")
+            .unwrap();
+        assert_eq!(db_file.pages_loaded(), pages_loaded, "nothing is run");
+        assert_eq!(plan.table.as_deref(), Some("customers"));
+        let AccessPath::Index { index, key } = &plan.access else {
+            panic!("expected an index lookup, got {:?}", plan.access);
+        };
+        assert_eq!(index.name, "idx_customers_name");
+        assert_eq!(key, &[SerialValue::Text(String::from("Ada"))]);
+        assert_eq!(plan.residual_filters.len(), 1);
+        assert!(matches!(
+            &plan.residual_filters[0],
+            Expr::Binary {
+                op: BinaryOperator::Like,
+                ..
+            }
+        ));
+
+        for (sql, access) in [
+            ("SELECT name FROM customers", AccessPath::FullScan),
+            ("SELECT COUNT(*) FROM orders", AccessPath::RowCount),
+            (
+                "SELECT id FROM customers WHERE id > 1",
+                AccessPath::RowidRange(2..=i64::MAX),
+            ),
+            ("SELECT 1 + 1", AccessPath::NoTable),
+        ] {
+            let plan = db_file.explain_query_plan(sql).unwrap();
+            assert_eq!(plan.access, access, "{}", sql);
+            assert!(plan.residual_filters.is_empty(), "{}", sql);
+        }
+
+        let plan = db_file
+            .explain_query_plan(
+                "SELECT * FROM orders JOIN customers ON customers.id = orders.customer_id \
+                 WHERE total > 10",
+            )
+            .unwrap();
+        assert_eq!(plan.access, AccessPath::FullScan);
+        assert_eq!(plan.joins, ["customers"]);
+        assert_eq!(plan.residual_filters.len(), 1);
+
+        assert_eq!(
+            db_file
+                .explain_query_plan("SELECT nope FROM customers")
+                .unwrap_err()
+                .to_string(),
+            "no such column: nope (valid columns are: id, name, city)"
+        );
+        assert!(db_file.explain_query_plan("DELETE FROM customers").is_err());
+    }
+
+    #[test]
+    fn composite_index_preferred() {
+        let mut db_file = DBFile::new(File::open("fixtures/composite.db").unwrap()).unwrap();
+        let index_name = |db_file: &mut DBFile, sql: &str| {
+            let plan = db_file.explain_query_plan(sql).unwrap();
+            match plan.access {
+                AccessPath::Index { index, .. } => index.name,
+                access => panic!("expected an index lookup for {}, got {:?}", sql, access),
+            }
+        };
+        assert_eq!(
+            index_name(
+                &mut db_file,
+                "SELECT id FROM events WHERE kind = 'kind3' AND day = 5"
+            ),
+            "idx_events_kind_day"
+        );
+        // With a single column compared the indexes are as good as each other and the first wins
+        assert_eq!(
+            index_name(&mut db_file, "SELECT id FROM events WHERE kind = 'kind3'"),
+            "idx_events_kind"
+        );
+
+        for (sql, expected) in [
+            (
+                "EXPLAIN QUERY PLAN SELECT id FROM events WHERE day = 5 AND kind = 'kind3'",
+                "SEARCH events USING INDEX idx_events_kind_day (kind=? AND day=?)",
+            ),
+            (
+                "EXPLAIN QUERY PLAN SELECT id FROM events WHERE kind = 'kind3'",
+                "SEARCH events USING INDEX idx_events_kind (kind=?)",
+            ),
+            (
+                "EXPLAIN QUERY PLAN SELECT id FROM events WHERE id BETWEEN 5 AND 10",
+                "SEARCH events USING INTEGER PRIMARY KEY (rowid>? AND rowid<?)",
+            ),
+            (
+                "EXPLAIN QUERY PLAN SELECT id FROM events WHERE day = 5",
+                "SCAN events",
+            ),
+        ] {
+            let result = db_file.query(sql).unwrap();
+            assert_eq!(result.columns[0].name, "detail");
+            assert_eq!(
+                result.rows,
+                [[SerialValue::Text(String::from(expected))]],
+                "{}",
+                sql
+            );
+        }
+        let rows = db_file
+            .query("SELECT id FROM events WHERE kind = 'kind3' AND day = 5")
+            .unwrap()
+            .rows;
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[test]
+    fn execute_with_stats() {
+        let mut db_file = DBFileOptions::new()
+            .cache_size(0)
+            .open(File::open("fixtures/composite.db").unwrap())
+            .unwrap();
+        let (result, full_scan) = db_file
+            .execute_with_stats("SELECT id FROM events WHERE note = 'note 153'")
+            .unwrap();
+        assert_eq!(result.rows, [[SerialValue::Int64(153)]]);
+        assert_eq!(full_scan.rows_scanned, 1000);
+
+        let (result, indexed) = db_file
+            .execute_with_stats("SELECT id FROM events WHERE kind = 'kind3' AND day = 5")
+            .unwrap();
+        assert_eq!(result.rows.len(), 3);
+        assert_eq!(indexed.rows_scanned, 3);
+        assert!(indexed.pages_read < full_scan.pages_read);
+
+        let (_, by_rowid) = db_file
+            .execute_with_stats("SELECT id FROM events WHERE id = 153")
+            .unwrap();
+        // Only the leaf page that holds the rowid is read
+        assert!(by_rowid.rows_scanned < 100, "{:?}", by_rowid);
+    }
+
+    #[test]
+    fn indexed_count() {
+        let mut db_file = DBFileOptions::new()
+            .cache_size(0)
+            .open(File::open("fixtures/composite.db").unwrap())
+            .unwrap();
+        let sql = "SELECT count(*) FROM events WHERE kind = 'kind3'";
+        assert_eq!(
+            db_file
+                .query(&format!("EXPLAIN QUERY PLAN {}", sql))
+                .unwrap()
+                .rows,
+            [[SerialValue::Text(String::from(
+                "SEARCH events USING COVERING INDEX idx_events_kind (kind=?)"
+            ))]]
+        );
+        let (result, counted) = db_file.execute_with_stats(sql).unwrap();
+        assert_eq!(result.rows, [[SerialValue::Int64(100)]]);
+        assert_eq!(counted.rows_scanned, 0, "no table rows are read");
+
+        // Counting the rows with a filter the index doesn't answer reads them from the table
+        let (result, scanned) = db_file
+            .execute_with_stats("SELECT count(*) FROM events WHERE kind || '' = 'kind3'")
+            .unwrap();
+        assert_eq!(result.rows, [[SerialValue::Int64(100)]]);
+        assert_eq!(scanned.rows_scanned, 1000);
+        assert!(
+            counted.pages_read * 4 < scanned.pages_read,
+            "{:?} vs {:?}",
+            counted,
+            scanned
+        );
+
+        // The single count row is subject to LIMIT and OFFSET whichever way it was found
+        for filter in ["WHERE kind = 'kind3'", "WHERE kind || '' = 'kind3'", ""] {
+            for (clause, expected) in [("LIMIT 0", 0), ("LIMIT 1 OFFSET 1", 0), ("LIMIT 1", 1)] {
+                let sql = format!("SELECT count(*) FROM events {} {}", filter, clause);
+                assert_eq!(db_file.query(&sql).unwrap().rows.len(), expected, "{}", sql);
+            }
+        }
+    }
+
+    #[test]
+    fn short_files() {
+        let sample = std::fs::read("sample.db").unwrap();
+        for (data, len) in [
+            (vec![], 0),
+            (vec![0; 50], 50),
+            (sample[..DBHeader::SIZE + 50].to_vec(), 150),
+        ] {
+            let Err(err) = DBFile::from_bytes(data) else {
+                panic!("opened a {} byte file", len);
+            };
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "file is not a valid SQLite database: too short ({} bytes)",
+                    len
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let sample = std::fs::read("sample.db").unwrap();
+        let with = |offset: usize, bytes: &[u8]| {
+            let mut data = sample.clone();
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            data
+        };
+        for (data, message) in [
+            (with(16, &[0, 0]), "invalid page size 0"),
+            (with(16, &[0x03, 0x00]), "invalid page size 768"),
+            (with(16, &[0x01, 0x00]), "invalid page size 256"),
+            (with(0, b"SQLite format 2"), "the header string is missing"),
+            (vec![0; 4096], "the header string is missing"),
+        ] {
+            let Err(err) = DBFile::from_bytes(data) else {
+                panic!("opened a file with an invalid header: {}", message);
+            };
+            assert_eq!(
+                err.to_string(),
+                format!("file is not a valid SQLite database: {}", message)
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_first_page() {
+        let mut data = std::fs::read("sample.db").unwrap();
+        // The cell pointer array follows the b-tree page header, which is longer on interior pages
+        let pointers = DBHeader::SIZE + if data[DBHeader::SIZE] == 0x05 { 12 } else { 8 };
+        data[pointers..pointers + 2].copy_from_slice(&[0xFF, 0xFF]);
+        let Err(err) = DBFile::from_bytes(data) else {
+            panic!("opened a file with a cell pointer past the end of its first page");
+        };
+        assert!(
+            format!("{:#}", err).starts_with("reading first page: cell pointer 0 is out of bounds"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn query_limits() {
+        let open = |options: DBFileOptions| {
+            options
+                .cache_size(0)
+                .open(File::open("fixtures/affinity.db").unwrap())
+                .unwrap()
+        };
+
+        let mut db_file = open(DBFileOptions::new().max_pages(3));
+        let err = db_file.query("SELECT * FROM codes").unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("query exceeded the limit of 3 pages read"),
+            "{:#}",
+            err
+        );
+        // Each query gets the full allowance, and a lookup by rowid stays within it
+        for _ in 0..3 {
+            let rows = db_file
+                .query("SELECT code FROM codes WHERE id = 150")
+                .unwrap()
+                .rows;
+            assert_eq!(rows, [[SerialValue::Text(String::from("150"))]]);
+        }
+
+        let mut db_file = open(DBFileOptions::new().max_rows(10));
+        assert_eq!(
+            db_file
+                .query("SELECT id FROM codes")
+                .unwrap_err()
+                .to_string(),
+            "query exceeded the limit of 10 rows"
+        );
+        // The cap is on the rows read, which LIMIT doesn't reduce, but an index lookup reads few
+        assert!(db_file.query("SELECT id FROM codes LIMIT 10").is_err());
+        assert_eq!(
+            db_file
+                .query("SELECT id FROM codes WHERE code = '150'")
+                .unwrap()
+                .rows,
+            [[SerialValue::Int64(150)]]
+        );
+        assert_eq!(
+            db_file.query("SELECT count(*) FROM codes").unwrap().rows,
+            [[SerialValue::Int64(300)]]
+        );
+        let mut seen = 0;
+        let err = db_file
+            .for_each_row("codes", |_| {
+                seen += 1;
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "query exceeded the limit of 10 rows");
+        assert_eq!(seen, 10);
+
+        // Rows are handed on as they're read, so the ones before the cap was hit have been seen
+        let mut db_file = open(DBFileOptions::new().max_rows(100));
+        let mut ids = vec![];
+        let err = db_file
+            .query_each("SELECT id FROM codes", |columns, row| {
+                assert_eq!(columns[0].name, "id");
+                ids.push(row[0].clone());
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "query exceeded the limit of 100 rows");
+        assert!(!ids.is_empty() && ids.len() < 100);
+        assert_eq!(ids[0], SerialValue::Int64(1));
+
+        // Both tables fit in the cap, but the rows made by joining them don't
+        let mut db_file = open(DBFileOptions::new().max_rows(700));
+        let err = db_file
+            .query("SELECT count(*) FROM codes JOIN codes")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "query exceeded the limit of 700 rows");
+    }
+
+    #[test]
+    fn quoted_table_names() {
+        let mut db_file = DBFile::new(File::open("fixtures/quoted.db").unwrap()).unwrap();
+        for sql in [
+            r#"SELECT "first name", [last-name] FROM "My Table" WHERE id = 2"#,
+            "SELECT `first name`, \"last-name\" FROM [My Table] WHERE id = 2",
+            r#"SELECT "first name", "last-name" FROM "my table" WHERE id = 2"#,
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows,
+                [[
+                    SerialValue::Text(String::from("Alan")),
+                    SerialValue::Text(String::from("Turing"))
+                ]],
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(
+            db_file
+                .query(r#"SELECT COUNT(*) FROM "My Table""#)
+                .unwrap()
+                .rows,
+            [[SerialValue::Int64(2)]]
+        );
+        assert!(db_file.query("SELECT * FROM \"My  Table\"").is_err());
+    }
+
+    #[test]
+    fn read_cell_at() {
+        let mut db_file = DBFile::new(File::open("sample.db").unwrap()).unwrap();
+        assert_eq!(
+            db_file.read_cell_at(2, 0).unwrap(),
+            [
+                SerialValue::Null,
+                SerialValue::Text(String::from("Granny Smith")),
+                SerialValue::Text(String::from("Light Green")),
+            ]
+        );
+        let last = db_file.read_cell_at(2, 3).unwrap();
+        assert_eq!(last[1], SerialValue::Text(String::from("Golden Delicious")));
+        assert_eq!(
+            format!("{:#}", db_file.read_cell_at(2, 4).unwrap_err()),
+            "reading cell 4 of page 2: cell 4 is out of range: the page has 4 cells"
+        );
+
+        let mut db_file = DBFile::new(File::open("fixtures/composite.db").unwrap()).unwrap();
+        let root_page = db_file
+            .schema_for_table("events")
+            .unwrap()
+            .root_page
+            .unwrap();
+        assert_eq!(
+            db_file.read_cell_at(root_page, 0).unwrap_err().to_string(),
+            format!(
+                "page {} is an interior table page, which has no records to read",
+                root_page
+            )
+        );
+    }
+
+    #[test]
+    fn row_by_rowid() {
+        let mut file = File::open("fixtures/affinity.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let pages_loaded = db_file.pages_loaded();
+        assert_eq!(
+            db_file.row_by_rowid("codes", 250).unwrap(),
+            Some(vec![
+                SerialValue::Int64(250),
+                SerialValue::Text(String::from("250")),
+                SerialValue::Int16(500),
+            ])
+        );
+        // Only the path from the root to one leaf is read
+        assert!(db_file.pages_loaded() - pages_loaded <= 3);
+        assert_eq!(db_file.row_by_rowid("codes", 0).unwrap(), None);
+        assert_eq!(db_file.row_by_rowid("codes", 301).unwrap(), None);
+
+        // Rows written before a column was added get its default
+        let mut file = File::open("fixtures/altered.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert_eq!(
+            db_file.row_by_rowid("items", 1).unwrap(),
+            Some(vec![
+                SerialValue::Int64(1),
+                SerialValue::Text(String::from("old")),
+                SerialValue::Null,
+                SerialValue::Int64(-1),
+                SerialValue::Text(String::from("each")),
+                SerialValue::Float64(0.5),
+            ])
+        );
+        assert!(db_file.row_by_rowid("missing", 1).is_err());
+    }
+
+    #[test]
+    fn table_indexes() {
+        let mut file = File::open("fixtures/unique.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let names = |columns: &[&str]| columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            db_file.table_indexes("users").unwrap(),
+            [
+                (String::from("sqlite_autoindex_users_1"), names(&["email"])),
+                (
+                    String::from("sqlite_autoindex_users_2"),
+                    names(&["team", "badge"])
+                ),
+                (String::from("idx_users_name"), names(&["name"])),
+            ]
+        );
+        assert_eq!(
+            db_file.table_indexes("codes").unwrap(),
+            [(String::from("sqlite_autoindex_codes_1"), names(&["code"]))]
+        );
+        assert!(!db_file.schema_sql(true).unwrap().contains("autoindex"));
+
+        let mut file = File::open("fixtures/partial_indexes.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert_eq!(
+            db_file.table_indexes("tags").unwrap(),
+            [
+                (String::from("idx_tags_partial"), names(&["tag"])),
+                (String::from("idx_tags_lower"), names(&["lower(tag)"])),
+            ]
+        );
+        assert!(db_file.table_indexes("missing").is_err());
+    }
+
+    #[test]
+    fn page_out_of_range() {
+        let mut file = File::open("fixtures/tables.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let page_count = db_file.page_count();
+        for page in [0, page_count + 1] {
+            let Err(err) = db_file.load_page_at(page) else {
+                panic!("expected page {} to be rejected", page)
+            };
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "page {} is out of range, the database has {} pages",
+                    page, page_count
+                )
+            );
+        }
+        assert!(db_file.load_page_at(page_count).is_ok());
+    }
+
+    #[test]
+    fn lenient_header() {
+        let mut data = std::fs::read("fixtures/tables.db").unwrap();
+        let pages = u32::from_be_bytes(data[28..32].try_into().unwrap());
+        data[28..32].copy_from_slice(&(pages + 10).to_be_bytes());
+        data[56..60].copy_from_slice(&7u32.to_be_bytes());
+        assert!(DBFile::from_bytes(data.clone()).is_err());
+
+        let mut db_file = DBFileOptions::new()
+            .strict(false)
+            .open(Cursor::new(data))
+            .unwrap();
+        assert_eq!(db_file.row_count("numbers").unwrap(), 500);
+    }
+
+    #[test]
+    fn cache_size() {
+        let file = File::open("fixtures/tables.db").unwrap();
+        let mut db_file = DBFileOptions::new().cache_size(2).open(file).unwrap();
+        for (page, pages_loaded) in [(2, 1), (3, 2), (2, 2), (3, 2), (4, 3), (2, 4), (4, 4)] {
+            db_file.load_page_at(page).unwrap();
+            assert_eq!(db_file.pages_loaded(), pages_loaded, "page {}", page);
+        }
+
+        let mut db_file = DBFileOptions::new()
+            .cache_size(0)
+            .open(File::open("fixtures/tables.db").unwrap())
+            .unwrap();
+        for _ in 0..3 {
+            db_file.load_page_at(2).unwrap();
+        }
+        assert_eq!(db_file.pages_loaded(), 3);
+    }
+
+    #[test]
+    fn utf16_text() {
+        let mut file = File::open("fixtures/utf16.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert_eq!(db_file.table_names().unwrap(), ["greetings"]);
+        let result = db_file
+            .query("SELECT greeting FROM greetings WHERE id > 1")
+            .unwrap();
+        assert_eq!(
+            result.rows,
+            [
+                [SerialValue::Text(String::from("héllo wörld"))],
+                [SerialValue::Text(String::from("こんにちは"))],
+            ]
+        );
+
+        // The override is used even when the header names a different encoding
+        let mut data = std::fs::read("fixtures/tables.db").unwrap();
+        data[56..60].copy_from_slice(&3u32.to_be_bytes());
+        assert!(DBFile::from_bytes(data.clone())
+            .unwrap()
+            .table_names()
+            .is_err());
+        let db_file = DBFileOptions::new()
+            .text_encoding(TextEncoding::Utf8)
+            .open(Cursor::new(data))
+            .unwrap();
+        assert_eq!(db_file.table_names().unwrap(), ["empty", "numbers"]);
+    }
+
+    /// An interior table page with no cells, whose only child is its right-most pointer.
+    fn interior_page(page_size: usize, child: usize) -> Vec<u8> {
+        let mut page = vec![0; page_size];
+        page[0] = 0x05;
+        page[5..7].copy_from_slice(&(page_size as u16).to_be_bytes());
+        page[8..12].copy_from_slice(&(child as u32).to_be_bytes());
+        page
+    }
+
+    #[test]
+    fn deep_tree() {
+        // Replace the root of the empty table with a long chain of interior pages that ends at
+        // the root of the numbers table
+        const DEPTH: usize = 20_000;
+        let mut data = std::fs::read("fixtures/tables.db").unwrap();
+        let db_file = DBFile::from_bytes(data.clone()).unwrap();
+        let page_size = db_file.header.page_size() as usize;
+        let root_page = |name| {
+            db_file
+                .schema_objects()
+                .unwrap()
+                .find(|obj| obj.name == name)
+                .and_then(|obj| obj.root_page)
+                .unwrap()
+        };
+        let (empty_root, numbers_root) = (root_page("empty"), root_page("numbers"));
+
+        let first_new_page = data.len() / page_size + 1;
+        let offset = (empty_root - 1) * page_size;
+        data[offset..offset + page_size].copy_from_slice(&interior_page(page_size, first_new_page));
+        for depth in 0..DEPTH {
+            let child = match depth {
+                d if d == DEPTH - 1 => numbers_root,
+                d => first_new_page + d + 1,
+            };
+            data.extend(interior_page(page_size, child));
+        }
+
+        let mut db_file = DBFile::from_bytes(data).unwrap();
+        assert_eq!(db_file.row_count("empty").unwrap(), 500);
+        let result = db_file.query("SELECT name FROM empty LIMIT 1").unwrap();
+        assert_eq!(
+            result.rows,
+            [vec![SerialValue::Text(String::from("number 1"))]]
+        );
+    }
+
+    #[test]
+    fn page_cycle() {
+        let mut data = std::fs::read("fixtures/tables.db").unwrap();
+        let db_file = DBFile::from_bytes(data.clone()).unwrap();
+        let page_size = db_file.header.page_size() as usize;
+        let root = db_file
+            .schema_objects()
+            .unwrap()
+            .find(|obj| obj.name == "empty")
+            .and_then(|obj| obj.root_page)
+            .unwrap();
+
+        // Make the empty table's root page its own child
+        let offset = (root - 1) * page_size;
+        data[offset..offset + page_size].copy_from_slice(&interior_page(page_size, root));
+
+        let mut db_file = DBFile::from_bytes(data).unwrap();
+        let expected = format!("cycle detected: page {} is reached more than once", root);
+        let err = db_file.row_count("empty").unwrap_err();
+        assert_eq!(err.root_cause().to_string(), expected);
+        let err = db_file.query("SELECT name FROM empty").unwrap_err();
+        assert_eq!(err.root_cause().to_string(), expected);
+        let err = db_file.table_is_empty("empty").unwrap_err();
+        assert_eq!(err.root_cause().to_string(), expected);
+    }
 }
@@ -65,14 +65,46 @@ macro_rules! field_decoder {
 impl DBHeader {
     pub const SIZE: usize = std::mem::size_of::<Self>();
 
+    /// The header string every database file starts with
+    const HEADER_STRING: &'static [u8; 16] = b"SQLite format 3\0";
+
+    /// Reads a header, checking it starts with the header string and has a valid page size since
+    /// nothing else in the file can be found without them.
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
         if data.len() != Self::SIZE {
             bail!("Input data is wrong size to construct DB header")
         }
 
         let header = data as *const [u8] as *const Self;
-        Ok(unsafe { *header })
+        let header = unsafe { *header };
+        if &header.header_string != Self::HEADER_STRING {
+            bail!("the header string is missing");
+        }
+        let page_size = header.page_size();
+        if !(512..=65536).contains(&page_size) || !page_size.is_power_of_two() {
+            bail!("invalid page size {}", page_size);
+        }
+        Ok(header)
+    }
+
+    /// The page size in bytes. A page size of 65536 doesn't fit in the header's two bytes, so it's
+    /// stored as 1.
+    pub fn page_size(&self) -> u32 {
+        match u16::from_be_bytes(self.page_size) {
+            1 => 65536,
+            size => size as u32,
+        }
     }
 
-    field_decoder! {u16; page_size}
+    field_decoder! {u32; file_change_counter}
+    field_decoder! {u32; in_header_db_size}
+    field_decoder! {u32; version_valid_for}
+    field_decoder! {u32; db_text_encoding}
+
+    /// The size of the database in pages as recorded in the header. This is only trusted when it's
+    /// non-zero and the file was last written by a version of SQLite that keeps it up to date.
+    pub fn db_size(&self) -> Option<u32> {
+        let size = self.in_header_db_size();
+        (size != 0 && self.version_valid_for() == self.file_change_counter()).then_some(size)
+    }
 }
@@ -0,0 +1,983 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+
+use crate::serial_value::{Affinity, Collation, SerialValue};
+use crate::sql::{BinaryOperator, Expr, UnaryOperator, ROWID_NAMES};
+
+/// Evaluates an expression against a single row. `column_map` maps lowercased column names to their
+/// position in `row`. Constant expressions can be evaluated with an empty row and column map.
+pub fn evaluate(
+    expr: &Expr,
+    row: &[SerialValue],
+    column_map: &HashMap<String, usize>,
+) -> Result<SerialValue> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Column(name) => {
+            let ind = column_map
+                .get(&name.to_ascii_lowercase())
+                .ok_or_else(|| no_such_column(name, column_map))?;
+            row.get(*ind)
+                .cloned()
+                .with_context(|| format!("row has no value for column '{}'", name))
+        }
+        Expr::QualifiedColumn { table, column } => {
+            let name = format!("{}.{}", table, column);
+            let ind = column_map
+                .get(&name.to_ascii_lowercase())
+                .ok_or_else(|| no_such_column(&name, column_map))?;
+            row.get(*ind)
+                .cloned()
+                .with_context(|| format!("row has no value for column '{}'", name))
+        }
+        Expr::Star => bail!("'*' is only valid in count(*)"),
+        Expr::Unary { op, expr } => {
+            let value = evaluate(expr, row, column_map)?;
+            match op {
+                UnaryOperator::Negate => Ok(match Numeric::from(&value) {
+                    None => SerialValue::Null,
+                    Some(Numeric::Integer(i)) => match i.checked_neg() {
+                        Some(i) => SerialValue::Int64(i),
+                        None => SerialValue::Float64(-(i as f64)),
+                    },
+                    Some(Numeric::Real(r)) => SerialValue::Float64(-r),
+                }),
+                UnaryOperator::Not => Ok(match is_true(&value) {
+                    None => SerialValue::Null,
+                    Some(b) => boolean(!b),
+                }),
+            }
+        }
+        Expr::Binary { left, op, right } => {
+            let collation = comparison_collation(left, right)?;
+            let left = evaluate(left, row, column_map)?;
+            let right = evaluate(right, row, column_map)?;
+            Ok(evaluate_binary(*op, &left, &right, collation))
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => {
+            let low_collation = comparison_collation(expr, low)?;
+            let high_collation = comparison_collation(expr, high)?;
+            let value = evaluate(expr, row, column_map)?;
+            let low = evaluate(low, row, column_map)?;
+            let high = evaluate(high, row, column_map)?;
+            let between = evaluate_binary(
+                BinaryOperator::And,
+                &evaluate_binary(BinaryOperator::GreaterEqual, &value, &low, low_collation),
+                &evaluate_binary(BinaryOperator::LessEqual, &value, &high, high_collation),
+                Collation::Binary,
+            );
+            Ok(match (is_true(&between), negated) {
+                (None, _) => SerialValue::Null,
+                (Some(b), negated) => boolean(b != *negated),
+            })
+        }
+        Expr::Collate { expr, collation } => {
+            Collation::from_name(collation)?;
+            evaluate(expr, row, column_map)
+        }
+        Expr::Function { name, .. } if expr.is_aggregate() => {
+            bail!("aggregate function {}() cannot be used here", name)
+        }
+        Expr::Function { name, args } => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, row, column_map))
+                .collect::<Result<Vec<_>>>()?;
+            call_function(name, &args)
+        }
+        Expr::Case {
+            operand,
+            branches,
+            else_result,
+        } => {
+            let operand = match operand {
+                Some(operand) => Some((operand, evaluate(operand, row, column_map)?)),
+                None => None,
+            };
+            // Only the result of the branch that's taken is evaluated
+            for (when, then) in branches {
+                let matched = match &operand {
+                    Some((operand, value)) => {
+                        let collation = comparison_collation(operand, when)?;
+                        let when = evaluate(when, row, column_map)?;
+                        evaluate_binary(BinaryOperator::Equal, value, &when, collation)
+                    }
+                    None => evaluate(when, row, column_map)?,
+                };
+                if is_true(&matched) == Some(true) {
+                    return evaluate(then, row, column_map);
+                }
+            }
+            match else_result {
+                Some(result) => evaluate(result, row, column_map),
+                None => Ok(SerialValue::Null),
+            }
+        }
+    }
+}
+
+/// Evaluates an expression over a group of rows. Aggregate functions are computed over every row of
+/// the group, while anything outside of them is evaluated against the group's first row, or a row
+/// of NULLs if the group is empty.
+pub fn evaluate_group(
+    expr: &Expr,
+    rows: &[Vec<SerialValue>],
+    column_map: &HashMap<String, usize>,
+) -> Result<SerialValue> {
+    let expr = replace_aggregates(expr, rows, column_map)?;
+    match rows.first() {
+        Some(row) => evaluate(&expr, row, column_map),
+        None => {
+            let width = column_map.values().max().map_or(0, |max| max + 1);
+            evaluate(&expr, &vec![SerialValue::Null; width], column_map)
+        }
+    }
+}
+
+/// Replaces each aggregate function call in the expression with its result over `rows`.
+fn replace_aggregates(
+    expr: &Expr,
+    rows: &[Vec<SerialValue>],
+    column_map: &HashMap<String, usize>,
+) -> Result<Expr> {
+    match expr {
+        Expr::Function { name, args } if expr.is_aggregate() => {
+            Ok(Expr::Literal(aggregate(name, &args[0], rows, column_map)?))
+        }
+        expr => expr.try_map_children(|child| replace_aggregates(child, rows, column_map)),
+    }
+}
+
+/// The result of `count()`. Counts are kept as `u64` until they're turned into an SQL integer,
+/// which holds any count up to `i64::MAX`.
+pub fn count_value(count: u64) -> Result<SerialValue> {
+    let count = i64::try_from(count).with_context(|| format!("count {} is too large", count))?;
+    Ok(SerialValue::Int64(count))
+}
+
+/// Computes an aggregate function over a group of rows. NULL values of the argument are ignored by
+/// every function.
+fn aggregate(
+    name: &str,
+    arg: &Expr,
+    rows: &[Vec<SerialValue>],
+    column_map: &HashMap<String, usize>,
+) -> Result<SerialValue> {
+    if name == "count" && *arg == Expr::Star {
+        return count_value(rows.len() as u64);
+    }
+
+    let mut values = vec![];
+    for row in rows {
+        let value = evaluate(arg, row, column_map)
+            .with_context(|| format!("evaluating argument of {}()", name))?;
+        if !value.is_null() {
+            values.push(value);
+        }
+    }
+
+    let numbers = || values.iter().filter_map(Numeric::from);
+    let total = || numbers().map(Numeric::as_f64).sum::<f64>();
+    Ok(match name {
+        "count" => count_value(values.len() as u64)?,
+        "min" => values
+            .iter()
+            .min_by(|a, b| a.compare(b))
+            .cloned()
+            .unwrap_or(SerialValue::Null),
+        "max" => values
+            .iter()
+            .max_by(|a, b| a.compare(b))
+            .cloned()
+            .unwrap_or(SerialValue::Null),
+        "sum" if values.is_empty() => SerialValue::Null,
+        "sum" => {
+            // The sum stays an integer as long as every value is one
+            let mut sum = Some(0i64);
+            for number in numbers() {
+                sum = match (sum, number) {
+                    (Some(sum), Numeric::Integer(i)) => {
+                        Some(sum.checked_add(i).context("integer overflow")?)
+                    }
+                    _ => None,
+                };
+            }
+            match sum {
+                Some(sum) => SerialValue::Int64(sum),
+                None => SerialValue::Float64(total()),
+            }
+        }
+        "total" => SerialValue::Float64(total()),
+        "avg" if values.is_empty() => SerialValue::Null,
+        "avg" => SerialValue::Float64(total() / values.len() as f64),
+        _ => unreachable!("not an aggregate function: {}", name),
+    })
+}
+
+/// Whether `text` matches a LIKE pattern, where `%` matches any sequence of characters and `_`
+/// any single character. Letters are compared case-insensitively, but only ASCII ones.
+fn like(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|skip| like(rest, &text[skip..])),
+        Some((&p, rest)) => match text.split_first() {
+            Some((&t, text)) if p == '_' || p.eq_ignore_ascii_case(&t) => like(rest, text),
+            _ => false,
+        },
+    }
+}
+
+/// Whether `text` matches a GLOB pattern, where `*` matches any sequence of characters, `?` any
+/// single character and `[...]` any one of a set of characters, or any character not in it when
+/// the set starts with `^`. Sets can contain ranges such as `a-z`.
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob(rest, &text[skip..])),
+        Some((&p, rest)) => {
+            let Some((&t, text)) = text.split_first() else {
+                return false;
+            };
+            match p {
+                '?' => glob(rest, text),
+                '[' => {
+                    let (negated, set) = match rest.split_first() {
+                        Some(('^', set)) => (true, set),
+                        _ => (false, rest),
+                    };
+                    // A `]` straight after the opening bracket is part of the set
+                    let Some(end) = set.iter().skip(1).position(|&c| c == ']') else {
+                        return false;
+                    };
+                    let (set, rest) = (&set[..end + 1], &set[end + 2..]);
+                    let mut found = false;
+                    let mut i = 0;
+                    while i < set.len() {
+                        if i + 2 < set.len() && set[i + 1] == '-' {
+                            found |= (set[i]..=set[i + 2]).contains(&t);
+                            i += 3;
+                        } else {
+                            found |= set[i] == t;
+                            i += 1;
+                        }
+                    }
+                    found != negated && glob(rest, text)
+                }
+                p => p == t && glob(rest, text),
+            }
+        }
+    }
+}
+
+/// The error for a reference to a column that isn't in the column map, which lists the columns
+/// that are.
+pub fn no_such_column(name: &str, column_map: &HashMap<String, usize>) -> anyhow::Error {
+    if column_map.is_empty() {
+        return anyhow!("no such column: {}", name);
+    }
+
+    // List each column once, preferring its unqualified name, and leave out the rowid's names
+    let mut valid = column_map
+        .iter()
+        .filter(|(name, _)| {
+            let bare = name.rsplit('.').next().unwrap_or(name);
+            !ROWID_NAMES.contains(&bare)
+        })
+        .collect_vec();
+    valid.sort_by_key(|(name, &ind)| (ind, name.contains('.')));
+    valid.dedup_by_key(|(_, ind)| **ind);
+    anyhow!(
+        "no such column: {} (valid columns are: {})",
+        name,
+        valid.into_iter().map(|(name, _)| name).join(", ")
+    )
+}
+
+/// The truth value of a value used as a condition. NULL is neither true nor false, which is
+/// represented as `None`. Anything else is true when its numeric value is non-zero.
+pub fn is_true(value: &SerialValue) -> Option<bool> {
+    match Numeric::from(value)? {
+        Numeric::Integer(i) => Some(i != 0),
+        Numeric::Real(r) => Some(r != 0.0),
+    }
+}
+
+fn boolean(b: bool) -> SerialValue {
+    SerialValue::Int64(b as i64)
+}
+
+fn call_function(name: &str, args: &[SerialValue]) -> Result<SerialValue> {
+    match (name, args) {
+        ("typeof", [value]) => Ok(SerialValue::Text(String::from(value.type_name()))),
+        // The length of text is in characters, while blobs are measured in bytes
+        ("length", [SerialValue::Null]) => Ok(SerialValue::Null),
+        ("length", [value]) => {
+            let length = match (value.as_blob(), value.as_str()) {
+                (Some(blob), _) => blob.len(),
+                (_, Some(text)) => text.chars().count(),
+                _ => value.to_string().chars().count(),
+            };
+            Ok(SerialValue::Int64(length as i64))
+        }
+        ("quote", [value]) => Ok(SerialValue::Text(value.to_sql_literal())),
+        // Anything other than a blob is converted to text first and its UTF-8 bytes are given
+        ("hex", [value]) => {
+            let bytes = match value.apply_affinity(Affinity::Text) {
+                SerialValue::Null => vec![],
+                SerialValue::Blob(bytes) => bytes,
+                text => text.to_string().into_bytes(),
+            };
+            Ok(SerialValue::Text(
+                bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+            ))
+        }
+        // NULL is never equal to anything, so a NULL argument gives back the first one
+        ("nullif", [a, b]) if !a.is_null() && !b.is_null() && a.compare(b).is_eq() => {
+            Ok(SerialValue::Null)
+        }
+        ("nullif", [a, _]) => Ok(a.clone()),
+        ("ifnull", [SerialValue::Null, b]) => Ok(b.clone()),
+        ("ifnull", [a, _]) => Ok(a.clone()),
+        // With several arguments min() and max() are scalar functions rather than aggregates.
+        // Unlike the aggregates they don't skip NULLs: any NULL argument makes the result NULL.
+        ("min" | "max", [first, rest @ ..]) if !rest.is_empty() => {
+            if args.iter().any(SerialValue::is_null) {
+                return Ok(SerialValue::Null);
+            }
+            // As in SQLite, min() takes the last of equal values and max() the first
+            let mut best = first;
+            for arg in rest {
+                let ordering = best.compare(arg);
+                if (name == "min" && ordering.is_ge()) || (name == "max" && ordering.is_lt()) {
+                    best = arg;
+                }
+            }
+            Ok(best.clone())
+        }
+        _ => bail!("no such function: {} with {} argument(s)", name, args.len()),
+    }
+}
+
+/// The numeric value of an operand, following SQLite's conversion rules: NULL stays NULL and
+/// text or blobs that don't look like numbers are treated as 0.
+#[derive(Debug, Clone, Copy)]
+enum Numeric {
+    Integer(i64),
+    Real(f64),
+}
+
+impl Numeric {
+    fn from(value: &SerialValue) -> Option<Self> {
+        match value {
+            SerialValue::Null => None,
+            SerialValue::Float64(r) => Some(Self::Real(*r)),
+            SerialValue::Text(t) => Some(Self::from_prefix(t)),
+            SerialValue::Blob(_) => Some(Self::Integer(0)),
+            v => Some(Self::Integer(v.as_i64().expect("integer serial value"))),
+        }
+    }
+
+    /// The number at the start of some text, read as SQLite does for arithmetic: leading
+    /// whitespace is skipped and anything after the longest prefix that forms a number is ignored,
+    /// so `'3abc'` is 3. Text that doesn't start with a number is 0.
+    fn from_prefix(text: &str) -> Self {
+        let text = text.trim_start();
+        let bytes = text.as_bytes();
+        let digits_from = |start: usize| {
+            start
+                + bytes[start.min(bytes.len())..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_digit())
+                    .count()
+        };
+
+        let sign = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+        let mut end = digits_from(sign);
+        let mut num_digits = end - sign;
+        let mut is_real = false;
+        if bytes.get(end) == Some(&b'.') {
+            let fraction_end = digits_from(end + 1);
+            num_digits += fraction_end - end - 1;
+            end = fraction_end;
+            is_real = true;
+        }
+        if num_digits == 0 {
+            return Self::Integer(0);
+        }
+        // An exponent only counts when it has digits
+        if let Some(b'e' | b'E') = bytes.get(end) {
+            let exponent_start =
+                end + 1 + usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+            let exponent_end = digits_from(exponent_start);
+            if exponent_end > exponent_start {
+                end = exponent_end;
+                is_real = true;
+            }
+        }
+
+        let prefix = &text[..end];
+        match (is_real, prefix.parse::<i64>()) {
+            (false, Ok(i)) => Self::Integer(i),
+            _ => Self::Real(prefix.parse().unwrap_or(0.0)),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Self::Integer(i) => i as f64,
+            Self::Real(r) => r,
+        }
+    }
+
+    fn into_value(self) -> SerialValue {
+        match self {
+            Self::Integer(i) => SerialValue::Int64(i),
+            Self::Real(r) => SerialValue::Float64(r),
+        }
+    }
+}
+
+/// The collating sequence given to an expression with `COLLATE`, if any.
+pub fn collation_of(expr: &Expr) -> Result<Option<Collation>> {
+    match expr {
+        Expr::Collate { collation, .. } => Collation::from_name(collation).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// The collating sequence used to compare two operands: that of the left operand if it has one,
+/// otherwise that of the right, otherwise `BINARY`.
+fn comparison_collation(left: &Expr, right: &Expr) -> Result<Collation> {
+    Ok(collation_of(left)?
+        .or(collation_of(right)?)
+        .unwrap_or_default())
+}
+
+fn evaluate_binary(
+    op: BinaryOperator,
+    left: &SerialValue,
+    right: &SerialValue,
+    collation: Collation,
+) -> SerialValue {
+    match op {
+        BinaryOperator::Concat => {
+            return match (left, right) {
+                (SerialValue::Null, _) | (_, SerialValue::Null) => SerialValue::Null,
+                (l, r) => SerialValue::Text(format!("{}{}", l, r)),
+            }
+        }
+        // Three-valued logic: a NULL operand only matters if the other side doesn't already
+        // decide the result
+        BinaryOperator::And => {
+            return match (is_true(left), is_true(right)) {
+                (Some(false), _) | (_, Some(false)) => boolean(false),
+                (Some(true), Some(true)) => boolean(true),
+                _ => SerialValue::Null,
+            }
+        }
+        BinaryOperator::Or => {
+            return match (is_true(left), is_true(right)) {
+                (Some(true), _) | (_, Some(true)) => boolean(true),
+                (Some(false), Some(false)) => boolean(false),
+                _ => SerialValue::Null,
+            }
+        }
+        BinaryOperator::Equal
+        | BinaryOperator::NotEqual
+        | BinaryOperator::Less
+        | BinaryOperator::LessEqual
+        | BinaryOperator::Greater
+        | BinaryOperator::GreaterEqual => {
+            if left.is_null() || right.is_null() {
+                return SerialValue::Null;
+            }
+            let ordering = left.compare_collated(right, collation);
+            return boolean(match op {
+                BinaryOperator::Equal => ordering.is_eq(),
+                BinaryOperator::NotEqual => ordering.is_ne(),
+                BinaryOperator::Less => ordering.is_lt(),
+                BinaryOperator::LessEqual => ordering.is_le(),
+                BinaryOperator::Greater => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            });
+        }
+        BinaryOperator::Like
+        | BinaryOperator::NotLike
+        | BinaryOperator::Glob
+        | BinaryOperator::NotGlob => {
+            if left.is_null() || right.is_null() {
+                return SerialValue::Null;
+            }
+            let text = left.to_string().chars().collect_vec();
+            let pattern = right.to_string().chars().collect_vec();
+            return boolean(match op {
+                BinaryOperator::Like => like(&pattern, &text),
+                BinaryOperator::NotLike => !like(&pattern, &text),
+                BinaryOperator::Glob => glob(&pattern, &text),
+                _ => !glob(&pattern, &text),
+            });
+        }
+        _ => {}
+    }
+
+    let (Some(l), Some(r)) = (Numeric::from(left), Numeric::from(right)) else {
+        return SerialValue::Null;
+    };
+
+    let result = match (l, r) {
+        (Numeric::Integer(l), Numeric::Integer(r)) => {
+            let checked = match op {
+                BinaryOperator::Add => l.checked_add(r),
+                BinaryOperator::Subtract => l.checked_sub(r),
+                BinaryOperator::Multiply => l.checked_mul(r),
+                BinaryOperator::Divide | BinaryOperator::Modulo if r == 0 => {
+                    return SerialValue::Null
+                }
+                BinaryOperator::Divide => l.checked_div(r),
+                // The only overflow is i64::MIN % -1, which is 0
+                BinaryOperator::Modulo => Some(l.wrapping_rem(r)),
+                _ => unreachable!("handled above"),
+            };
+            match checked {
+                Some(i) => Some(Numeric::Integer(i)),
+                // Integer overflow falls back to floating point, as SQLite does
+                None => real_arithmetic(op, l as f64, r as f64),
+            }
+        }
+        (l, r) => real_arithmetic(op, l.as_f64(), r.as_f64()),
+    };
+
+    result.map_or(SerialValue::Null, Numeric::into_value)
+}
+
+fn real_arithmetic(op: BinaryOperator, l: f64, r: f64) -> Option<Numeric> {
+    Some(Numeric::Real(match op {
+        BinaryOperator::Add => l + r,
+        BinaryOperator::Subtract => l - r,
+        BinaryOperator::Multiply => l * r,
+        BinaryOperator::Divide if r == 0.0 => return None,
+        BinaryOperator::Divide => l / r,
+        // SQLite computes the remainder of real operands on their integer parts
+        BinaryOperator::Modulo if r as i64 == 0 => return None,
+        BinaryOperator::Modulo => (l as i64).wrapping_rem(r as i64) as f64,
+        _ => unreachable!("only called for arithmetic operators"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::sql::sql_statement;
+    use crate::sql::Statement;
+
+    fn evaluate_constant(sql: &str) -> Vec<SerialValue> {
+        let Ok(Statement::Select(select)) = sql_statement(sql) else {
+            panic!("expected a select statement")
+        };
+        select
+            .select
+            .iter()
+            .map(|column| evaluate(&column.expr, &[], &HashMap::new()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(evaluate_constant("SELECT 2 * 3"), [SerialValue::Int64(6)]);
+        assert_eq!(
+            evaluate_constant("SELECT 1 + 2 * 3, (1 + 2) * 3, 7 / 2, 7 % 4, 7.0 / 2, -(2 - 5)"),
+            [
+                SerialValue::Int64(7),
+                SerialValue::Int64(9),
+                SerialValue::Int64(3),
+                SerialValue::Int64(3),
+                SerialValue::Float64(3.5),
+                SerialValue::Int64(3),
+            ]
+        );
+        // The remainder of the smallest integer by -1 overflows, but is 0
+        assert_eq!(
+            evaluate_constant(
+                "SELECT (-9223372036854775807 - 1) % -1, -9223372036854775808.0 % -1"
+            ),
+            [SerialValue::Int64(0), SerialValue::Float64(0.0)]
+        );
+    }
+
+    #[test]
+    fn case_expressions() {
+        assert_eq!(
+            evaluate_constant(
+                "SELECT CASE WHEN 1 > 2 THEN 'a' WHEN 2 > 1 THEN 'b' ELSE 'c' END, \
+                 CASE WHEN NULL THEN 1 WHEN 0 THEN 2 ELSE 3 END, \
+                 CASE WHEN 0 THEN 1 END"
+            ),
+            [
+                SerialValue::Text(String::from("b")),
+                SerialValue::Int64(3),
+                SerialValue::Null,
+            ]
+        );
+        assert_eq!(
+            evaluate_constant(
+                "SELECT CASE 2 WHEN 1 THEN 'one' WHEN 2 THEN 'two' END, \
+                 CASE 'X' WHEN 'x' THEN 1 WHEN 'x' COLLATE NOCASE THEN 2 END, \
+                 CASE NULL WHEN NULL THEN 1 ELSE 0 END, \
+                 CASE 3 WHEN 1 THEN 'one' END"
+            ),
+            [
+                SerialValue::Text(String::from("two")),
+                SerialValue::Int64(2),
+                SerialValue::Int64(0),
+                SerialValue::Null,
+            ]
+        );
+        // Branches that aren't taken aren't evaluated
+        assert_eq!(
+            evaluate_constant("SELECT CASE WHEN 1 THEN 'ok' ELSE 1 / 0 END"),
+            [SerialValue::Text(String::from("ok"))]
+        );
+    }
+
+    #[test]
+    fn null_and_division_by_zero() {
+        assert_eq!(
+            evaluate_constant("SELECT 1 + NULL, 1 / 0, NULL || 'a'"),
+            [SerialValue::Null, SerialValue::Null, SerialValue::Null]
+        );
+    }
+
+    #[test]
+    fn text_operands() {
+        assert_eq!(
+            evaluate_constant("SELECT 'hello' || ' ' || 'world', '3' + 4, 'abc' + 1"),
+            [
+                SerialValue::Text(String::from("hello world")),
+                SerialValue::Int64(7),
+                SerialValue::Int64(1),
+            ]
+        );
+        // Only the longest prefix of the text that's a number is used
+        assert_eq!(
+            evaluate_constant(
+                "SELECT '3abc' + 1, ' 2.5x' + 0, '1e3x' + 0, '.5a' + 0, '-3z' * 2, '1e' + 0, \
+                 '5.' + 0, '  +7 ' + 0, '0x10' + 0, '-' + 0, '.' + 0, 'e5' + 0, \
+                 '99999999999999999999x' + 0"
+            ),
+            [
+                SerialValue::Int64(4),
+                SerialValue::Float64(2.5),
+                SerialValue::Float64(1000.0),
+                SerialValue::Float64(0.5),
+                SerialValue::Int64(-6),
+                SerialValue::Int64(1),
+                SerialValue::Float64(5.0),
+                SerialValue::Int64(7),
+                SerialValue::Int64(0),
+                SerialValue::Int64(0),
+                SerialValue::Int64(0),
+                SerialValue::Int64(0),
+                SerialValue::Float64(1e20),
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_quote() {
+        let text = |t: &str| SerialValue::Text(String::from(t));
+        assert_eq!(
+            evaluate_constant(
+                "SELECT hex(X'FF00'), hex('ab'), hex(12), hex(2.5), hex(1.0), hex(NULL), hex('é')"
+            ),
+            [
+                text("FF00"),
+                text("6162"),
+                text("3132"),
+                text("322E35"),
+                text("312E30"),
+                text(""),
+                text("C3A9"),
+            ]
+        );
+        assert_eq!(
+            evaluate_constant(
+                "SELECT quote('a''b'), quote(NULL), quote(12), quote(2.5), quote(1.0), \
+                 quote(X'CAFE')"
+            ),
+            [
+                text("'a''b'"),
+                text("NULL"),
+                text("12"),
+                text("2.5"),
+                text("1.0"),
+                text("X'CAFE'"),
+            ]
+        );
+        // Reals too large or small to write out in full keep an exponent, matching sqlite3
+        assert_eq!(
+            evaluate_constant(
+                "SELECT quote(100000000000000000000.0), quote(-1.5e300), quote(1e15), \
+                 quote(100000000000000.0), quote(123456789012345678.0), quote(0.00001), \
+                 quote(0.0001), quote(0.0)"
+            ),
+            [
+                text("1.0e+20"),
+                text("-1.5e+300"),
+                text("1.0e+15"),
+                text("100000000000000.0"),
+                text("1.2345678901234568e+17"),
+                text("1.0e-05"),
+                text("0.0001"),
+                text("0.0"),
+            ]
+        );
+        for literal in ["1.0e+20", "-1.5e+300", "1.0e-05"] {
+            assert_eq!(
+                evaluate_constant(&format!("SELECT quote({0}) = '{0}', typeof({0})", literal)),
+                [SerialValue::Int64(1), text("real")],
+                "{}",
+                literal
+            );
+        }
+    }
+
+    #[test]
+    fn nullif_ifnull() {
+        assert_eq!(
+            evaluate_constant(
+                "SELECT nullif(1, 1), nullif(1, 1.0), nullif(1, 2), nullif('a', 'A'), \
+                 nullif(NULL, 1), nullif(1, NULL)"
+            ),
+            [
+                SerialValue::Null,
+                SerialValue::Null,
+                SerialValue::Int64(1),
+                SerialValue::Text(String::from("a")),
+                SerialValue::Null,
+                SerialValue::Int64(1),
+            ]
+        );
+        assert_eq!(
+            evaluate_constant("SELECT ifnull(NULL, 'b'), ifnull('a', 'b'), ifnull(NULL, NULL)"),
+            [
+                SerialValue::Text(String::from("b")),
+                SerialValue::Text(String::from("a")),
+                SerialValue::Null,
+            ]
+        );
+        for name in ["nullif", "ifnull"] {
+            let call = |args: usize| {
+                let expr = Expr::Function {
+                    name: String::from(name),
+                    args: vec![Expr::Literal(SerialValue::Int64(1)); args],
+                };
+                evaluate(&expr, &[], &HashMap::new())
+            };
+            assert_eq!(
+                call(1).unwrap_err().to_string(),
+                format!("no such function: {} with 1 argument(s)", name)
+            );
+            assert!(call(3).is_err());
+        }
+    }
+
+    #[test]
+    fn scalar_min_max() {
+        assert_eq!(
+            evaluate_constant(
+                "SELECT min(3, 1, 2), max(3, 1, 2), min('b', 'a', 5), max('a', 2, X'00'), \
+                 max(2, 2.5), min(1, 1.0), max(1, 1.0)"
+            ),
+            [
+                SerialValue::Int64(1),
+                SerialValue::Int64(3),
+                SerialValue::Int64(5),
+                SerialValue::Blob(vec![0]),
+                SerialValue::Float64(2.5),
+                SerialValue::Float64(1.0),
+                SerialValue::Int64(1),
+            ]
+        );
+        assert_eq!(
+            evaluate_constant("SELECT min(3, NULL, 1), max(NULL, 'a')"),
+            [SerialValue::Null, SerialValue::Null]
+        );
+        assert!(evaluate(
+            &Expr::Function {
+                name: String::from("max"),
+                args: vec![],
+            },
+            &[],
+            &HashMap::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn typeof_function() {
+        assert_eq!(
+            evaluate_constant("SELECT typeof(42), TYPEOF(1.5), typeof('a'), typeof(NULL)"),
+            [
+                SerialValue::Text(String::from("integer")),
+                SerialValue::Text(String::from("real")),
+                SerialValue::Text(String::from("text")),
+                SerialValue::Text(String::from("null")),
+            ]
+        );
+    }
+
+    #[test]
+    fn comparison_and_logic() {
+        assert_eq!(
+            evaluate_constant(
+                "SELECT 1 = 1.0, 2 < 'a', 'b' >= 'a', 1 != NULL, NULL AND 0, NULL OR 1, NOT NULL, NOT 0"
+            ),
+            [
+                SerialValue::Int64(1),
+                SerialValue::Int64(1),
+                SerialValue::Int64(1),
+                SerialValue::Null,
+                SerialValue::Int64(0),
+                SerialValue::Int64(1),
+                SerialValue::Null,
+                SerialValue::Int64(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn between() {
+        assert_eq!(
+            evaluate_constant(
+                "SELECT 2 BETWEEN 1 AND 3, 5 BETWEEN 1 AND 1 + 3, 'b' NOT BETWEEN 'a' AND 'c', \
+                 NULL BETWEEN 1 AND 2, 0 BETWEEN 1 AND NULL"
+            ),
+            [
+                SerialValue::Int64(1),
+                SerialValue::Int64(0),
+                SerialValue::Int64(0),
+                SerialValue::Null,
+                SerialValue::Int64(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn length() {
+        assert_eq!(
+            evaluate_constant("SELECT length('héllo'), length(12.5), length(NULL), length('')"),
+            [
+                SerialValue::Int64(5),
+                SerialValue::Int64(4),
+                SerialValue::Null,
+                SerialValue::Int64(0),
+            ]
+        );
+        let blob = call_function("length", &[SerialValue::Blob(vec![0xca, 0xfe, 0])]).unwrap();
+        assert_eq!(blob, SerialValue::Int64(3));
+    }
+
+    #[test]
+    fn like_and_glob() {
+        let matches = |sql: &str| {
+            evaluate_constant(sql)
+                .into_iter()
+                .map(|v| v.to_string())
+                .join(",")
+        };
+        assert_eq!(
+            matches(
+                "SELECT 'The Hobbit' LIKE 'the%', 'abc' LIKE 'a_c', 'abc' LIKE 'a_', \
+                 'ABC' LIKE 'abc', 123 LIKE '1%', 'abc' NOT LIKE 'A%', NULL LIKE '%'"
+            ),
+            "1,1,0,1,1,0,(null)"
+        );
+        assert_eq!(
+            matches(
+                "SELECT 'The Hobbit' GLOB 'the*', 'The Hobbit' GLOB 'The*', 'abc' GLOB 'a?c', \
+                 'abc' GLOB '[a-c]b[^a-b]', 'a]' GLOB 'a[]]', 'abc' NOT GLOB '*d'"
+            ),
+            "0,1,1,1,1,1"
+        );
+    }
+
+    #[test]
+    fn collate() {
+        assert_eq!(
+            evaluate_constant(
+                "SELECT 'abc' = 'abc  ' COLLATE RTRIM, 'abc' COLLATE RTRIM = 'abc  ', \
+                 'abc' = 'abc  ', 'ABC' COLLATE NOCASE = 'abc', \
+                 'abc ' COLLATE RTRIM BETWEEN 'abc' AND 'abc'"
+            ),
+            [
+                SerialValue::Int64(1),
+                SerialValue::Int64(1),
+                SerialValue::Int64(0),
+                SerialValue::Int64(1),
+                SerialValue::Int64(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn column_lookup() {
+        let column_map = HashMap::from([(String::from("a"), 0), (String::from("b"), 1)]);
+        let row = [SerialValue::Int8(2), SerialValue::Int16(40)];
+        let Ok(Statement::Select(select)) = sql_statement("SELECT a + B FROM t") else {
+            panic!("expected a select statement")
+        };
+        assert_eq!(
+            evaluate(&select.select[0].expr, &row, &column_map).unwrap(),
+            SerialValue::Int64(42)
+        );
+    }
+
+    #[test]
+    fn large_count() {
+        let count = u32::MAX as u64 + 1;
+        assert_eq!(count_value(count).unwrap(), SerialValue::Int64(4294967296));
+        assert_eq!(count_value(count).unwrap().to_string(), "4294967296");
+        assert_eq!(
+            count_value(i64::MAX as u64).unwrap(),
+            SerialValue::Int64(i64::MAX)
+        );
+        assert_eq!(
+            count_value(u64::MAX).unwrap_err().to_string(),
+            "count 18446744073709551615 is too large"
+        );
+    }
+
+    #[test]
+    fn missing_column() {
+        // A column the row doesn't have, e.g. because the schema no longer matches the query
+        let Ok(Statement::Select(select)) =
+            sql_statement("SELECT id FROM t WHERE bogus = 1 AND t.missing = 2")
+        else {
+            panic!("expected a select statement")
+        };
+        let column_map = HashMap::from([
+            (String::from("id"), 0),
+            (String::from("t.id"), 0),
+            (String::from("rowid"), 0),
+            (String::from("name"), 1),
+            (String::from("t.name"), 1),
+        ]);
+        let row = [SerialValue::Int64(1), SerialValue::Text(String::from("a"))];
+        let Some(Expr::Binary { left, right, .. }) = select.where_clause else {
+            panic!("expected a binary expression")
+        };
+        for (expr, name) in [(left, "bogus"), (right, "t.missing")] {
+            assert_eq!(
+                evaluate(&expr, &row, &column_map).unwrap_err().to_string(),
+                format!("no such column: {} (valid columns are: id, name)", name)
+            );
+        }
+    }
+}
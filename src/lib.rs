@@ -1,14 +1,19 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use anyhow::Result;
-use byteorder::ReadBytesExt;
+use byteorder::{ReadBytesExt, WriteBytesExt};
 
 pub mod btree_page;
 pub mod db_file;
 mod db_header;
+pub mod eval;
+pub mod output;
+pub mod query;
 pub mod schema_object;
 pub mod serial_value;
 pub mod sql;
+#[cfg(test)]
+mod testing;
 
 pub trait ReadVarint<T> {
     fn read_varint(&mut self) -> Result<u64>;
@@ -28,7 +33,7 @@ where
     /// the later bytes.
     fn read_varint(&mut self) -> Result<u64> {
         let mut result = 0u64;
-        for _ in 0..=8 {
+        for _ in 0..8 {
             let a = self.read_u8()?;
             result <<= 7; // make room for this byte
             result += (a & 0b0111_1111) as u64;
@@ -46,6 +51,40 @@ where
     }
 }
 
+pub trait WriteVarint {
+    fn write_varint(&mut self, value: u64) -> Result<usize>;
+}
+
+impl<T> WriteVarint for T
+where
+    T: Write,
+{
+    /// Writes `value` as a varint in the fewest bytes that can hold it, the inverse of
+    /// `ReadVarint::read_varint`, and returns the number of bytes written.
+    fn write_varint(&mut self, value: u64) -> Result<usize> {
+        if value >> 56 != 0 {
+            // The ninth byte holds a full 8 bits, leaving 56 bits for the first eight
+            for i in 0..8 {
+                self.write_u8((value >> (57 - 7 * i)) as u8 | 0x80)?;
+            }
+            self.write_u8(value as u8)?;
+            return Ok(9);
+        }
+
+        // Groups of seven bits, least significant first
+        let mut groups = vec![(value & 0x7f) as u8];
+        let mut rest = value >> 7;
+        while rest != 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        for byte in groups.iter().rev() {
+            self.write_u8(*byte)?;
+        }
+        Ok(groups.len())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,5 +108,28 @@ mod tests {
         assert_eq!(data.read_varint().unwrap(), 16566)
     }
 
+    #[test]
+    fn write_round_trip() {
+        for (value, len) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16383, 2),
+            (16384, 3),
+            ((1 << 56) - 1, 8),
+            (1 << 56, 9),
+            (u64::MAX, 9),
+        ] {
+            let mut data = vec![];
+            assert_eq!(data.write_varint(value).unwrap(), len, "{}", value);
+            assert_eq!(data.len(), len, "{}", value);
+            assert_eq!(Cursor::new(data).read_varint().unwrap(), value);
+        }
+
+        let mut data = vec![];
+        data.write_varint(14398).unwrap();
+        assert_eq!(data, [240, 62]);
+    }
+
     // TODO: More tests
 }
@@ -1,349 +1,585 @@
-use std::cmp::Ordering;
 use std::fs::File;
+use std::io::{BufRead, IsTerminal, Write};
 
 use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 
-use sqlite_starter_rust::btree_page::{BTreePage, InteriorCell, PageType};
 use sqlite_starter_rust::db_file::DBFile;
-use sqlite_starter_rust::schema_object::SchemaObject;
-use sqlite_starter_rust::serial_value::SerialValue;
-use sqlite_starter_rust::sql::sql::sql_statement;
-use sqlite_starter_rust::sql::{SelectStatement, Statement};
+use sqlite_starter_rust::output::{write_name_grid, OutputFormatter, OutputMode, LINE_WIDTH};
+use sqlite_starter_rust::sql::is_complete_statement;
 
 fn main() -> Result<()> {
     // Parse arguments
     let args = std::env::args().collect::<Vec<_>>();
-    match args.len() {
-        0 | 1 => bail!("Missing <database path> and <command>"),
-        2 => bail!("Missing <command>"),
-        _ => {}
+    if args.len() < 2 {
+        bail!("Missing <database path>");
     }
 
     let mut file = File::open(&args[1])?;
     let mut db_file = DBFile::new(&mut file).context("constructing DBFile")?;
 
-    // Parse command and act accordingly
-    let command = &args[2];
-    match command.as_str() {
-        ".dbinfo" => {
-            println!("database page size: {}", db_file.header.page_size());
-            println!("number of tables: {}", db_file.first_page.num_cells);
-        }
-        ".tables" => {
-            println!(
-                "{}",
-                db_file.table_objects()?.map(|obj| obj.table_name).join(" ")
-            );
+    let mut cli = Cli::default();
+    let mut stdout = std::io::stdout().lock();
+    if args.len() == 2 {
+        // Without a command, commands are read from stdin instead
+        let stdin = std::io::stdin();
+        let prompt = stdin.is_terminal();
+        return cli.run_repl(&mut db_file, stdin.lock(), &mut stdout, prompt);
+    }
+
+    // Every remaining argument is run as a separate command, in order
+    for command in &args[2..] {
+        cli.run_command(&mut db_file, command, &mut stdout)?;
+    }
+
+    Ok(())
+}
+
+/// State that persists between the commands run in a single session.
+struct Cli {
+    mode: OutputMode,
+    /// Separator between values in list mode
+    separator: String,
+    /// Writes query results, as selected by `mode` and `separator`
+    formatter: Box<dyn OutputFormatter>,
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        let mode = OutputMode::default();
+        let separator = String::from("|");
+        Self {
+            formatter: mode.formatter(&separator),
+            mode,
+            separator,
         }
-        ".tables-long" => {
-            for table in db_file.table_objects()? {
-                println!("{}: {}", table.table_name, table.sql);
+    }
+}
+
+impl Cli {
+    fn run_command(
+        &mut self,
+        db_file: &mut DBFile,
+        command: &str,
+        out: &mut impl Write,
+    ) -> Result<()> {
+        // Parse command and act accordingly
+        match command.split_whitespace().collect_vec().as_slice() {
+            [".dbinfo"] => {
+                writeln!(out, "database page size: {}", db_file.header.page_size())?;
+                writeln!(
+                    out,
+                    "number of tables: {}",
+                    db_file.all_schema_objects()?.count()
+                )?;
             }
-        }
-        ".indexes" => {
-            for index in db_file.index_objects()? {
-                println!("{} on {}:\n\t{}", index.name, index.table_name, index.sql)
+            [".tables"] => {
+                let names = db_file.table_names()?.into_iter().sorted().collect_vec();
+                write_name_grid(out, &names, LINE_WIDTH)?;
+            }
+            [".tables", "--plain"] => {
+                writeln!(out, "{}", db_file.table_names()?.join(" "))?;
+            }
+            [".tables-long"] => {
+                for table in db_file.table_objects()? {
+                    writeln!(out, "{}: {}", table.table_name, table.sql)?;
+                }
+            }
+            [".schema"] => {
+                write!(out, "{}", db_file.schema_sql(true)?)?;
+            }
+            [".indexes", table @ ..] if table.len() <= 1 => {
+                let indexes = db_file.index_objects()?.filter(|index| {
+                    table
+                        .first()
+                        .is_none_or(|table| index.table_name.eq_ignore_ascii_case(table))
+                });
+                for index in indexes {
+                    writeln!(
+                        out,
+                        "{} on {}:\n\t{}",
+                        index.name, index.table_name, index.sql
+                    )?;
+                }
+            }
+            [".pageinfo", page] => {
+                let page = page
+                    .parse()
+                    .with_context(|| format!("invalid page number '{}'", page))?;
+                let page = db_file.load_page_at(page)?;
+                writeln!(out, "page type: {}", page.page_type)?;
+                writeln!(out, "number of cells: {}", page.num_cells)?;
+                writeln!(out, "cell content start: {}", page.cell_content_start())?;
+                writeln!(out, "free space: {}", page.free_space()?)?;
+                if let Some(right_ptr) = page.right_most_pointer {
+                    writeln!(out, "right-most pointer: {}", right_ptr)?;
+                }
+                writeln!(
+                    out,
+                    "cell pointers: {}",
+                    page.cell_pointers.iter().join(" ")
+                )?;
+            }
+            [".mode", mode] => {
+                self.mode = OutputMode::from(mode)?;
+                self.formatter = self.mode.formatter(&self.separator);
+            }
+            [".separator", separator] => {
+                self.separator = unescape(separator);
+                self.formatter = self.mode.formatter(&self.separator);
+            }
+            _ => {
+                let formatter = &mut self.formatter;
+                let mut names = None;
+                let result = db_file.query_each(command, |columns, row| {
+                    let names = names.get_or_insert_with(|| {
+                        columns.iter().map(|c| c.name.clone()).collect_vec()
+                    });
+                    formatter.write_row(out, names, &row)
+                });
+                // Rows written before an error still make up a result set
+                self.formatter.finish(out)?;
+                result?;
             }
         }
-        command => {
-            let statement = sql_statement(command).context("parsing SQL statement")?;
-            match statement {
-                Statement::Select(s) => {
-                    let schema = db_file
-                        .schema_for_table(&s.from)
-                        .context("loading table schema")?;
-
-                    let root_page = db_file
-                        .load_page_at(
-                            schema
-                                .root_page
-                                .context("getting root page from table schema")?,
-                        )
-                        .context("loading root page for table")?;
-
-                    if s.select.len() == 1 && s.select[0].eq_ignore_ascii_case("count(*)") {
-                        // TODO: We don't really need to go and retrieve the rows to get a count if
-                        // there's an index.
-                        println!("{}", select_rows(&mut db_file, root_page, &s)?.len());
+
+        Ok(())
+    }
+
+    /// Reads commands from `input` until it ends. Dot-commands take a single line, while SQL is
+    /// collected over as many lines as it takes to form a complete statement, so a statement
+    /// doesn't need a closing semicolon. Errors are reported on stderr and don't end the session.
+    fn run_repl(
+        &mut self,
+        db_file: &mut DBFile,
+        input: impl BufRead,
+        out: &mut impl Write,
+        prompt: bool,
+    ) -> Result<()> {
+        let mut statement = String::new();
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(
+                    out,
+                    "{}",
+                    if statement.is_empty() {
+                        "sqlite> "
                     } else {
-                        return select_and_print(&mut db_file, &schema, root_page, &s);
+                        "   ...> "
                     }
+                )?;
+                out.flush()?;
+            }
+            let Some(line) = lines.next().transpose()? else {
+                break;
+            };
+
+            let command = if statement.is_empty() && line.trim_start().starts_with('.') {
+                line.trim().to_string()
+            } else {
+                statement.push_str(&line);
+                statement.push('\n');
+                if statement.trim().is_empty() {
+                    statement.clear();
+                    continue;
                 }
-                Statement::CreateTable(_) | Statement::CreateIndex(_) => {
-                    bail!("create statements not supported")
+                if !is_complete_statement(&statement) {
+                    continue;
                 }
+                std::mem::take(&mut statement)
+            };
+            if let Err(err) = self.run_command(db_file, command.trim(), out) {
+                eprintln!("Error: {:#}", err);
             }
         }
+        Ok(())
     }
-
-    Ok(())
 }
 
-fn select_and_print(
-    db_file: &mut DBFile,
-    table: &SchemaObject,
-    root_page: BTreePage,
-    select_statement: &SelectStatement,
-) -> Result<()> {
-    let column_map = table.column_map().context("retrieving column order")?;
-    let column_indices: Vec<usize> = select_statement
-        .select
-        .iter()
-        .map(|col| column_map[col])
-        .collect();
-
-    let rows = select_rows(db_file, root_page, select_statement)?;
-    match &select_statement.where_clause {
-        Some(where_clause) => {
-            let where_col_ind = column_map
-                .get(where_clause.column.as_str())
-                .copied()
-                .context("finding index of where column")?;
-            let where_val = where_clause.value.as_str();
-
-            for row in rows {
-                if row[where_col_ind].to_string() == where_val {
-                    print_row(row, &column_indices)
-                }
+/// Expands the `\t`, `\n` and `\\` escapes sqlite3 accepts in dot-command arguments.
+fn unescape(arg: &str) -> String {
+    let mut result = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('t')) => result.push('\t'),
+            ('\\', Some('n')) => result.push('\n'),
+            ('\\', Some('\\')) => result.push('\\'),
+            _ => {
+                result.push(c);
+                continue;
             }
         }
-        None => {
-            for row in rows {
-                print_row(row, &column_indices)
-            }
-        }
-    };
-
-    Ok(())
+        chars.next();
+    }
+    result
 }
 
-fn select_rows(
-    db_file: &mut DBFile,
-    root_page: BTreePage,
-    select_statement: &SelectStatement,
-) -> Result<Vec<Vec<SerialValue>>> {
-    // If there is a where clause, try to load an index for the given filter column. If an
-    // index is found, load the matching row_id's from the index.
-    let index_row_ids: Option<Vec<u64>> = match &select_statement.where_clause {
-        Some(where_clause) => {
-            let index_page = db_file
-                .get_index_page(&select_statement.from, &where_clause.column)
-                .context("finding index page")?;
-
-            match index_page {
-                Some(pos) => {
-                    let page = db_file.load_page_at(pos)?;
-                    Some(search_index(db_file, page, &where_clause.value)?)
-                }
-                None => None,
-            }
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use sqlite_starter_rust::serial_value::SerialValue;
+
+    use super::*;
+
+    fn run_session(commands: &[&str]) -> String {
+        run_session_on("sample.db", commands)
+    }
+
+    fn run_session_on(path: &str, commands: &[&str]) -> String {
+        let mut file = File::open(path).unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut cli = Cli::default();
+        let mut out = vec![];
+        for command in commands {
+            cli.run_command(&mut db_file, command, &mut out).unwrap();
         }
-        None => None,
-    };
+        String::from_utf8(out).unwrap()
+    }
 
-    match index_row_ids {
-        Some(row_ids) => select_with_index(db_file, root_page, &row_ids),
-        None => select_without_index(db_file, root_page),
+    fn run_repl(input: &str) -> String {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut out = vec![];
+        Cli::default()
+            .run_repl(&mut db_file, input.as_bytes(), &mut out, false)
+            .unwrap();
+        String::from_utf8(out).unwrap()
     }
-}
 
-fn select_without_index(db_file: &mut DBFile, page: BTreePage) -> Result<Vec<Vec<SerialValue>>> {
-    let mut result = vec![];
+    #[test]
+    fn repl() {
+        assert_eq!(
+            run_repl("SELECT name FROM apples WHERE id = 2\n.mode csv\nSELECT id, name FROM apples WHERE id = 3;\n"),
+            "Fuji\n3,Honeycrisp\n"
+        );
+        // The open quote continues the statement on the next line
+        assert_eq!(
+            run_repl("SELECT 'multi\nline: ' || name FROM apples WHERE id = 1\n\n"),
+            "multi\nline: Granny Smith\n"
+        );
+        // An error doesn't stop later statements from running
+        assert_eq!(
+            run_repl("SELECT bogus FROM apples\nSELECT count(*) FROM apples\n"),
+            "4\n"
+        );
+    }
 
-    match page.page_type {
-        PageType::LeafTable => {
-            // TODO: It would be possible to pass the column indices we want to this function and
-            // skip over the serial values for any columns we don't care about.
-            let cells = page.read_cells().context("reading cells from root page")?;
-            for cell in cells {
-                result.push(cell)
-            }
-        }
-        PageType::InteriorTable => {
-            let cells = page
-                .read_interior_cells()
-                .context("reading interior cells")?;
-
-            for interior_cell in cells {
-                let InteriorCell::Table(cell) = interior_cell else {
-                    bail!("invalid cell type")
-                };
-                let page = db_file
-                    .load_page_at(cell.left_child_page as usize)
-                    .context("loading page")?;
-                result.extend(select_without_index(db_file, page)?);
-            }
+    #[test]
+    fn mode_applies_to_later_queries() {
+        let query = "SELECT id, name FROM apples WHERE color = 'Red'";
+        assert_eq!(
+            run_session(&[query, ".mode csv", query, ".mode json", query]),
+            "2|Fuji\n2,Fuji\n[{\"id\":2,\"name\":\"Fuji\"}]\n"
+        );
+    }
 
-            if let Some(right_ptr) = page.right_most_pointer {
-                let page = db_file
-                    .load_page_at(right_ptr as usize)
-                    .context("loading page")?;
-                result.extend(select_without_index(db_file, page)?);
-            }
-        }
-        _ => bail!("unhandled page type"),
+    #[test]
+    fn json_lines_mode() {
+        assert_eq!(
+            run_session(&[
+                ".mode jsonlines",
+                "SELECT id, name FROM apples WHERE id < 3"
+            ]),
+            "{\"id\":1,\"name\":\"Granny Smith\"}\n{\"id\":2,\"name\":\"Fuji\"}\n"
+        );
     }
 
-    Ok(result)
-}
+    #[test]
+    fn column_mode() {
+        assert_eq!(
+            run_session(&[".mode column", "SELECT id, name, color FROM apples"]),
+            "\
+id  name              color
+--  ----------------  -----------
+1   Granny Smith      Light Green
+2   Fuji              Red
+3   Honeycrisp        Blush Red
+4   Golden Delicious  Yellow
+"
+        );
+    }
 
-fn select_with_index(
-    db_file: &mut DBFile,
-    page: BTreePage,
-    row_ids: &[u64],
-) -> Result<Vec<Vec<SerialValue>>> {
-    let mut results = vec![];
-    match page.page_type {
-        PageType::InteriorTable => {
-            let cells = page
-                .read_interior_cells()
-                .context("reading interior cells")?;
-
-            let mut right_ids = row_ids;
-
-            for (ind, interior_cell) in cells.iter().enumerate() {
-                let InteriorCell::Table(cell) = interior_cell else {
-                    bail!("invalid cell type - expected interior table cell")
-                };
-
-                let pp = right_ids.partition_point(|&id| id <= cell.row_id);
-                let left_ids = &right_ids[..pp];
-                right_ids = &right_ids[pp..];
-
-                if !left_ids.is_empty() {
-                    // The left page of this BTree item or its child pages should contain the IDs in
-                    // left_ids. Load that page then add its select results to the result set.
-                    let next_page = db_file
-                        .load_page_at(cell.left_child_page as usize)
-                        .context("loading next index page")?;
-
-                    results.extend(
-                        select_with_index(db_file, next_page, left_ids)
-                            .context("loading results from next index page")?,
-                    );
-                }
+    #[test]
+    fn separator() {
+        let query = "SELECT id, name FROM apples WHERE color = 'Red'";
+        assert_eq!(
+            run_session(&[
+                ".separator ,",
+                query,
+                ".separator \\t",
+                query,
+                ".separator ::",
+                query
+            ]),
+            "2,Fuji\n2\tFuji\n2::Fuji\n"
+        );
+    }
 
-                if right_ids.is_empty() {
-                    // No more rows to find in  this page
-                    break;
-                }
+    #[test]
+    fn order_by() {
+        assert_eq!(
+            run_session(&["SELECT name FROM apples ORDER BY color DESC, id"]),
+            "Golden Delicious\nFuji\nGranny Smith\nHoneycrisp\n"
+        );
+    }
 
-                if let Some(right_page) = page.right_most_pointer {
-                    if ind == cells.len() - 1 {
-                        // There might be additional results in the right page pointer
-                        let right_page = db_file
-                            .load_page_at(right_page as usize)
-                            .context("loading right page")?;
-
-                        results.extend(
-                            select_with_index(db_file, right_page, right_ids)
-                                .context("searching in right index page")?,
-                        );
-                    }
-                }
-            }
-        }
-        PageType::LeafTable => {
-            let mut cells = page
-                .read_cells()
-                .context("reading cells from leaf table page")?
-                .into_iter();
-
-            for &id in row_ids {
-                results.push(
-                    cells
-                        .by_ref()
-                        .find(|c| match c[0].as_rowid() {
-                            Some(rowid) => rowid == id,
-                            None => unreachable!(),
-                        })
-                        .context("must have a value")?,
-                );
-            }
+    #[test]
+    fn join() {
+        assert_eq!(
+            run_session(&[
+                "SELECT apples.name, oranges.name FROM apples JOIN oranges ON apples.id = oranges.id \
+                 WHERE color = 'Red'",
+                "SELECT count(*) FROM apples, oranges",
+            ]),
+            "Fuji|Tangelo\n24\n"
+        );
+        assert_eq!(
+            run_session(&["SELECT apples.name FROM apples WHERE color = 'Red'"]),
+            "Fuji\n"
+        );
+    }
+
+    #[test]
+    fn ambiguous_join_column() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let err = Cli::default()
+            .run_command(
+                &mut db_file,
+                "SELECT name FROM apples JOIN oranges ON apples.id = oranges.id",
+                &mut vec![],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ambiguous column name: name (found in tables apples, oranges)"
+        );
+    }
+
+    #[test]
+    fn unknown_column() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for query in [
+            "SELECT id, bogus FROM apples",
+            "SELECT id FROM apples WHERE bogus = 'x'",
+        ] {
+            let err = Cli::default()
+                .run_command(&mut db_file, query, &mut vec![])
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "no such column: bogus (valid columns are: id, name, color)"
+            );
         }
-        _ => unreachable!(),
     }
-    Ok(results)
-}
 
-// Searches an index starting from the given page and returns the rowids for any values matching the
-// query.
-fn search_index(db_file: &mut DBFile, page: BTreePage, query: &str) -> Result<Vec<u64>> {
-    match page.page_type {
-        PageType::InteriorIndex => {
-            let cells = page
-                .read_interior_cells()
-                .context("reading interior cells")?;
-
-            let mut results = vec![];
-            for (ind, interior_cell) in cells.iter().enumerate() {
-                let InteriorCell::Index(cell) = interior_cell else {
-                    bail!("invalid cell type")
-                };
-
-                // TODO: Handle checking types properly
-                let cell_content = &cell.columns[0].to_string();
-
-                let cell_content = cell_content.as_str();
-                let cell_cmp = cell_content.cmp(query);
-
-                if cell_cmp == Ordering::Greater || cell_cmp == Ordering::Equal {
-                    // The left page of this BTree item _might_ contain more matching entries so
-                    // load that page and add any rowids it produces to the result set.
-                    let next_page = db_file
-                        .load_page_at(cell.left_child_page as usize)
-                        .context("loading next index page")?;
-
-                    results.extend(
-                        search_index(db_file, next_page, query)
-                            .context("loading results from next index page")?,
-                    );
-                }
+    #[test]
+    fn case_insensitive_columns() {
+        assert_eq!(
+            run_session(&["SELECT NAME, Apples.Color FROM apples WHERE COLOR = 'Red' ORDER BY Id"]),
+            "Fuji|Red\n"
+        );
+        assert_eq!(
+            run_session(&[".mode json", "SELECT NAME FROM apples WHERE color = 'Red'"]),
+            "[{\"NAME\":\"Fuji\"}]\n"
+        );
+    }
 
-                if cell_cmp == Ordering::Greater {
-                    // The following BTree items _cannot_ contain the search query - we can bail out
-                    // from the loop now
-                    break;
-                }
+    #[test]
+    fn limit_and_offset() {
+        assert_eq!(
+            run_session(&[
+                "SELECT name FROM oranges ORDER BY name LIMIT 2 OFFSET 1",
+                "SELECT name FROM oranges ORDER BY name LIMIT 1, 2",
+            ]),
+            "Mandarin\nNavel Orange\n".repeat(2)
+        );
+        assert_eq!(run_session(&["SELECT name FROM oranges LIMIT 0"]), "");
+    }
+
+    #[test]
+    fn select_star() {
+        assert_eq!(
+            run_session(&[
+                "SELECT * FROM sqlite_sequence",
+                ".mode json",
+                "SELECT * FROM apples JOIN oranges ON apples.id = oranges.id LIMIT 1",
+            ]),
+            "apples|4\noranges|6\n\
+             [{\"id\":1,\"name\":\"Granny Smith\",\"color\":\"Light Green\",\"id\":1,\
+             \"name\":\"Mandarin\",\"description\":\"great for snacking\"}]\n"
+        );
+    }
+
+    #[test]
+    fn select_from_schema_table() {
+        let expected = "table|apples\ntable|sqlite_sequence\ntable|oranges\n";
+        assert_eq!(
+            run_session(&["SELECT type, name FROM sqlite_schema"]),
+            expected
+        );
+        assert_eq!(
+            run_session(&["SELECT type, name FROM SQLITE_MASTER"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            run_session(&[".tables", ".tables --plain"]),
+            "apples   oranges\napples oranges\n"
+        );
+    }
+
+    #[test]
+    fn indexes() {
+        let customer_indexes = "\
+idx_customers_name on customers:
+	CREATE INDEX idx_customers_name ON customers (name)
+";
+        let order_indexes = "\
+idx_orders_customer on orders:
+	CREATE INDEX idx_orders_customer ON orders (customer_id)
+idx_orders_total on orders:
+	CREATE INDEX idx_orders_total ON orders (total)
+";
+        assert_eq!(
+            run_session_on("fixtures/shop.db", &[".indexes"]),
+            format!("{}{}", customer_indexes, order_indexes)
+        );
+        assert_eq!(
+            run_session_on("fixtures/shop.db", &[".indexes orders"]),
+            order_indexes
+        );
+        assert_eq!(
+            run_session_on("fixtures/shop.db", &[".indexes CUSTOMERS"]),
+            customer_indexes
+        );
+        assert_eq!(
+            run_session_on("fixtures/shop.db", &[".indexes missing"]),
+            ""
+        );
+    }
 
-                if cell_cmp == Ordering::Equal {
-                    // This cell matches the query - add the rowid to the result set.
-                    results.push(cell.rowid);
+    #[test]
+    fn schema() {
+        let schema = run_session(&[".schema"]);
+        assert!(schema.starts_with("CREATE TABLE apples\n("));
+        assert!(schema.contains("CREATE TABLE sqlite_sequence(name,seq);\n"));
+        assert!(schema.ends_with(";\n"));
+    }
+
+    #[test]
+    fn pageinfo() {
+        assert_eq!(
+            run_session(&[".pageinfo 2"]),
+            "\
+page type: leaf table
+number of cells: 4
+cell content start: 4001
+free space: 3985
+cell pointers: 4067 4054 4029 4001
+"
+        );
+
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (page, message) in [
+            ("0", "page 0 is out of range, the database has 4 pages"),
+            ("5", "page 5 is out of range, the database has 4 pages"),
+            ("two", "invalid page number 'two'"),
+        ] {
+            let err = Cli::default()
+                .run_command(&mut db_file, &format!(".pageinfo {}", page), &mut vec![])
+                .unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn custom_formatter() {
+        type Results = Vec<(Vec<String>, Vec<Vec<SerialValue>>)>;
+
+        /// Keeps every result set it's given instead of writing it
+        struct Recorder(Rc<RefCell<Results>>, Vec<Vec<SerialValue>>);
+
+        impl OutputFormatter for Recorder {
+            fn write_row(
+                &mut self,
+                _out: &mut dyn Write,
+                columns: &[String],
+                row: &[SerialValue],
+            ) -> Result<()> {
+                if self.1.is_empty() {
+                    self.0.borrow_mut().push((columns.to_vec(), vec![]));
                 }
+                self.1.push(row.to_vec());
+                Ok(())
+            }
 
-                if let Some(right_page) = page.right_most_pointer {
-                    if ind == cells.len() - 1
-                        && (cell_cmp == Ordering::Equal || cell_cmp == Ordering::Less)
-                    {
-                        // There might be additional results in the right page pointer
-                        let right_page = db_file
-                            .load_page_at(right_page as usize)
-                            .context("loading right page")?;
-
-                        results.extend(
-                            search_index(db_file, right_page, query)
-                                .context("searching in right index page")?,
-                        )
-                    }
+            fn finish(&mut self, _out: &mut dyn Write) -> Result<()> {
+                if let Some(set) = self.0.borrow_mut().last_mut() {
+                    set.1.append(&mut self.1);
                 }
+                Ok(())
             }
-            Ok(results)
-        }
-        PageType::LeafIndex => {
-            // TODO: It might make sense to do a binary search over the cells on leaf pages
-            // These cells are laid out as [Serial(<indexed column>)..., Int?(<rowid>)]
-            Ok(page
-                .read_cells()?
-                .into_iter()
-                .filter(|c| c[0].to_string() == query)
-                .map(|c| c[1].as_rowid().unwrap_or(0u64))
-                .collect())
         }
-        _ => unreachable!(),
+
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let results = Rc::new(RefCell::new(vec![]));
+        let mut cli = Cli {
+            formatter: Box::new(Recorder(Rc::clone(&results), vec![])),
+            ..Cli::default()
+        };
+        let mut out = vec![];
+        cli.run_command(
+            &mut db_file,
+            "SELECT id, name AS apple FROM apples WHERE id < 3",
+            &mut out,
+        )
+        .unwrap();
+        assert!(out.is_empty());
+        assert_eq!(
+            *results.borrow(),
+            [(
+                vec![String::from("id"), String::from("apple")],
+                vec![
+                    vec![
+                        SerialValue::Int64(1),
+                        SerialValue::Text(String::from("Granny Smith"))
+                    ],
+                    vec![
+                        SerialValue::Int64(2),
+                        SerialValue::Text(String::from("Fuji"))
+                    ],
+                ]
+            )]
+        );
+
+        // Changing the mode replaces the formatter
+        cli.run_command(&mut db_file, ".mode csv", &mut out)
+            .unwrap();
+        cli.run_command(&mut db_file, "SELECT id FROM apples WHERE id = 1", &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n");
+        assert_eq!(results.borrow().len(), 1);
     }
-}
 
-fn print_row(row: Vec<SerialValue>, indices: &[usize]) {
-    println!("{}", indices.iter().map(|ind| &row[*ind]).join("|"))
+    #[test]
+    fn unknown_mode() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut cli = Cli::default();
+        assert!(cli
+            .run_command(&mut db_file, ".mode html", &mut vec![])
+            .is_err());
+        assert_eq!(cli.mode, OutputMode::List);
+    }
 }
@@ -0,0 +1,418 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::serial_value::SerialValue;
+
+/// The formats query results can be printed in, selected with the `.mode` command.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputMode {
+    /// Values separated by the `.separator` (`|` by default), one row per line
+    #[default]
+    List,
+    /// Comma separated values, quoted where necessary
+    Csv,
+    /// A JSON array with one object per row, keyed by column name
+    Json,
+    /// One JSON object per line, keyed by column name, for streaming into other tools
+    JsonLines,
+    /// Tab separated values, one row per line
+    Tabs,
+    /// Left-aligned columns padded to the widest value, with a header row
+    Column,
+}
+
+impl OutputMode {
+    pub fn from(mode: &str) -> Result<Self> {
+        match mode.to_ascii_lowercase().as_str() {
+            "list" => Ok(Self::List),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "jsonlines" | "ndjson" => Ok(Self::JsonLines),
+            "tabs" => Ok(Self::Tabs),
+            "column" => Ok(Self::Column),
+            _ => bail!(
+                "unknown output mode '{}', expected one of: list, csv, json, jsonlines, tabs, column",
+                mode
+            ),
+        }
+    }
+
+    /// The formatter that writes results in this mode. `separator` is placed between values in
+    /// list mode.
+    pub fn formatter(&self, separator: &str) -> Box<dyn OutputFormatter> {
+        match self {
+            Self::List => Box::new(ListFormatter {
+                separator: separator.to_string(),
+            }),
+            Self::Tabs => Box::new(ListFormatter {
+                separator: String::from("\t"),
+            }),
+            Self::Csv => Box::new(CsvFormatter),
+            Self::Json => Box::<JsonFormatter>::default(),
+            Self::JsonLines => Box::new(JsonLinesFormatter),
+            Self::Column => Box::<ColumnFormatter>::default(),
+        }
+    }
+}
+
+/// Writes query results in one format. Rows are passed in one at a time as the query produces
+/// them, and `finish` is called once a result set is complete.
+pub trait OutputFormatter {
+    /// Writes one row of a result set. `columns` holds the name of each output column, which are
+    /// the same for every row of a set, and the row has one value per column.
+    fn write_row(
+        &mut self,
+        out: &mut dyn Write,
+        columns: &[String],
+        row: &[SerialValue],
+    ) -> Result<()>;
+
+    /// Writes whatever follows the last row of a result set, ready to start the next. Formats that
+    /// write each row in full have nothing to do.
+    fn finish(&mut self, _out: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes a full result set.
+    fn write_rows(
+        &mut self,
+        out: &mut dyn Write,
+        columns: &[String],
+        rows: &[Vec<SerialValue>],
+    ) -> Result<()> {
+        for row in rows {
+            self.write_row(out, columns, row)?;
+        }
+        self.finish(out)
+    }
+}
+
+/// Values separated by `separator`, one row per line, without a header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListFormatter {
+    pub separator: String,
+}
+
+impl OutputFormatter for ListFormatter {
+    fn write_row(
+        &mut self,
+        out: &mut dyn Write,
+        _columns: &[String],
+        row: &[SerialValue],
+    ) -> Result<()> {
+        writeln!(out, "{}", row.iter().join(&self.separator))?;
+        Ok(())
+    }
+}
+
+/// Comma separated values, quoted where necessary. NULL is written as an empty field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsvFormatter;
+
+impl OutputFormatter for CsvFormatter {
+    fn write_row(
+        &mut self,
+        out: &mut dyn Write,
+        _columns: &[String],
+        row: &[SerialValue],
+    ) -> Result<()> {
+        writeln!(out, "{}", row.iter().map(csv_field).join(","))?;
+        Ok(())
+    }
+}
+
+/// A JSON array with one object per row, keyed by column name. Nothing is written for an empty
+/// result, as sqlite3 does.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct JsonFormatter {
+    /// Whether the current result set has had a row written, which opened the array
+    started: bool,
+}
+
+impl OutputFormatter for JsonFormatter {
+    fn write_row(
+        &mut self,
+        out: &mut dyn Write,
+        columns: &[String],
+        row: &[SerialValue],
+    ) -> Result<()> {
+        let separator = if self.started { ",\n" } else { "[" };
+        write!(out, "{}{}", separator, json_object(columns, row))?;
+        self.started = true;
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<()> {
+        if std::mem::take(&mut self.started) {
+            writeln!(out, "]")?;
+        }
+        Ok(())
+    }
+}
+
+/// One JSON object per line, keyed by column name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JsonLinesFormatter;
+
+impl OutputFormatter for JsonLinesFormatter {
+    fn write_row(
+        &mut self,
+        out: &mut dyn Write,
+        columns: &[String],
+        row: &[SerialValue],
+    ) -> Result<()> {
+        // Each line is complete on its own, so it's passed on as soon as it's written
+        writeln!(out, "{}", json_object(columns, row))?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Left-aligned columns padded to the widest value, with a header row. The widths depend on every
+/// row, so rows are held until the result set is finished.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColumnFormatter {
+    columns: Vec<String>,
+    rows: Vec<Vec<SerialValue>>,
+}
+
+impl OutputFormatter for ColumnFormatter {
+    fn write_row(
+        &mut self,
+        _out: &mut dyn Write,
+        columns: &[String],
+        row: &[SerialValue],
+    ) -> Result<()> {
+        if self.rows.is_empty() {
+            self.columns = columns.to_vec();
+        }
+        self.rows.push(row.to_vec());
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<()> {
+        let rows = std::mem::take(&mut self.rows);
+        write_columns(out, &self.columns, &rows)
+    }
+}
+
+/// Writes the header, a dashed separator and each row, padding every column to the width of its
+/// widest value. Widths are counted in chars so multi-byte UTF-8 text lines up. Blobs are shown
+/// by their length rather than their contents.
+fn write_columns(out: &mut dyn Write, columns: &[String], rows: &[Vec<SerialValue>]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(|value| format!("{:#}", value)).collect_vec())
+        .collect_vec();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(ind, column)| {
+            rows.iter()
+                .filter_map(|row| row.get(ind))
+                .chain([column])
+                .map(|value| value.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect_vec();
+
+    let separator = widths.iter().map(|&width| "-".repeat(width)).collect_vec();
+    for line in [columns.to_vec(), separator].iter().chain(&rows) {
+        let line = line
+            .iter()
+            .zip(&widths)
+            .map(|(value, &width)| format!("{:<width$}", value, width = width))
+            .join("  ");
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// The terminal width sqlite3 assumes when laying out `.tables`.
+pub const LINE_WIDTH: usize = 80;
+
+/// Lays names out in as many columns as fit in `line_width`, filling each column top to bottom
+/// before moving on to the next, as sqlite3 does for `.tables`. Columns are as wide as the longest
+/// name and two spaces apart. A name wider than the line gets a line of its own.
+pub fn write_name_grid(out: &mut impl Write, names: &[String], line_width: usize) -> Result<()> {
+    let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+    let num_columns = (line_width / (width + 2)).max(1);
+    let num_rows = names.len().div_ceil(num_columns);
+    for row in 0..num_rows {
+        let line = names
+            .iter()
+            .skip(row)
+            .step_by(num_rows)
+            .map(|name| format!("{:<width$}", name, width = width))
+            .join("  ");
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &SerialValue) -> String {
+    let value = match value {
+        SerialValue::Null => String::new(),
+        v => v.to_string(),
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn json_object(columns: &[String], row: &[SerialValue]) -> String {
+    let fields = columns
+        .iter()
+        .zip(row)
+        .map(|(column, value)| format!("{}:{}", json_string(column), json_value(value)))
+        .join(",");
+    format!("{{{}}}", fields)
+}
+
+fn json_value(value: &SerialValue) -> String {
+    match value {
+        SerialValue::Null => String::from("null"),
+        SerialValue::Float64(f) if !f.is_finite() => String::from("null"),
+        SerialValue::Text(t) => json_string(t),
+        SerialValue::Blob(b) => json_string(&b.iter().map(|byte| format!("{:02X}", byte)).join("")),
+        v => v.to_string(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(mode: OutputMode, rows: &[Vec<SerialValue>]) -> String {
+        let columns = [String::from("id"), String::from("name")];
+        let mut out = vec![];
+        mode.formatter("|")
+            .write_rows(&mut out, &columns, rows)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn rows() -> Vec<Vec<SerialValue>> {
+        vec![
+            vec![
+                SerialValue::Int8(1),
+                SerialValue::Text(String::from("a, \"b\"")),
+            ],
+            vec![SerialValue::Int8(2), SerialValue::Null],
+        ]
+    }
+
+    #[test]
+    fn name_grid() {
+        let names = (1..=7).map(|i| format!("table_{}", i)).collect_vec();
+        let mut out = vec![];
+        write_name_grid(&mut out, &names, 30).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "table_1  table_4  table_7\ntable_2  table_5\ntable_3  table_6\n"
+        );
+
+        let names = [String::from("a"), "b".repeat(40)];
+        let mut out = vec![];
+        write_name_grid(&mut out, &names, 30).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("a\n{}\n", "b".repeat(40))
+        );
+
+        let mut out = vec![];
+        write_name_grid(&mut out, &[], 30).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn list_and_tabs() {
+        assert_eq!(render(OutputMode::List, &rows()), "1|a, \"b\"\n2|(null)\n");
+        assert_eq!(
+            render(OutputMode::Tabs, &rows()),
+            "1\ta, \"b\"\n2\t(null)\n"
+        );
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(render(OutputMode::Csv, &rows()), "1,\"a, \"\"b\"\"\"\n2,\n");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            render(OutputMode::Json, &rows()),
+            "[{\"id\":1,\"name\":\"a, \\\"b\\\"\"},\n{\"id\":2,\"name\":null}]\n"
+        );
+        assert_eq!(render(OutputMode::Json, &[]), "");
+    }
+
+    #[test]
+    fn json_lines() {
+        assert_eq!(
+            render(OutputMode::JsonLines, &rows()),
+            "{\"id\":1,\"name\":\"a, \\\"b\\\"\"}\n{\"id\":2,\"name\":null}\n"
+        );
+        assert_eq!(render(OutputMode::JsonLines, &[]), "");
+    }
+
+    #[test]
+    fn column() {
+        let rows = vec![
+            vec![
+                SerialValue::Int8(1),
+                SerialValue::Text(String::from("Äpfel")),
+            ],
+            vec![
+                SerialValue::Int16(1000),
+                SerialValue::Text(String::from("b")),
+            ],
+        ];
+        assert_eq!(
+            render(OutputMode::Column, &rows),
+            "id    name\n----  -----\n1     Äpfel\n1000  b\n"
+        );
+        assert_eq!(render(OutputMode::Column, &[]), "");
+        assert_eq!(
+            render(
+                OutputMode::Column,
+                &[vec![SerialValue::Int8(1), SerialValue::Blob(vec![0; 20])]]
+            ),
+            "id  name\n--  ---------------\n1   <blob 20 bytes>\n"
+        );
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!(OutputMode::from("CSV").unwrap(), OutputMode::Csv);
+        assert!(OutputMode::from("html").is_err());
+    }
+}
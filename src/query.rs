@@ -0,0 +1,2476 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+
+use crate::btree_page::{BTreePage, InteriorCell, PageType};
+use crate::db_file::{mark_visited, DBFile, IndexLookup};
+use crate::eval::{collation_of, count_value, evaluate, evaluate_group, is_true, no_such_column};
+use crate::serial_value::{Affinity, Collation, SerialValue};
+use crate::sql::{
+    rowid_column, BinaryOperator, ColumnDefinition, Expr, Join, OrderingTerm, ResultColumn,
+    SelectStatement, ROWID_NAMES,
+};
+
+/// A single row of values, either as stored in a table or as produced by a query.
+pub type Row = Vec<SerialValue>;
+
+/// Describes one column of a query's output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMeta {
+    /// The alias given with `AS`, or otherwise the selected expression as written
+    pub name: String,
+    /// The declared type of the table column this output comes from. `None` for computed
+    /// expressions and for columns declared without a type.
+    pub declared_type: Option<String>,
+}
+
+/// The output of a query: metadata for each column and the resulting rows, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<ColumnMeta>,
+    pub rows: Vec<Row>,
+}
+
+/// Runs a select statement against the database.
+pub fn execute_select(
+    db_file: &mut DBFile,
+    select_statement: &SelectStatement,
+) -> Result<QueryResult> {
+    let mut rows = vec![];
+    let columns = for_each_result_row(db_file, select_statement, |_, row| {
+        rows.push(row);
+        Ok(())
+    })?;
+    Ok(QueryResult { columns, rows })
+}
+
+/// Runs a select statement, calling `f` with each result row as it's produced along with the
+/// description of the result's columns, which are also returned. The rows of a query on one table
+/// that isn't sorted, grouped or made distinct are produced as the table is read. Otherwise every
+/// row has to be found first, but they're still passed on one at a time.
+pub fn for_each_result_row(
+    db_file: &mut DBFile,
+    select_statement: &SelectStatement,
+    mut f: impl FnMut(&[ColumnMeta], Row) -> Result<()>,
+) -> Result<Vec<ColumnMeta>> {
+    let Some(table_name) = &select_statement.from else {
+        if select_statement.select.iter().any(|c| c.expr == Expr::Star) {
+            bail!("no tables specified");
+        }
+        // Constant expressions are evaluated once against an empty row
+        let columns = column_meta(&select_statement.select, &[]);
+        let row = evaluate_row(
+            &[],
+            select_statement.select.iter().map(|c| &c.expr),
+            &HashMap::new(),
+        )?;
+        f(&columns, row)?;
+        return Ok(columns);
+    };
+
+    let resolved = resolve_select(db_file, select_statement)?;
+    let tables = resolved.tables();
+    let select_statement = &resolved.statement;
+    let column_map = &resolved.column_map;
+    let columns = column_meta(&select_statement.select, &tables);
+    let plan = plan_select(db_file, &resolved)?;
+    let offset = select_statement.offset.unwrap_or(0) as usize;
+    let limit = select_statement.limit.map_or(usize::MAX, |l| l as usize);
+    let result_row = |row: &[SerialValue]| {
+        evaluate_row(
+            row,
+            select_statement.select.iter().map(|c| &c.expr),
+            column_map,
+        )
+    };
+
+    let count = match &plan.access {
+        AccessPath::RowCount => Some(db_file.row_count(table_name)?),
+        AccessPath::IndexCount { index, key } => {
+            Some(index_row_ids(db_file, index, key)?.len() as u64)
+        }
+        _ => None,
+    };
+    if let Some(count) = count {
+        if offset == 0 && limit > 0 {
+            f(&columns, vec![count_value(count)?])?;
+        }
+        return Ok(columns);
+    }
+
+    if select_statement.joins.is_empty()
+        && !select_statement.is_aggregate()
+        && !select_statement.distinct
+        && select_statement.order_by.is_empty()
+    {
+        // Each row can be finished as soon as it's read
+        let mut skipped = 0;
+        let mut produced = 0;
+        for_each_selected_row(db_file, table_name, tables[0].1, &plan.access, &mut |row| {
+            if produced == limit {
+                return Ok(());
+            }
+            if let Some(condition) = &select_statement.where_clause {
+                if !row_matches(&row, column_map, condition).context("evaluating WHERE")? {
+                    return Ok(());
+                }
+            }
+            if skipped < offset {
+                skipped += 1;
+                return Ok(());
+            }
+            produced += 1;
+            f(&columns, result_row(&row)?)
+        })?;
+        return Ok(columns);
+    }
+
+    let rows = if select_statement.joins.is_empty() {
+        let mut rows = vec![];
+        for_each_selected_row(db_file, table_name, tables[0].1, &plan.access, &mut |row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        rows
+    } else {
+        join_rows(db_file, select_statement, column_map)?
+    };
+    let mut rows = filter_rows(rows, column_map, select_statement)?;
+
+    if select_statement.is_aggregate() || select_statement.distinct {
+        // Every result row is needed up front here, either to compute the aggregates or to find
+        // the duplicates. Duplicates are removed before sorting and applying the limit.
+        let mut results = vec![];
+        if select_statement.is_aggregate() {
+            for group in group_rows(rows, &select_statement.group_by, column_map)? {
+                if let Some(having) = &select_statement.having {
+                    let values = evaluate_group_row(&group, [having], column_map)
+                        .context("evaluating HAVING")?;
+                    if is_true(&values[0]) != Some(true) {
+                        continue;
+                    }
+                }
+                let row = evaluate_group_row(
+                    &group,
+                    select_statement.select.iter().map(|c| &c.expr),
+                    column_map,
+                )?;
+                let key = evaluate_group_row(
+                    &group,
+                    select_statement.order_by.iter().map(|term| &term.expr),
+                    column_map,
+                )
+                .context("evaluating ORDER BY terms")?;
+                results.push((key, row));
+            }
+        } else {
+            for row in &rows {
+                let key = evaluate_row(
+                    row,
+                    select_statement.order_by.iter().map(|term| &term.expr),
+                    column_map,
+                )
+                .context("evaluating ORDER BY terms")?;
+                results.push((key, result_row(row)?));
+            }
+        }
+        if select_statement.distinct {
+            let mut seen = HashSet::new();
+            results.retain(|(_, row)| seen.insert(GroupKey(row.clone())));
+        }
+        results.sort_by(|(a, _), (b, _)| compare_sort_keys(a, b, &select_statement.order_by));
+        for (_, row) in results.into_iter().skip(offset).take(limit) {
+            f(&columns, row)?;
+        }
+        return Ok(columns);
+    }
+
+    if !select_statement.order_by.is_empty() {
+        sort_rows(&mut rows, &select_statement.order_by, column_map)?;
+    }
+    for row in rows.iter().skip(offset).take(limit) {
+        f(&columns, result_row(row)?)?;
+    }
+    Ok(columns)
+}
+
+/// Describes how a select statement would be run, as returned by `DBFile::explain_query_plan`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    /// The table in FROM, or `None` for a select without one
+    pub table: Option<String>,
+    /// How the rows of `table` are found
+    pub access: AccessPath,
+    /// The terms of the WHERE clause that the access path doesn't answer, which are checked
+    /// against every row it finds. They are written with the columns' affinities and collations
+    /// made explicit.
+    pub residual_filters: Vec<Expr>,
+    /// The joined tables, in order. Each is scanned in full for every row it's joined to.
+    pub joins: Vec<String>,
+}
+
+impl QueryPlan {
+    /// One line for each table read, in the format of SQLite's `EXPLAIN QUERY PLAN`.
+    pub fn details(&self) -> Vec<String> {
+        let Some(table) = &self.table else {
+            return vec![String::from("SCAN CONSTANT ROW")];
+        };
+        let first = match &self.access {
+            AccessPath::NoTable | AccessPath::RowCount | AccessPath::FullScan => {
+                format!("SCAN {}", table)
+            }
+            AccessPath::RowidRange(rowids) => {
+                let constraint = if rowids.start() == rowids.end() {
+                    String::from("rowid=?")
+                } else {
+                    [
+                        (*rowids.start() != i64::MIN).then_some("rowid>?"),
+                        (*rowids.end() != i64::MAX).then_some("rowid<?"),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .join(" AND ")
+                };
+                format!(
+                    "SEARCH {} USING INTEGER PRIMARY KEY ({})",
+                    table, constraint
+                )
+            }
+            AccessPath::Index { index, .. } | AccessPath::IndexCount { index, .. } => format!(
+                "SEARCH {} USING {}INDEX {} ({})",
+                table,
+                if matches!(self.access, AccessPath::IndexCount { .. }) {
+                    "COVERING "
+                } else {
+                    ""
+                },
+                index.name,
+                index
+                    .columns
+                    .iter()
+                    .map(|c| format!("{}=?", c.name))
+                    .join(" AND ")
+            ),
+        };
+        std::iter::once(first)
+            .chain(self.joins.iter().map(|join| format!("SCAN {}", join)))
+            .collect()
+    }
+}
+
+/// The way rows are read from a table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessPath {
+    /// There is no table, so the result columns are evaluated once
+    NoTable,
+    /// Only the number of rows is needed, which is read from the table b-tree's page headers
+    RowCount,
+    /// Every row of the table is read
+    FullScan,
+    /// Only the table b-tree pages that can hold rowids in the range are read
+    RowidRange(RangeInclusive<i64>),
+    /// The rowids are found by searching an index for entries whose leading columns equal `key`,
+    /// and the rows are then looked up by rowid
+    Index {
+        index: IndexLookup,
+        key: Vec<SerialValue>,
+    },
+    /// Only the number of rows is needed, which is the number of entries found by searching an
+    /// index as for `Index`. The table itself isn't read.
+    IndexCount {
+        index: IndexLookup,
+        key: Vec<SerialValue>,
+    },
+}
+
+/// Counts of the work done to run a query, as returned by `DBFile::execute_with_stats`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryStats {
+    /// The pages read from the file. Pages served from the cache aren't counted.
+    pub pages_read: usize,
+    /// The rows read from tables before the WHERE clause was checked
+    pub rows_scanned: usize,
+    pub elapsed: Duration,
+}
+
+/// Works out how a select statement would be run without reading any rows.
+pub fn explain_select(
+    db_file: &mut DBFile,
+    select_statement: &SelectStatement,
+) -> Result<QueryPlan> {
+    if select_statement.from.is_none() {
+        if select_statement.select.iter().any(|c| c.expr == Expr::Star) {
+            bail!("no tables specified");
+        }
+        return Ok(QueryPlan {
+            table: None,
+            access: AccessPath::NoTable,
+            residual_filters: vec![],
+            joins: vec![],
+        });
+    }
+    let resolved = resolve_select(db_file, select_statement)?;
+    plan_select(db_file, &resolved)
+}
+
+/// A select statement with a FROM clause, checked against the tables it reads. Its stars are
+/// expanded, ORDER BY terms given by position or alias are replaced with the expressions they
+/// refer to, and the columns' affinities and collations are made explicit.
+struct ResolvedSelect {
+    /// The name and columns of each table, the one in FROM first and then each join's
+    tables: Vec<(String, Vec<ColumnDefinition>)>,
+    statement: SelectStatement,
+    column_map: HashMap<String, usize>,
+}
+
+impl ResolvedSelect {
+    fn tables(&self) -> Vec<(&str, &[ColumnDefinition])> {
+        self.tables
+            .iter()
+            .map(|(name, columns)| (name.as_str(), columns.as_slice()))
+            .collect()
+    }
+}
+
+fn resolve_select(
+    db_file: &mut DBFile,
+    select_statement: &SelectStatement,
+) -> Result<ResolvedSelect> {
+    let mut tables = vec![];
+    for name in select_statement
+        .from
+        .iter()
+        .chain(select_statement.joins.iter().map(|j| &j.table))
+    {
+        let schema = db_file
+            .schema_for_table(name)
+            .context("loading table schema")?;
+        let columns = schema.columns().context("retrieving table columns")?;
+        tables.push((schema.table_name, columns));
+    }
+
+    // A single table is treated as a one table join so its columns can be qualified with the
+    // table name
+    let table_columns = tables
+        .iter()
+        .map(|(name, columns)| (name.as_str(), columns.as_slice()))
+        .collect_vec();
+    let select = expand_stars(&select_statement.select, &table_columns);
+    let select_statement = &SelectStatement {
+        order_by: resolve_order_by(
+            &select_statement.order_by,
+            &select_statement.select,
+            &select,
+        )?,
+        select,
+        ..select_statement.clone()
+    };
+    let (column_map, ambiguous) = joined_column_map(&table_columns);
+    let select_statement = &SelectStatement {
+        where_clause: select_statement
+            .where_clause
+            .as_ref()
+            .map(|condition| {
+                resolve_where_aliases(condition, &select_statement.select, &|name| {
+                    column_map.contains_key(name) || ambiguous.contains_key(name)
+                })
+            })
+            .transpose()?,
+        ..select_statement.clone()
+    };
+    check_columns(select_statement, &column_map, &ambiguous)?;
+    let collations = column_collations(&table_columns);
+    let affinities = column_affinities(&table_columns);
+    let with_column_rules = |expr: &Expr| {
+        let expr = apply_column_affinities(expr, &affinities);
+        apply_column_collations(&expr, &collations, false)
+    };
+    let statement = SelectStatement {
+        joins: select_statement
+            .joins
+            .iter()
+            .map(|join| Join {
+                on: join.on.as_ref().map(with_column_rules),
+                ..join.clone()
+            })
+            .collect(),
+        where_clause: select_statement
+            .where_clause
+            .as_ref()
+            .map(with_column_rules),
+        having: select_statement.having.as_ref().map(with_column_rules),
+        order_by: select_statement
+            .order_by
+            .iter()
+            .map(|term| OrderingTerm {
+                expr: apply_column_collations(&term.expr, &collations, true),
+                ..term.clone()
+            })
+            .collect(),
+        ..select_statement.clone()
+    };
+
+    if statement.group_by.is_empty()
+        && statement.is_aggregate()
+        && statement.select.iter().any(|c| c.expr.has_bare_column())
+    {
+        bail!("aggregate and non-aggregate columns cannot be mixed without GROUP BY");
+    }
+
+    Ok(ResolvedSelect {
+        tables,
+        statement,
+        column_map,
+    })
+}
+
+fn plan_select(db_file: &mut DBFile, resolved: &ResolvedSelect) -> Result<QueryPlan> {
+    let select_statement = &resolved.statement;
+    let (table_name, columns) = &resolved.tables[0];
+    let terms = select_statement
+        .where_clause
+        .as_ref()
+        .map(conjuncts)
+        .unwrap_or_default();
+
+    let (access, residual_filters) = if select_statement.is_count_star()
+        && select_statement.joins.is_empty()
+        && select_statement.where_clause.is_none()
+        && select_statement.group_by.is_empty()
+        && select_statement.having.is_none()
+    {
+        // Without a filter the count can be read from the page headers
+        (AccessPath::RowCount, vec![])
+    } else if select_statement.joins.is_empty() {
+        match plan_access(db_file, table_name, columns, &terms)? {
+            // When the index answers the whole WHERE clause, its entries can be counted instead
+            (AccessPath::Index { index, key }, residual)
+                if residual.is_empty()
+                    && select_statement.is_count_star()
+                    && select_statement.group_by.is_empty()
+                    && select_statement.having.is_none() =>
+            {
+                (AccessPath::IndexCount { index, key }, residual)
+            }
+            planned => planned,
+        }
+    } else {
+        (AccessPath::FullScan, terms.into_iter().cloned().collect())
+    };
+
+    Ok(QueryPlan {
+        table: Some(table_name.clone()),
+        access,
+        residual_filters,
+        joins: resolved.tables[1..]
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect(),
+    })
+}
+
+/// Chooses how to read the rows of a table that must satisfy every one of `terms`, and returns
+/// the terms that the chosen path doesn't answer.
+fn plan_access(
+    db_file: &mut DBFile,
+    table_name: &str,
+    columns: &[ColumnDefinition],
+    terms: &[&Expr],
+) -> Result<(AccessPath, Vec<Expr>)> {
+    let residual = |answered: &dyn Fn(usize) -> bool| {
+        terms
+            .iter()
+            .enumerate()
+            .filter(|(ind, _)| !answered(*ind))
+            .map(|(_, term)| (*term).clone())
+            .collect_vec()
+    };
+
+    // Comparisons with the rowid narrow down which parts of the table b-tree need to be read
+    let is_rowid = |name: &str| is_rowid_column(columns, name);
+    let ranges = terms
+        .iter()
+        .map(|term| rowid_range(term, &is_rowid))
+        .collect_vec();
+    if ranges.iter().any(|range| *range != ALL_ROWIDS) {
+        let rowids = ranges.iter().fold(ALL_ROWIDS, |all, range| {
+            *all.start().max(range.start())..=*all.end().min(range.end())
+        });
+        let filters = residual(&|ind| ranges[ind] != ALL_ROWIDS);
+        return Ok((AccessPath::RowidRange(rowids), filters));
+    }
+
+    // Otherwise look for an index on the columns compared for equality, preferring one that
+    // covers more of them
+    let equalities = terms
+        .iter()
+        .enumerate()
+        .filter_map(|(ind, term)| {
+            equality_term(term).map(|(column, value, collation)| (ind, column, value, collation))
+        })
+        .collect_vec();
+    let term_columns = equalities
+        .iter()
+        .map(|&(_, column, _, collation)| (column, collation))
+        .collect_vec();
+    let Some(index) = db_file
+        .find_index(table_name, &term_columns)
+        .context("finding index page")?
+    else {
+        return Ok((AccessPath::FullScan, residual(&|_| false)));
+    };
+
+    // The index holds values as stored in the table, so the query values have to be converted
+    // the same way to be found
+    let mut key = vec![];
+    let mut answered = vec![];
+    for (index_column, &collation) in index.columns.iter().zip(&index.collations) {
+        let Some((ind, _, value, _)) = equalities.iter().find(|(_, column, _, compared)| {
+            column.eq_ignore_ascii_case(&index_column.name) && *compared == collation
+        }) else {
+            bail!("no value to look up {} by", index_column.name);
+        };
+        let affinity = columns
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(&index_column.name))
+            .map_or(Affinity::Blob, ColumnDefinition::affinity);
+        key.push(value.apply_affinity(affinity));
+        answered.push(*ind);
+    }
+    let filters = residual(&|ind| answered.contains(&ind));
+    Ok((AccessPath::Index { index, key }, filters))
+}
+
+/// Replaces each `*` result column with every column of every table, in order. The columns are
+/// qualified with their table so they can't be ambiguous in a join, but keep their bare names as
+/// the output name.
+fn expand_stars(
+    select: &[ResultColumn],
+    tables: &[(&str, &[ColumnDefinition])],
+) -> Vec<ResultColumn> {
+    select
+        .iter()
+        .flat_map(|result_column| match result_column.expr {
+            Expr::Star => tables
+                .iter()
+                .flat_map(|(table, columns)| {
+                    columns.iter().map(|column| ResultColumn {
+                        expr: Expr::QualifiedColumn {
+                            table: table.to_string(),
+                            column: column.name.clone(),
+                        },
+                        alias: Some(column.name.clone()),
+                    })
+                })
+                .collect(),
+            _ => vec![result_column.clone()],
+        })
+        .collect()
+}
+
+/// Replaces ORDER BY terms that refer to a result column, either by its position such as
+/// `ORDER BY 2` or by its alias, with that column's expression. Positions count the columns a `*`
+/// expands to, but only aliases given with `AS` are matched, so a name that isn't an alias still
+/// refers to a table column.
+fn resolve_order_by(
+    order_by: &[OrderingTerm],
+    written: &[ResultColumn],
+    expanded: &[ResultColumn],
+) -> Result<Vec<OrderingTerm>> {
+    order_by
+        .iter()
+        .map(|term| {
+            let expr = match &term.expr {
+                Expr::Literal(SerialValue::Int64(position)) => {
+                    match usize::try_from(*position)
+                        .ok()
+                        .and_then(|position| expanded.get(position.checked_sub(1)?))
+                    {
+                        Some(column) => column.expr.clone(),
+                        None => bail!(
+                            "ORDER BY term out of range - should be between 1 and {}",
+                            expanded.len()
+                        ),
+                    }
+                }
+                Expr::Column(name) => written
+                    .iter()
+                    .find(|c| {
+                        c.alias
+                            .as_ref()
+                            .is_some_and(|a| a.eq_ignore_ascii_case(name))
+                    })
+                    .map_or_else(|| term.expr.clone(), |c| c.expr.clone()),
+                expr => expr.clone(),
+            };
+            Ok(OrderingTerm {
+                expr,
+                ..term.clone()
+            })
+        })
+        .collect()
+}
+
+/// Replaces the names in a WHERE clause that refer to the alias of a result column with the
+/// aliased expression. Names of table columns, as told by `is_column` for a lowercased name, take
+/// precedence over aliases. An alias given to several different expressions or to an aggregate
+/// can't be used.
+fn resolve_where_aliases(
+    condition: &Expr,
+    select: &[ResultColumn],
+    is_column: &impl Fn(&str) -> bool,
+) -> Result<Expr> {
+    let Expr::Column(name) = condition else {
+        return condition.try_map_children(|child| resolve_where_aliases(child, select, is_column));
+    };
+    if is_column(&name.to_ascii_lowercase()) {
+        return Ok(condition.clone());
+    }
+    let aliased = select
+        .iter()
+        .filter(|c| {
+            c.alias
+                .as_ref()
+                .is_some_and(|a| a.eq_ignore_ascii_case(name))
+        })
+        .map(|c| &c.expr)
+        .collect_vec();
+    match aliased.as_slice() {
+        [] => Ok(condition.clone()),
+        [expr, rest @ ..] if rest.iter().any(|other| other != expr) => {
+            bail!(
+                "ambiguous column name: {} (aliases different expressions)",
+                name
+            )
+        }
+        [expr, ..] if expr.contains_aggregate() => bail!("misuse of aliased aggregate {}", name),
+        [expr, ..] => Ok((*expr).clone()),
+    }
+}
+
+/// Describes each selected column. Only plain column references have a declared type.
+fn column_meta(select: &[ResultColumn], tables: &[(&str, &[ColumnDefinition])]) -> Vec<ColumnMeta> {
+    let find = |table: Option<&str>, column: &str| {
+        tables
+            .iter()
+            .filter(|(name, _)| table.is_none_or(|table| name.eq_ignore_ascii_case(table)))
+            .flat_map(|(_, columns)| columns.iter())
+            .find(|c| c.name.eq_ignore_ascii_case(column))
+            .and_then(|c| c.type_name.clone())
+    };
+
+    select
+        .iter()
+        .map(|result_column| ColumnMeta {
+            name: result_column.name(),
+            declared_type: match &result_column.expr {
+                Expr::Column(column) => find(None, column),
+                Expr::QualifiedColumn { table, column } => find(Some(table), column),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+/// Maps the names of columns declared with `COLLATE` to their collating sequence. Like the column
+/// map, both bare and qualified names are lowercased.
+fn column_collations(tables: &[(&str, &[ColumnDefinition])]) -> HashMap<String, String> {
+    let mut collations = HashMap::new();
+    for (table, columns) in tables {
+        for column in columns.iter() {
+            if let Some(collation) = &column.collation {
+                let name = column.name.to_ascii_lowercase();
+                let qualified = format!("{}.{}", table.to_ascii_lowercase(), name);
+                collations.insert(qualified, collation.clone());
+                collations.entry(name).or_insert(collation.clone());
+            }
+        }
+    }
+    collations
+}
+
+/// Makes the collating sequences declared on columns explicit. A comparison where neither side
+/// has a `COLLATE` uses the collation of its left operand if that's a column with one, otherwise
+/// that of its right operand. With `ordering` set, the expression is an ORDER BY term, which
+/// sorts with the collation of the column it names.
+fn apply_column_collations(
+    expr: &Expr,
+    collations: &HashMap<String, String>,
+    ordering: bool,
+) -> Expr {
+    let column_collation = |expr: &Expr| {
+        let name = match expr {
+            Expr::Column(column) => column.to_ascii_lowercase(),
+            Expr::QualifiedColumn { table, column } => {
+                format!("{}.{}", table, column).to_ascii_lowercase()
+            }
+            _ => return None,
+        };
+        collations.get(&name).cloned()
+    };
+    let collate = |expr: &Expr, collation| {
+        Box::new(Expr::Collate {
+            expr: Box::new(expr.clone()),
+            collation,
+        })
+    };
+    let explicit = |exprs: &[&Expr]| exprs.iter().any(|e| matches!(e, Expr::Collate { .. }));
+
+    if ordering {
+        return match column_collation(expr) {
+            Some(collation) => *collate(expr, collation),
+            None => expr.clone(),
+        };
+    }
+
+    let expr = expr
+        .try_map_children(|child| Ok(apply_column_collations(child, collations, false)))
+        .expect("mapping can't fail");
+    match expr {
+        Expr::Binary { left, op, right } if op.is_comparison() && !explicit(&[&left, &right]) => {
+            match (column_collation(&left), column_collation(&right)) {
+                (Some(collation), _) => Expr::Binary {
+                    left: collate(&left, collation),
+                    op,
+                    right,
+                },
+                (None, Some(collation)) => Expr::Binary {
+                    left,
+                    op,
+                    right: collate(&right, collation),
+                },
+                (None, None) => Expr::Binary { left, op, right },
+            }
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } if !explicit(&[&expr, &low, &high]) => Expr::Between {
+            expr: match column_collation(&expr) {
+                Some(collation) => collate(&expr, collation),
+                None => expr,
+            },
+            low,
+            high,
+            negated,
+        },
+        expr => expr,
+    }
+}
+
+/// Maps each column to its affinity, with names lowercased as in the column map.
+fn column_affinities(tables: &[(&str, &[ColumnDefinition])]) -> HashMap<String, Affinity> {
+    let mut affinities = HashMap::new();
+    for (table, columns) in tables {
+        for column in columns.iter() {
+            let name = column.name.to_ascii_lowercase();
+            let qualified = format!("{}.{}", table.to_ascii_lowercase(), name);
+            affinities.insert(qualified, column.affinity());
+            affinities.entry(name).or_insert(column.affinity());
+        }
+    }
+    affinities
+}
+
+/// Converts literals compared with a column to the column's affinity, as SQLite does before
+/// comparing, so that e.g. `text_column = 1` matches the text `'1'`. Only the text and numeric
+/// affinities convert anything.
+fn apply_column_affinities(expr: &Expr, affinities: &HashMap<String, Affinity>) -> Expr {
+    let column_affinity = |expr: &Expr| match expr {
+        Expr::Column(column) => affinities.get(&column.to_ascii_lowercase()).copied(),
+        Expr::QualifiedColumn { table, column } => affinities
+            .get(&format!("{}.{}", table, column).to_ascii_lowercase())
+            .copied(),
+        _ => None,
+    };
+    let convert = |operand: Box<Expr>, affinity: Option<Affinity>| match (*operand, affinity) {
+        (Expr::Literal(value), Some(affinity)) => {
+            Box::new(Expr::Literal(value.apply_affinity(affinity)))
+        }
+        (operand, _) => Box::new(operand),
+    };
+
+    let expr = expr
+        .try_map_children(|child| Ok(apply_column_affinities(child, affinities)))
+        .expect("mapping can't fail");
+    match expr {
+        Expr::Binary { left, op, right } if op.is_comparison() => {
+            let (left_affinity, right_affinity) = (column_affinity(&left), column_affinity(&right));
+            Expr::Binary {
+                left: convert(left, right_affinity),
+                op,
+                right: convert(right, left_affinity),
+            }
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => {
+            let affinity = column_affinity(&expr);
+            Expr::Between {
+                expr,
+                low: convert(low, affinity),
+                high: convert(high, affinity),
+                negated,
+            }
+        }
+        expr => expr,
+    }
+}
+
+/// Joins are evaluated as nested loops over full table scans, with each row of the result being
+/// the concatenation of one row from every table.
+fn join_rows(
+    db_file: &mut DBFile,
+    select_statement: &SelectStatement,
+    column_map: &HashMap<String, usize>,
+) -> Result<Vec<Row>> {
+    let mut tables = vec![];
+    for name in select_statement
+        .from
+        .iter()
+        .chain(select_statement.joins.iter().map(|j| &j.table))
+    {
+        let columns = db_file
+            .schema_for_table(name)
+            .with_context(|| format!("searching for table with name '{}'", name))?
+            .columns()
+            .context("retrieving table columns")?;
+        // Each table's rows are full width, so the joined column offsets line up
+        let mut rows = vec![];
+        for_each_selected_row(db_file, name, &columns, &AccessPath::FullScan, &mut |row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        tables.push(rows);
+    }
+
+    let mut tables = tables.into_iter();
+    let mut rows = tables.next().unwrap_or_default();
+    for (join, right_rows) in select_statement.joins.iter().zip(tables) {
+        let mut joined = vec![];
+        for left in &rows {
+            for right in &right_rows {
+                let row = left.iter().chain(right).cloned().collect_vec();
+                let matches = match &join.on {
+                    Some(on) => {
+                        let value = evaluate(on, &row, column_map).context("evaluating ON")?;
+                        is_true(&value) == Some(true)
+                    }
+                    None => true,
+                };
+                if matches {
+                    joined.push(row);
+                    db_file.limits().check_rows(joined.len())?;
+                }
+            }
+        }
+        rows = joined;
+    }
+    Ok(rows)
+}
+
+/// Extends a row read from a table to the table's full width. A record can hold fewer values
+/// than the table has columns, e.g. when it was written before `ALTER TABLE ADD COLUMN`, and
+/// the missing trailing columns take their default value, or NULL if they have none.
+pub fn pad_row(row: &mut Row, columns: &[ColumnDefinition]) {
+    for column in columns.iter().skip(row.len()) {
+        row.push(column.default.clone().unwrap_or(SerialValue::Null));
+    }
+}
+
+/// Builds the column map for rows made by concatenating a row from each of `tables` in order.
+/// Every column can be referenced as `table.column`, but a bare column name is only mapped when
+/// exactly one table has it. Bare names shared by several tables are returned separately along
+/// with the names of those tables. As with `SchemaObject::column_map`, names are lowercased.
+fn joined_column_map(
+    tables: &[(&str, &[ColumnDefinition])],
+) -> (HashMap<String, usize>, HashMap<String, Vec<String>>) {
+    let mut column_map = HashMap::new();
+    let mut owners: HashMap<String, Vec<String>> = HashMap::new();
+    let mut offset = 0;
+    for (table, columns) in tables {
+        for (ind, column) in columns.iter().enumerate() {
+            // The rowid names refer to the alias column, if the table has one
+            let mut names = vec![column.name.to_ascii_lowercase()];
+            if column.is_rowid_alias() {
+                names.extend(
+                    ROWID_NAMES
+                        .into_iter()
+                        .filter(|name| !columns.iter().any(|c| c.name.eq_ignore_ascii_case(name)))
+                        .map(String::from),
+                );
+            }
+            for column in names {
+                let qualified = format!("{}.{}", table.to_ascii_lowercase(), column);
+                column_map.insert(qualified, offset + ind);
+                column_map.entry(column.clone()).or_insert(offset + ind);
+                owners.entry(column).or_default().push(table.to_string());
+            }
+        }
+        offset += columns.len();
+    }
+
+    let ambiguous: HashMap<_, _> = owners
+        .into_iter()
+        .filter(|(_, tables)| tables.len() > 1)
+        .collect();
+    for column in ambiguous.keys() {
+        column_map.remove(column);
+    }
+    (column_map, ambiguous)
+}
+
+/// Checks that every column referenced by the statement exists and, for unqualified names, that
+/// it isn't shared by more than one table.
+fn check_columns(
+    select_statement: &SelectStatement,
+    column_map: &HashMap<String, usize>,
+    ambiguous: &HashMap<String, Vec<String>>,
+) -> Result<()> {
+    let mut columns = vec![];
+    let exprs = select_statement
+        .select
+        .iter()
+        .map(|c| &c.expr)
+        .chain(select_statement.joins.iter().filter_map(|j| j.on.as_ref()))
+        .chain(&select_statement.where_clause)
+        .chain(&select_statement.group_by)
+        .chain(&select_statement.having)
+        .chain(select_statement.order_by.iter().map(|term| &term.expr));
+    for expr in exprs {
+        expr.visit(&mut |e| match e {
+            Expr::Column(name) => columns.push(name.clone()),
+            Expr::QualifiedColumn { table, column } => {
+                columns.push(format!("{}.{}", table, column))
+            }
+            _ => {}
+        });
+    }
+    for column in columns {
+        let key = column.to_ascii_lowercase();
+        if let Some(tables) = ambiguous.get(&key) {
+            bail!(
+                "ambiguous column name: {} (found in tables {})",
+                column,
+                tables.join(", ")
+            );
+        }
+        if !column_map.contains_key(&key) {
+            return Err(no_such_column(&column, column_map));
+        }
+    }
+    Ok(())
+}
+
+fn filter_rows(
+    rows: Vec<Row>,
+    column_map: &HashMap<String, usize>,
+    select_statement: &SelectStatement,
+) -> Result<Vec<Row>> {
+    let Some(condition) = &select_statement.where_clause else {
+        return Ok(rows);
+    };
+    let mut matching = vec![];
+    for row in rows {
+        if row_matches(&row, column_map, condition).context("evaluating WHERE")? {
+            matching.push(row);
+        }
+    }
+    Ok(matching)
+}
+
+/// Whether a row satisfies a condition such as a WHERE clause. A condition that evaluates to NULL
+/// doesn't match, the same as one that is false.
+pub fn row_matches(
+    row: &[SerialValue],
+    column_map: &HashMap<String, usize>,
+    condition: &Expr,
+) -> Result<bool> {
+    Ok(is_true(&evaluate(condition, row, column_map)?) == Some(true))
+}
+
+/// Splits a condition into the terms of its top level AND, all of which must hold for the whole
+/// condition to be true.
+fn conjuncts(condition: &Expr) -> Vec<&Expr> {
+    match condition {
+        Expr::Binary {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            terms
+        }
+        condition => vec![condition],
+    }
+}
+
+/// The column, value and collating sequence of a `column = value` comparison, which can be
+/// answered with an index kept in that collation.
+fn equality_term(term: &Expr) -> Option<(&str, &SerialValue, Collation)> {
+    let Expr::Binary {
+        left,
+        op: BinaryOperator::Equal,
+        right,
+    } = term
+    else {
+        return None;
+    };
+    fn uncollated(expr: &Expr) -> (&Expr, Option<&str>) {
+        match expr {
+            Expr::Collate { expr, collation } => (expr, Some(collation)),
+            expr => (expr, None),
+        }
+    }
+    let ((left, left_collation), (right, right_collation)) = (uncollated(left), uncollated(right));
+    // A COLLATE on the left operand takes precedence over one on the right
+    let collation = match left_collation.or(right_collation) {
+        Some(name) => Collation::from_name(name).ok()?,
+        None => Collation::Binary,
+    };
+    match (left, right) {
+        (Expr::Column(column), Expr::Literal(value))
+        | (Expr::Literal(value), Expr::Column(column))
+            if !value.is_null() =>
+        {
+            Some((column.as_str(), value, collation))
+        }
+        _ => None,
+    }
+}
+
+/// The range of every possible rowid.
+const ALL_ROWIDS: RangeInclusive<i64> = i64::MIN..=i64::MAX;
+
+/// Whether `name` refers to the rowid of a table with the given columns: either the column that
+/// aliases the rowid or one of the rowid's own names when no column has taken it.
+fn is_rowid_column(columns: &[ColumnDefinition], name: &str) -> bool {
+    match columns.iter().find(|c| c.name.eq_ignore_ascii_case(name)) {
+        Some(column) => column.is_rowid_alias(),
+        None => ROWID_NAMES.contains(&name.to_ascii_lowercase().as_str()),
+    }
+}
+
+/// The range of rowids a row must have for the whole condition to be true, found from the
+/// comparisons of the rowid with integers in the condition or any term of a top level AND.
+fn rowid_range(condition: &Expr, is_rowid: &impl Fn(&str) -> bool) -> RangeInclusive<i64> {
+    const ALL: RangeInclusive<i64> = ALL_ROWIDS;
+    let rowid_literal = |expr: &Expr| match expr {
+        Expr::Literal(value) => value.as_i64(),
+        _ => None,
+    };
+    match condition {
+        Expr::Binary {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            let (left, right) = (rowid_range(left, is_rowid), rowid_range(right, is_rowid));
+            *left.start().max(right.start())..=*left.end().min(right.end())
+        }
+        Expr::Binary { left, op, right } => {
+            // Put the rowid on the left, flipping the comparison if needed
+            let (value, op) = match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column), value) if is_rowid(column) => (value, *op),
+                (value, Expr::Column(column)) if is_rowid(column) => match op {
+                    BinaryOperator::Less => (value, BinaryOperator::Greater),
+                    BinaryOperator::LessEqual => (value, BinaryOperator::GreaterEqual),
+                    BinaryOperator::Greater => (value, BinaryOperator::Less),
+                    BinaryOperator::GreaterEqual => (value, BinaryOperator::LessEqual),
+                    op => (value, *op),
+                },
+                _ => return ALL,
+            };
+            let Some(value) = rowid_literal(value) else {
+                return ALL;
+            };
+            match op {
+                BinaryOperator::Equal => value..=value,
+                // Saturating is fine at the extremes since rows are filtered afterwards anyway
+                BinaryOperator::Greater => value.saturating_add(1)..=i64::MAX,
+                BinaryOperator::GreaterEqual => value..=i64::MAX,
+                BinaryOperator::Less => i64::MIN..=value.saturating_sub(1),
+                BinaryOperator::LessEqual => i64::MIN..=value,
+                _ => ALL,
+            }
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated: false,
+        } => match (expr.as_ref(), rowid_literal(low), rowid_literal(high)) {
+            (Expr::Column(column), Some(low), Some(high)) if is_rowid(column) => low..=high,
+            _ => ALL,
+        },
+        _ => ALL,
+    }
+}
+
+/// Sorts rows by the given ORDER BY terms. The sort is stable, so rows that compare equal on
+/// every term keep their original order.
+fn sort_rows(
+    rows: &mut Vec<Row>,
+    order_by: &[OrderingTerm],
+    column_map: &HashMap<String, usize>,
+) -> Result<()> {
+    let mut keyed = rows
+        .drain(..)
+        .map(|row| {
+            let key = evaluate_row(&row, order_by.iter().map(|term| &term.expr), column_map)?;
+            Ok((key, row))
+        })
+        .collect::<Result<Vec<_>>>()
+        .context("evaluating ORDER BY terms")?;
+
+    keyed.sort_by(|(a, _), (b, _)| compare_sort_keys(a, b, order_by));
+
+    rows.extend(keyed.into_iter().map(|(_, row)| row));
+    Ok(())
+}
+
+/// Compares the values of the ORDER BY terms for two rows.
+fn compare_sort_keys(a: &[SerialValue], b: &[SerialValue], order_by: &[OrderingTerm]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .zip(order_by)
+        .map(|((a, b), term)| match (a, b) {
+            (SerialValue::Null, SerialValue::Null) => Ordering::Equal,
+            (SerialValue::Null, _) if term.nulls_first => Ordering::Less,
+            (SerialValue::Null, _) => Ordering::Greater,
+            (_, SerialValue::Null) if term.nulls_first => Ordering::Greater,
+            (_, SerialValue::Null) => Ordering::Less,
+            (a, b) => {
+                // Unknown collations were already rejected while evaluating the keys
+                let collation = collation_of(&term.expr).ok().flatten().unwrap_or_default();
+                match term.descending {
+                    true => b.compare_collated(a, collation),
+                    false => a.compare_collated(b, collation),
+                }
+            }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// The values of the GROUP BY terms for one row, or a whole result row when removing duplicates.
+/// Keys are equal when each of their values is equal by `SerialValue::canonical_eq`, so `1` and
+/// `1.0` fall in the same group.
+#[derive(Debug)]
+struct GroupKey(Row);
+
+impl Ord for GroupKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| a.compare(b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+    }
+}
+
+impl PartialOrd for GroupKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for GroupKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| a.canonical_eq(b))
+    }
+}
+
+impl Eq for GroupKey {}
+
+impl Hash for GroupKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in &self.0 {
+            value.canonical_hash(state);
+        }
+    }
+}
+
+/// Splits rows into groups with equal values for the GROUP BY terms, ordered by those values as
+/// SQLite does. Without GROUP BY all the rows form a single group, even when there are none.
+fn group_rows(
+    rows: Vec<Row>,
+    group_by: &[Expr],
+    column_map: &HashMap<String, usize>,
+) -> Result<Vec<Vec<Row>>> {
+    if group_by.is_empty() {
+        return Ok(vec![rows]);
+    }
+
+    let mut groups: HashMap<GroupKey, Vec<Row>> = HashMap::new();
+    for row in rows {
+        let key = evaluate_row(&row, group_by, column_map).context("evaluating GROUP BY")?;
+        groups.entry(GroupKey(key)).or_default().push(row);
+    }
+    Ok(groups
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, rows)| rows)
+        .collect())
+}
+
+fn table_root_page(db_file: &mut DBFile, table_name: &str) -> Result<usize> {
+    db_file
+        .schema_for_table(table_name)
+        .with_context(|| format!("searching for table with name '{}'", table_name))?
+        .root_page
+        .context("getting root page from table schema")
+}
+
+/// Calls `f` with each row of a table found along the given access path, padded to the table's
+/// full width. Rows are passed on a leaf page at a time as they're read, so they don't all need to
+/// be held at once. Rows that don't satisfy the WHERE clause may be included, so they must still
+/// be filtered.
+fn for_each_selected_row(
+    db_file: &mut DBFile,
+    table_name: &str,
+    columns: &[ColumnDefinition],
+    access: &AccessPath,
+    f: &mut dyn FnMut(Row) -> Result<()>,
+) -> Result<()> {
+    let root_page = table_root_page(db_file, table_name)?;
+    let alias_column = rowid_column(columns);
+    let mut f = |mut row: Row| {
+        pad_row(&mut row, columns);
+        f(row)
+    };
+
+    match access {
+        AccessPath::FullScan | AccessPath::RowidRange(_) => {
+            let rowids = match access {
+                AccessPath::RowidRange(rowids) if rowids.is_empty() => return Ok(()),
+                AccessPath::RowidRange(rowids) => rowids.clone(),
+                _ => ALL_ROWIDS,
+            };
+            // The row cap is checked after each page so a huge table isn't read before failing
+            let limits = db_file.limits();
+            let scanned_before = db_file.rows_scanned();
+            let mut scanned = 0;
+            db_file.for_each_leaf_page_in_range(root_page, rowids, |page| {
+                // TODO: It would be possible to pass the column indices we want to this function
+                // and skip over the serial values for any columns we don't care about.
+                let rows = page
+                    .read_cells(alias_column)
+                    .context("reading cells from leaf page")?;
+                scanned += rows.len();
+                limits.check_rows(scanned_before + scanned)?;
+                rows.into_iter().try_for_each(&mut f)
+            })?;
+            db_file.count_rows_scanned(scanned)
+        }
+        AccessPath::Index { index, key } => {
+            let mut row_ids = index_row_ids(db_file, index, key)?;
+
+            // The table is searched in rowid order, which the index only keeps for equal values
+            // of all its columns. Rowids are signed, so negative ones come first.
+            row_ids.sort_unstable_by_key(|&id| id as i64);
+            let mut visited = HashSet::from([root_page]);
+            let root_page = db_file.load_page_at(root_page)?;
+            select_with_index(
+                db_file,
+                root_page,
+                &row_ids,
+                alias_column,
+                &mut visited,
+                &mut f,
+            )
+        }
+        AccessPath::NoTable | AccessPath::RowCount | AccessPath::IndexCount { .. } => {
+            unreachable!("no rows are read for {:?}", access)
+        }
+    }
+}
+
+/// Finds the rowids of the index entries whose leading columns equal `key`, in index order.
+fn index_row_ids(
+    db_file: &mut DBFile,
+    index: &IndexLookup,
+    key: &[SerialValue],
+) -> Result<Vec<u64>> {
+    let page = db_file.load_page_at(index.root_page)?;
+    let mut visited = HashSet::from([index.root_page]);
+    search_index(db_file, page, index, key, &mut visited)
+}
+
+/// Looks up rows by rowid in a table b-tree, starting from the given page, and calls `f` with each
+/// in turn. `row_ids` must be sorted by their signed values, which is the order the table keeps
+/// them in.
+fn select_with_index(
+    db_file: &mut DBFile,
+    page: BTreePage,
+    row_ids: &[u64],
+    alias_column: Option<usize>,
+    visited: &mut HashSet<usize>,
+    f: &mut dyn FnMut(Row) -> Result<()>,
+) -> Result<()> {
+    match page.page_type {
+        PageType::InteriorTable => {
+            let cells = page
+                .read_interior_cells()
+                .context("reading interior cells")?;
+
+            let mut right_ids = row_ids;
+
+            for (ind, interior_cell) in cells.iter().enumerate() {
+                let InteriorCell::Table(cell) = interior_cell else {
+                    bail!("invalid cell type - expected interior table cell")
+                };
+
+                // Keys are signed like the rowids, and `row_ids` is sorted the same way
+                let pp = right_ids.partition_point(|&id| id as i64 <= cell.row_id as i64);
+                let left_ids = &right_ids[..pp];
+                right_ids = &right_ids[pp..];
+
+                if !left_ids.is_empty() {
+                    // The left page of this BTree item or its child pages should contain the IDs in
+                    // left_ids. Load that page then look the rows up in it.
+                    mark_visited(visited, cell.left_child_page as usize)?;
+                    let next_page = db_file
+                        .load_page_at(cell.left_child_page as usize)
+                        .context("loading next index page")?;
+                    select_with_index(db_file, next_page, left_ids, alias_column, visited, f)?;
+                }
+
+                if right_ids.is_empty() {
+                    // No more rows to find in  this page
+                    break;
+                }
+
+                if let Some(right_page) = page.right_most_pointer {
+                    if ind == cells.len() - 1 {
+                        // There might be additional results in the right page pointer
+                        mark_visited(visited, right_page as usize)?;
+                        let right_page = db_file
+                            .load_page_at(right_page as usize)
+                            .context("loading right page")?;
+
+                        select_with_index(
+                            db_file,
+                            right_page,
+                            right_ids,
+                            alias_column,
+                            visited,
+                            f,
+                        )?;
+                    }
+                }
+            }
+        }
+        PageType::LeafTable => {
+            db_file.count_rows_scanned(row_ids.len())?;
+            for &id in row_ids {
+                let row = page
+                    .find_row(id, alias_column)
+                    .context("reading row from leaf table page")?
+                    .with_context(|| format!("the index refers to a missing rowid {}", id))?;
+                f(row)?;
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Compares the leading values of an index entry with `key`, in the order the index keeps them.
+fn compare_index_key(entry: &[SerialValue], key: &[SerialValue], index: &IndexLookup) -> Ordering {
+    entry
+        .iter()
+        .zip(key)
+        .zip(index.columns.iter().zip(&index.collations))
+        .map(|((value, key), (column, &collation))| {
+            let ordering = value.compare_collated(key, collation);
+            if column.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Searches an index starting from the given page and returns the rowids of the entries whose
+/// leading columns equal `key`, compared in each column's collation. Columns declared `DESC` are
+/// kept in reverse order in the b-tree.
+fn search_index(
+    db_file: &mut DBFile,
+    page: BTreePage,
+    index: &IndexLookup,
+    key: &[SerialValue],
+    visited: &mut HashSet<usize>,
+) -> Result<Vec<u64>> {
+    match page.page_type {
+        PageType::InteriorIndex => {
+            let cells = page
+                .read_interior_cells()
+                .context("reading interior cells")?;
+
+            let mut results = vec![];
+            for (ind, interior_cell) in cells.iter().enumerate() {
+                let InteriorCell::Index(cell) = interior_cell else {
+                    bail!("invalid cell type")
+                };
+
+                let cell_cmp = compare_index_key(&cell.columns, key, index);
+
+                if cell_cmp == Ordering::Greater || cell_cmp == Ordering::Equal {
+                    // The left page of this BTree item _might_ contain more matching entries so
+                    // load that page and add any rowids it produces to the result set.
+                    mark_visited(visited, cell.left_child_page as usize)?;
+                    let next_page = db_file
+                        .load_page_at(cell.left_child_page as usize)
+                        .context("loading next index page")?;
+
+                    results.extend(
+                        search_index(db_file, next_page, index, key, visited)
+                            .context("loading results from next index page")?,
+                    );
+                }
+
+                if cell_cmp == Ordering::Greater {
+                    // The following BTree items _cannot_ contain the search query - we can bail out
+                    // from the loop now
+                    break;
+                }
+
+                if cell_cmp == Ordering::Equal {
+                    // This cell matches the query - add the rowid to the result set.
+                    results.push(cell.rowid);
+                }
+
+                if let Some(right_page) = page.right_most_pointer {
+                    if ind == cells.len() - 1
+                        && (cell_cmp == Ordering::Equal || cell_cmp == Ordering::Less)
+                    {
+                        // There might be additional results in the right page pointer
+                        mark_visited(visited, right_page as usize)?;
+                        let right_page = db_file
+                            .load_page_at(right_page as usize)
+                            .context("loading right page")?;
+
+                        results.extend(
+                            search_index(db_file, right_page, index, key, visited)
+                                .context("searching in right index page")?,
+                        )
+                    }
+                }
+            }
+            Ok(results)
+        }
+        PageType::LeafIndex => {
+            // TODO: It might make sense to do a binary search over the cells on leaf pages
+            // These cells are laid out as [Serial(<indexed column>)..., Int?(<rowid>)]
+            Ok(page
+                .read_cells(None)?
+                .into_iter()
+                .filter(|c| compare_index_key(c, key, index).is_eq())
+                .map(|c| c.last().and_then(SerialValue::as_rowid).unwrap_or(0u64))
+                .collect())
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn evaluate_row<'a>(
+    row: &[SerialValue],
+    exprs: impl IntoIterator<Item = &'a Expr>,
+    column_map: &HashMap<String, usize>,
+) -> Result<Row> {
+    exprs
+        .into_iter()
+        .map(|expr| evaluate(expr, row, column_map))
+        .collect::<Result<Vec<_>>>()
+        .context("evaluating selected columns")
+}
+
+fn evaluate_group_row<'a>(
+    group: &[Row],
+    exprs: impl IntoIterator<Item = &'a Expr>,
+    column_map: &HashMap<String, usize>,
+) -> Result<Row> {
+    exprs
+        .into_iter()
+        .map(|expr| evaluate_group(expr, group, column_map))
+        .collect::<Result<Vec<_>>>()
+        .context("evaluating selected columns")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::db_file::DBFileOptions;
+    use crate::sql::sql::sql_statement;
+    use crate::sql::Statement;
+
+    #[test]
+    fn order_by_nulls() {
+        let column_map = HashMap::from([(String::from("a"), 0)]);
+        let mut rows = vec![
+            vec![SerialValue::Int8(2)],
+            vec![SerialValue::Null],
+            vec![SerialValue::Int8(1)],
+            vec![SerialValue::Null],
+            vec![SerialValue::Int8(3)],
+        ];
+        let sorted = |rows: &mut Vec<Row>, order_by: &str| {
+            let Statement::Select(s) =
+                sql_statement(&format!("SELECT a FROM t ORDER BY {}", order_by)).unwrap()
+            else {
+                panic!("expected a select statement")
+            };
+            sort_rows(rows, &s.order_by, &column_map).unwrap();
+            rows.iter().map(|row| row[0].to_string()).join(",")
+        };
+
+        assert_eq!(sorted(&mut rows, "a"), "(null),(null),1,2,3");
+        assert_eq!(sorted(&mut rows, "a ASC NULLS LAST"), "1,2,3,(null),(null)");
+        assert_eq!(sorted(&mut rows, "a DESC"), "3,2,1,(null),(null)");
+        assert_eq!(
+            sorted(&mut rows, "a DESC NULLS FIRST"),
+            "(null),(null),3,2,1"
+        );
+    }
+
+    #[test]
+    fn row_matches_conditions() {
+        let column_map = HashMap::from([
+            (String::from("a"), 0),
+            (String::from("b"), 1),
+            (String::from("c"), 2),
+        ]);
+        let row = [
+            SerialValue::Int8(2),
+            SerialValue::Text(String::from("x")),
+            SerialValue::Null,
+        ];
+        let matches = |condition: &str| {
+            let Statement::Select(s) =
+                sql_statement(&format!("SELECT a FROM t WHERE {}", condition)).unwrap()
+            else {
+                panic!("expected a select statement")
+            };
+            row_matches(&row, &column_map, &s.where_clause.unwrap()).unwrap()
+        };
+
+        for condition in [
+            "a = 2",
+            "a != 3",
+            "a < 3",
+            "a <= 2",
+            "a > 1",
+            "a >= 2",
+            "b = 'x'",
+            "a = 2 AND b = 'x'",
+            "a = 5 OR b = 'x'",
+            "NOT a = 5",
+        ] {
+            assert!(matches(condition), "{} should match", condition);
+        }
+        for condition in [
+            "a = 3",
+            "a != 2",
+            "a < 2",
+            "a > 2",
+            "b = 'y'",
+            "a = 2 AND b = 'y'",
+            "0",
+        ] {
+            assert!(!matches(condition), "{} should not match", condition);
+        }
+
+        // Comparisons with NULL are NULL, which doesn't match whichever way it's negated, unless
+        // the other side of an AND or OR decides the result
+        for condition in ["c = 1", "c != 1", "NOT c = 1", "c = 1 AND a = 2"] {
+            assert!(!matches(condition), "{} should not match", condition);
+        }
+        assert!(matches("c = 1 OR a = 2"));
+        assert!(!matches("NOT (c = 1 OR a = 5)"));
+    }
+
+    #[test]
+    fn indexed_where() {
+        let mut file = File::open("fixtures/schema.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            ("SELECT id FROM people WHERE name = 'Alice'", vec![1]),
+            (
+                "SELECT id FROM people WHERE 'Bob' = name AND age < 18",
+                vec![2],
+            ),
+            (
+                "SELECT id FROM people WHERE name = 'Bob' AND age > 18",
+                vec![],
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows,
+                expected
+                    .into_iter()
+                    .map(|id| vec![SerialValue::Int64(id)])
+                    .collect::<Vec<_>>(),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn index_affinity() {
+        // Without a cache every page read is counted, showing the index was searched
+        let mut db_file = DBFileOptions::new()
+            .cache_size(0)
+            .open(File::open("fixtures/affinity.db").unwrap())
+            .unwrap();
+        let full_scan = {
+            let pages_loaded = db_file.pages_loaded();
+            db_file
+                .query("SELECT id FROM codes WHERE id + 0 = 1")
+                .unwrap();
+            db_file.pages_loaded() - pages_loaded
+        };
+        for (sql, expected) in [
+            ("SELECT id FROM codes WHERE code = 42", vec![42]),
+            ("SELECT id FROM codes WHERE code = '42'", vec![42]),
+            ("SELECT id FROM codes WHERE quantity = '84'", vec![42]),
+            ("SELECT id FROM codes WHERE quantity = 500", vec![250]),
+            ("SELECT id FROM codes WHERE quantity = 85", vec![]),
+            ("SELECT id FROM codes WHERE code = 4.5", vec![]),
+        ] {
+            let pages_loaded = db_file.pages_loaded();
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows,
+                expected
+                    .into_iter()
+                    .map(|id| vec![SerialValue::Int64(id)])
+                    .collect::<Vec<_>>(),
+                "{}",
+                sql
+            );
+            assert!(db_file.pages_loaded() - pages_loaded < full_scan, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn index_with_residual_like() {
+        let mut db_file = DBFileOptions::new()
+            .cache_size(0)
+            .open(File::open("fixtures/books.db").unwrap())
+            .unwrap();
+        let full_scan = {
+            let pages_loaded = db_file.pages_loaded();
+            db_file.query("SELECT id FROM books").unwrap();
+            db_file.pages_loaded() - pages_loaded
+        };
+        for (sql, expected) in [
+            (
+                "SELECT title FROM books WHERE category = 'books' AND title LIKE 'The%'",
+                "The Hobbit,the road",
+            ),
+            (
+                "SELECT title FROM books WHERE title GLOB 'The*' AND category = 'books'",
+                "The Hobbit",
+            ),
+            (
+                "SELECT title FROM books WHERE category = 'books' AND title NOT LIKE 'the %'",
+                "Dune",
+            ),
+        ] {
+            let pages_loaded = db_file.pages_loaded();
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| &row[0]).join(","),
+                expected,
+                "{}",
+                sql
+            );
+            assert!(db_file.pages_loaded() - pages_loaded < full_scan, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn composite_index() {
+        let mut db_file = DBFileOptions::new()
+            .cache_size(0)
+            .open(File::open("fixtures/composite.db").unwrap())
+            .unwrap();
+        let index_columns = |db_file: &mut DBFile, columns: &[&str]| {
+            db_file
+                .find_index(
+                    "events",
+                    &columns
+                        .iter()
+                        .map(|&column| (column, Collation::Binary))
+                        .collect_vec(),
+                )
+                .unwrap()
+                .map(|lookup| {
+                    let columns = lookup.columns.into_iter().map(|c| c.name).collect_vec();
+                    (lookup.name, columns)
+                })
+        };
+        assert_eq!(
+            index_columns(&mut db_file, &["day", "KIND", "note"]),
+            Some((
+                String::from("idx_events_kind_day"),
+                vec![String::from("kind"), String::from("day")]
+            ))
+        );
+        assert_eq!(
+            index_columns(&mut db_file, &["kind", "note"]),
+            Some((String::from("idx_events_kind"), vec![String::from("kind")]))
+        );
+        assert_eq!(index_columns(&mut db_file, &["day"]), None);
+
+        let mut run = |sql: &str| {
+            let pages_loaded = db_file.pages_loaded();
+            let rows = db_file.query(sql).unwrap().rows;
+            let ids = rows.iter().map(|row| &row[0]).join(",");
+            (ids, db_file.pages_loaded() - pages_loaded)
+        };
+        let (_, kind_only) = run("SELECT id FROM events WHERE kind = 'kind3'");
+        for (sql, expected) in [
+            (
+                "SELECT id FROM events WHERE kind = 'kind3' AND day = 5",
+                "153,523,893",
+            ),
+            (
+                "SELECT id FROM events WHERE day = 5 AND kind = 'kind3'",
+                "153,523,893",
+            ),
+            // Conditions the index can't answer are still applied to the rows it finds
+            (
+                "SELECT id FROM events WHERE kind = 'kind3' AND day = 5 AND id > 200",
+                "523,893",
+            ),
+            (
+                "SELECT id FROM events WHERE kind = 'kind3' AND day = 5 AND note LIKE '%93'",
+                "893",
+            ),
+            (
+                "SELECT id FROM events WHERE kind = 'kind3' AND day = 40",
+                "",
+            ),
+        ] {
+            let (ids, pages) = run(sql);
+            assert_eq!(ids, expected, "{}", sql);
+            assert!(pages < kind_only, "{}: {} pages", sql, pages);
+        }
+
+        let (ids, _) = run("SELECT id FROM events WHERE day = 5");
+        assert_eq!(
+            ids,
+            (1..=1000).filter(|i| i % 37 == 5).join(","),
+            "a scan when only a later index column is compared"
+        );
+    }
+
+    #[test]
+    fn descending_index() {
+        let mut file = File::open("fixtures/indexes.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for id in [1, 2, 500, 999, 1000] {
+            let sql = format!("SELECT id FROM words WHERE word = 'word{:04}'", id);
+            assert_eq!(
+                db_file.query(&sql).unwrap().rows,
+                vec![vec![SerialValue::Int64(id)]],
+                "{}",
+                sql
+            );
+        }
+        assert!(db_file
+            .query("SELECT id FROM words WHERE word = 'word1001'")
+            .unwrap()
+            .rows
+            .is_empty());
+    }
+
+    #[test]
+    fn autoindexes() {
+        let mut file = File::open("fixtures/unique.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let index_page = |db_file: &mut DBFile, table, column| {
+            db_file
+                .get_index_page(table, column)
+                .unwrap()
+                .map(|(page, column)| (page, column.name))
+        };
+        assert_eq!(
+            index_page(&mut db_file, "users", "email"),
+            Some((3, String::from("email")))
+        );
+        assert_eq!(
+            index_page(&mut db_file, "users", "team"),
+            Some((4, String::from("team")))
+        );
+        assert_eq!(index_page(&mut db_file, "users", "badge"), None);
+        assert_eq!(
+            index_page(&mut db_file, "codes", "code"),
+            Some((6, String::from("code")))
+        );
+
+        for (sql, expected) in [
+            (
+                "SELECT name FROM users WHERE email = 'grace@example.com'",
+                "Grace",
+            ),
+            // The index is ordered by badge within a team, not by rowid
+            (
+                "SELECT name, badge FROM users WHERE team = 'red'",
+                "Ada|7,Grace|3",
+            ),
+            (
+                "SELECT name, badge FROM users WHERE team = 'red' AND badge > 5",
+                "Ada|7",
+            ),
+            ("SELECT label FROM codes WHERE code = 'b'", "second"),
+            ("SELECT label FROM codes WHERE code = 'c'", ""),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn partial_index_not_used() {
+        let mut file = File::open("fixtures/partial_indexes.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert_eq!(db_file.get_index_page("tags", "tag").unwrap(), None);
+        assert_eq!(
+            db_file
+                .query("SELECT id FROM tags WHERE tag = 'a'")
+                .unwrap()
+                .rows,
+            vec![
+                vec![SerialValue::Int64(1)],
+                vec![SerialValue::Int64(2)],
+                vec![SerialValue::Int64(3)]
+            ]
+        );
+    }
+
+    #[test]
+    fn group_by() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut query = |sql: &str| {
+            db_file
+                .query(sql)
+                .unwrap()
+                .rows
+                .iter()
+                .map(|row| row.iter().join("|"))
+                .collect_vec()
+        };
+        assert_eq!(
+            query(
+                "SELECT country, industry, count(*), sum(employees), max(name) FROM companies \
+                 GROUP BY country, industry"
+            ),
+            [
+                "FR|food|2|420|Vehement",
+                "UK|pharma|1|800|Umbrella",
+                "UK|tech|1|(null)|Hooli",
+                "US|finance|1|50|Initech",
+                "US|tech|2|350|Globex",
+            ]
+        );
+        assert_eq!(
+            query(
+                "SELECT country, count(employees) FROM companies GROUP BY country \
+                 ORDER BY count(*) DESC, country LIMIT 2"
+            ),
+            ["US|3", "FR|2"]
+        );
+        assert_eq!(
+            query(
+                "SELECT count(*), min(employees), avg(employees), total(employees) FROM companies"
+            ),
+            ["7|50|270|1620"]
+        );
+        assert_eq!(
+            query("SELECT count(*), sum(employees) FROM companies WHERE country = 'DE'"),
+            ["0|(null)"]
+        );
+    }
+
+    #[test]
+    fn order_by_position_and_alias() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            (
+                "SELECT name, employees FROM companies WHERE employees > 100 ORDER BY 2",
+                "Vehement|120,Globex|250,Soylent|300,Umbrella|800",
+            ),
+            (
+                "SELECT name, employees * 2 AS doubled FROM companies \
+                 WHERE country = 'US' ORDER BY doubled DESC",
+                "Globex|500,Acme|200,Initech|100",
+            ),
+            (
+                "SELECT country, count(*) AS total FROM companies GROUP BY country \
+                 ORDER BY total DESC, 1",
+                "US|3,FR|2,UK|2",
+            ),
+            // Positions count the columns `*` expands to
+            (
+                "SELECT * FROM companies WHERE industry = 'food' ORDER BY 5 DESC",
+                "6|Soylent|FR|food|300,7|Vehement|FR|food|120",
+            ),
+            // An alias takes precedence over a table column of the same name
+            (
+                "SELECT name, -employees AS employees FROM companies \
+                 WHERE industry = 'tech' AND employees > 0 ORDER BY employees",
+                "Globex|-250,Acme|-100",
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+        for sql in [
+            "SELECT name FROM companies ORDER BY 2",
+            "SELECT name FROM companies ORDER BY 0",
+        ] {
+            assert_eq!(
+                db_file.query(sql).unwrap_err().to_string(),
+                "ORDER BY term out of range - should be between 1 and 1",
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn rowid_alias_column() {
+        let mut file = File::open("fixtures/rowids.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            ("SELECT * FROM notes", "first|1|(null),second|2|x"),
+            ("SELECT id, body FROM notes WHERE id = 2", "2|second"),
+            // Without a rowid alias, NULLs are left alone, whether the rows are read by a full
+            // scan or through an index
+            ("SELECT * FROM plain", "(null)|x,5|y,(null)|y"),
+            ("SELECT a, b FROM plain WHERE b = 'x'", "(null)|x"),
+            ("SELECT a, b FROM plain WHERE b = 'y'", "5|y,(null)|y"),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn case_expressions() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            (
+                "SELECT name, CASE WHEN employees >= 300 THEN 'large' \
+                 WHEN employees >= 100 THEN 'medium' END AS size, \
+                 CASE country WHEN 'US' THEN 'America' WHEN 'UK' THEN 'Europe' END \
+                 FROM companies WHERE size != 'medium' OR country = 'FR'",
+                "Umbrella|large|Europe,Soylent|large|(null),Vehement|medium|(null)",
+            ),
+            (
+                "SELECT name, CASE WHEN employees > 200 THEN 'big' ELSE 'small' END AS size \
+                 FROM companies ORDER BY size, name",
+                "Globex|big,Soylent|big,Umbrella|big,\
+                 Acme|small,Hooli|small,Initech|small,Vehement|small",
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn scalar_min_max() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let rows = db_file
+            .query(
+                "SELECT name, max(employees, 200), min(employees, id * 100) FROM companies \
+                 WHERE country = 'UK'",
+            )
+            .unwrap()
+            .rows;
+        assert_eq!(
+            rows.iter().map(|row| row.iter().join("|")).join(","),
+            "Umbrella|800|400,Hooli|(null)|(null)"
+        );
+        // A single argument is still the aggregate
+        let rows = db_file
+            .query("SELECT max(employees), min(max(employees, 200)) FROM companies")
+            .unwrap()
+            .rows;
+        assert_eq!(rows[0].iter().join("|"), "800|200");
+    }
+
+    #[test]
+    fn where_alias() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            (
+                "SELECT name, employees * 2 AS doubled FROM companies WHERE doubled > 200 \
+                 ORDER BY name",
+                "Globex|500,Soylent|600,Umbrella|1600,Vehement|240",
+            ),
+            (
+                "SELECT name, employees + 1 AS total FROM companies \
+                 WHERE TOTAL BETWEEN 100 AND 200 OR name = 'Hooli'",
+                "Acme|101,Hooli|(null),Vehement|121",
+            ),
+            // A table column takes precedence over an alias of the same name
+            (
+                "SELECT name AS country FROM companies WHERE country = 'UK'",
+                "Umbrella,Hooli",
+            ),
+            ("SELECT id AS key FROM companies WHERE key = 3", "3"),
+            (
+                "SELECT length(name) AS n, length(name) AS n FROM companies WHERE n = 7",
+                "7|7,7|7",
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+
+        for (sql, error) in [
+            (
+                "SELECT COUNT(*) AS n FROM companies WHERE n > 1",
+                "misuse of aliased aggregate n",
+            ),
+            (
+                "SELECT employees AS x, name AS x FROM companies WHERE x > 1",
+                "ambiguous column name: x (aliases different expressions)",
+            ),
+        ] {
+            assert_eq!(
+                db_file.query(sql).unwrap_err().to_string(),
+                error,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn having() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            (
+                "SELECT country, sum(employees) FROM companies GROUP BY country \
+                 HAVING sum(employees) >= 420 ORDER BY country",
+                "FR|420,UK|800",
+            ),
+            (
+                "SELECT country FROM companies GROUP BY country HAVING count(*) != 2",
+                "US",
+            ),
+            (
+                "SELECT country, max(employees) AS most FROM companies GROUP BY country \
+                 HAVING max(employees) BETWEEN 100 AND 300 ORDER BY most",
+                "US|250,FR|300",
+            ),
+            // Grouped columns can be tested too, alongside aggregates of the group
+            (
+                "SELECT country, industry, count(*) FROM companies GROUP BY country, industry \
+                 HAVING country <> 'US' AND count(*) < 2",
+                "UK|pharma|1,UK|tech|1",
+            ),
+            ("SELECT count(*) FROM companies HAVING count(*) > 5", "7"),
+            ("SELECT count(*) FROM companies HAVING count(*) > 7", ""),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+        assert!(db_file
+            .query("SELECT country FROM companies GROUP BY country HAVING size > 1")
+            .is_err());
+    }
+
+    #[test]
+    fn mixed_aggregate_columns() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for sql in [
+            "SELECT count(*), name FROM companies",
+            "SELECT name, max(employees) FROM companies",
+            "SELECT count(*) + employees FROM companies",
+            "SELECT *, count(*) FROM companies",
+        ] {
+            assert_eq!(
+                db_file.query(sql).unwrap_err().to_string(),
+                "aggregate and non-aggregate columns cannot be mixed without GROUP BY",
+                "{}",
+                sql
+            );
+        }
+        for (sql, expected) in [
+            ("SELECT count(*), 'companies' FROM companies", "7|companies"),
+            (
+                "SELECT max(employees) - min(employees) FROM companies",
+                "750",
+            ),
+            (
+                "SELECT country, count(*) FROM companies GROUP BY country ORDER BY country",
+                "FR|2,UK|2,US|3",
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| row.iter().join("|")).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn distinct() {
+        let mut file = File::open("fixtures/companies.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut query = |sql: &str| {
+            db_file
+                .query(sql)
+                .unwrap()
+                .rows
+                .iter()
+                .map(|row| row.iter().join("|"))
+                .collect_vec()
+        };
+        assert_eq!(
+            query("SELECT DISTINCT industry FROM companies ORDER BY industry LIMIT 2"),
+            ["finance", "food"]
+        );
+        assert_eq!(
+            query(
+                "SELECT DISTINCT industry FROM companies ORDER BY industry DESC LIMIT 2 OFFSET 1"
+            ),
+            ["pharma", "food"]
+        );
+        assert_eq!(
+            query("SELECT DISTINCT country, count(*) > 2 FROM companies GROUP BY country"),
+            ["FR|0", "UK|0", "US|1"]
+        );
+        assert_eq!(
+            query("SELECT DISTINCT country FROM companies"),
+            ["US", "UK", "FR"]
+        );
+    }
+
+    #[test]
+    fn rowid_range() {
+        let mut file = File::open("fixtures/tables.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut query = |sql: &str| {
+            let pages_loaded = db_file.pages_loaded();
+            let rows = db_file
+                .query(sql)
+                .unwrap()
+                .rows
+                .iter()
+                .map(|row| row.iter().join("|"))
+                .collect_vec();
+            (rows, db_file.pages_loaded() - pages_loaded)
+        };
+
+        let (_, full_scan) = query("SELECT id FROM numbers WHERE name = 'number 250'");
+        for (sql, expected) in [
+            (
+                "SELECT name FROM numbers WHERE id BETWEEN 100 AND 102",
+                vec!["number 100", "number 101", "number 102"],
+            ),
+            (
+                "SELECT id FROM numbers WHERE rowid > 498",
+                vec!["499", "500"],
+            ),
+            (
+                "SELECT id FROM numbers WHERE 3 >= oid AND _rowid_ != 2",
+                vec!["1", "3"],
+            ),
+            ("SELECT id FROM numbers WHERE id = 250", vec!["250"]),
+            ("SELECT id FROM numbers WHERE id < 1", vec![]),
+        ] {
+            let (rows, pages) = query(sql);
+            assert_eq!(rows, expected, "{}", sql);
+            assert!(pages <= 3, "{} read {} of {} pages", sql, pages, full_scan);
+        }
+    }
+
+    #[test]
+    fn column_collation() {
+        let mut file = File::open("fixtures/collations.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        assert_eq!(db_file.get_index_page("words", "word").unwrap(), None);
+        for (sql, expected) in [
+            ("SELECT id FROM words WHERE word = 'abc'", "1,2"),
+            ("SELECT id FROM words WHERE 'abc' = word", "1,2"),
+            (
+                "SELECT id FROM words WHERE word = 'abc' COLLATE NOCASE",
+                "1,3",
+            ),
+            (
+                "SELECT id FROM words WHERE word BETWEEN 'abc' AND 'abc'",
+                "1,2",
+            ),
+            ("SELECT id FROM words ORDER BY word, id", "3,1,2,4"),
+            (
+                "SELECT id FROM words ORDER BY word COLLATE BINARY",
+                "3,1,2,4",
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| &row[0]).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn collated_index() {
+        let mut file = File::open("fixtures/collations.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, plan, expected) in [
+            (
+                "SELECT id FROM tags WHERE tag = 'tag3'",
+                "SEARCH tags USING INDEX idx_tags_tag (tag=?)",
+                100,
+            ),
+            (
+                "SELECT id FROM tags WHERE 'TAG3' = tag",
+                "SEARCH tags USING INDEX idx_tags_tag (tag=?)",
+                100,
+            ),
+            (
+                "SELECT id FROM tags WHERE tag COLLATE BINARY = 'Tag3'",
+                "SEARCH tags USING INDEX idx_tags_tag_binary (tag=?)",
+                33,
+            ),
+            (
+                "SELECT id FROM tags WHERE tag = 'Tag3' COLLATE BINARY",
+                "SEARCH tags USING INDEX idx_tags_tag_binary (tag=?)",
+                33,
+            ),
+            (
+                "SELECT id FROM tags WHERE tag COLLATE RTRIM = 'Tag3'",
+                "SCAN tags",
+                33,
+            ),
+            (
+                "SELECT id FROM words WHERE word = 'abc'",
+                "SEARCH words USING INDEX idx_words_word (word=?)",
+                2,
+            ),
+            (
+                "SELECT id FROM words WHERE word = 'abc' COLLATE NOCASE",
+                "SCAN words",
+                2,
+            ),
+        ] {
+            let details = db_file.explain_query_plan(sql).unwrap().details();
+            assert_eq!(details, [plan], "{}", sql);
+            assert_eq!(db_file.query(sql).unwrap().rows.len(), expected, "{}", sql);
+        }
+        assert_eq!(
+            db_file
+                .query("SELECT count(*) FROM tags WHERE tag = 'tAg3'")
+                .unwrap()
+                .rows,
+            [[SerialValue::Int64(100)]]
+        );
+    }
+
+    #[test]
+    fn blobs() {
+        let mut file = File::open("fixtures/blobs.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        for (sql, expected) in [
+            ("SELECT name FROM files WHERE data = X'00'", "a"),
+            ("SELECT name FROM files WHERE data = x'0001'", "b"),
+            ("SELECT name FROM files WHERE data > X'00'", "b,c"),
+            (
+                "SELECT name FROM files WHERE data < X'01' AND data >= X''",
+                "a,b,d",
+            ),
+            ("SELECT name FROM files WHERE data = '00'", ""),
+            ("SELECT name FROM files ORDER BY data", "f,g,e,d,a,b,c"),
+            ("SELECT name FROM files ORDER BY data DESC", "c,b,a,d,e,g,f"),
+            ("SELECT X'CAFE' = X'CAFE', X'CAFE' < X'CB'", "1"),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| &row[0]).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn large_rowids() {
+        let mut file = File::open("fixtures/large_rowids.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let label_3 = std::iter::once(i64::MIN)
+            .chain(
+                (1..=300)
+                    .filter(|i| i % 5 == 3)
+                    .map(|i| i * 30_000_000_000_000_000 - 4_500_000_000_000_000_000),
+            )
+            .chain([i64::MAX])
+            .collect_vec();
+        for (sql, expected) in [
+            (
+                "SELECT id FROM items WHERE label = 'label 3'",
+                label_3.iter().join(","),
+            ),
+            (
+                "SELECT id FROM items WHERE id >= 0 AND label = 'label 3'",
+                label_3.iter().filter(|&&id| id >= 0).join(","),
+            ),
+            (
+                "SELECT label FROM items WHERE rowid = 9223372036854775807",
+                String::from("label 3"),
+            ),
+            (
+                "SELECT COUNT(*) FROM items WHERE rowid < 0",
+                String::from("150"),
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter().map(|row| &row[0]).join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn short_records() {
+        let mut file = File::open("fixtures/altered.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let mut widths = vec![];
+        db_file
+            .for_each_row("items", |row| {
+                widths.push(row.len());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(widths, [6, 6, 6]);
+
+        for (sql, expected) in [
+            (
+                "SELECT name, note FROM items WHERE id < 3",
+                "old|(null),new|added",
+            ),
+            (
+                "SELECT quantity, unit, price FROM items",
+                "-1|each|0.5,-1|each|0.5,3|box|2.5",
+            ),
+            ("SELECT name FROM items WHERE quantity = -1", "old,new"),
+            ("SELECT note FROM items WHERE name = 'old'", "(null)"),
+            (
+                "SELECT note, label FROM items JOIN labels ON items.id = item_id",
+                "(null)|first,added|second",
+            ),
+        ] {
+            let rows = db_file.query(sql).unwrap().rows;
+            assert_eq!(
+                rows.iter()
+                    .map(|row| row.iter().map(|v| v.to_string()).join("|"))
+                    .join(","),
+                expected,
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn column_metadata() {
+        let mut file = File::open("sample.db").unwrap();
+        let mut db_file = DBFile::new(&mut file).unwrap();
+        let result = db_file
+            .query("SELECT id, name AS apple FROM apples WHERE color = 'Red'")
+            .unwrap();
+        assert_eq!(
+            result.columns,
+            [
+                ColumnMeta {
+                    name: String::from("id"),
+                    declared_type: Some(String::from("integer")),
+                },
+                ColumnMeta {
+                    name: String::from("apple"),
+                    declared_type: Some(String::from("text")),
+                },
+            ]
+        );
+        assert_eq!(
+            result.rows,
+            [vec![
+                SerialValue::Int64(2),
+                SerialValue::Text(String::from("Fuji"))
+            ]]
+        );
+    }
+}
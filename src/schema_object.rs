@@ -1,9 +1,14 @@
 use anyhow::{bail, Context, Result};
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 
+use crate::eval::no_such_column;
 use crate::serial_value::SerialValue;
-use crate::sql::sql::sql_statement;
-use crate::sql::Statement;
+use crate::sql::parse_statement;
+use crate::sql::{ColumnDefinition, Statement};
+
+/// The start of the names of SQLite's internal tables and indexes.
+const INTERNAL_PREFIX: &str = "sqlite_";
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ObjectType {
@@ -25,6 +30,17 @@ impl ObjectType {
     }
 }
 
+impl Display for ObjectType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Table => write!(f, "table"),
+            Self::Index => write!(f, "index"),
+            Self::View => write!(f, "view"),
+            Self::Trigger => write!(f, "trigger"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct SchemaObject {
@@ -32,10 +48,21 @@ pub struct SchemaObject {
     pub name: String,
     pub table_name: String,
     pub root_page: Option<usize>,
+    /// The statement that created the object. It's empty for the indexes SQLite creates itself
+    /// for `UNIQUE` and `PRIMARY KEY` constraints, which have no SQL.
     pub sql: String,
 }
 
 impl SchemaObject {
+    /// Whether this is one of the objects SQLite creates for itself, such as `sqlite_sequence` or
+    /// the indexes for `UNIQUE` constraints. Their names all start with `sqlite_`, which SQLite
+    /// doesn't allow for user objects.
+    pub fn is_internal(&self) -> bool {
+        self.name
+            .get(..INTERNAL_PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(INTERNAL_PREFIX))
+    }
+
     pub fn from(cell: Vec<SerialValue>) -> Result<Self> {
         // We're only handling the schema table here. Columns should be ordered as follows:
         //
@@ -70,7 +97,8 @@ impl SchemaObject {
         };
 
         let root_page = match &cell[3] {
-            SerialValue::Null => None,
+            // Views and triggers have no b-tree and store a root page of 0
+            SerialValue::Null | SerialValue::Zero => None,
             SerialValue::Int8(value) => Some(*value as usize),
             v => match v.as_usize() {
                 Some(u) => Some(u),
@@ -80,6 +108,7 @@ impl SchemaObject {
 
         let sql = match &cell[4] {
             SerialValue::Text(value) => value.to_string(),
+            SerialValue::Null if object_type == ObjectType::Index => String::new(),
             _ => bail!("unexpected serial value for sql"),
         };
 
@@ -92,20 +121,127 @@ impl SchemaObject {
         })
     }
 
-    pub fn column_order(&self) -> Result<Vec<String>> {
-        match sql_statement(&self.sql).context("parsing create table statement")? {
+    /// The table's column definitions, in the order values are stored in its rows.
+    pub fn columns(&self) -> Result<Vec<ColumnDefinition>> {
+        match parse_statement(&self.sql).context("parsing create table statement")? {
             Statement::CreateTable(create_statement) => Ok(create_statement.columns),
             _ => bail!("invalid create statement"),
         }
     }
 
+    pub fn column_order(&self) -> Result<Vec<String>> {
+        Ok(self
+            .columns()?
+            .into_iter()
+            .map(|column| column.name)
+            .collect())
+    }
+
+    /// Maps each column name to its position in the table's rows. Column names are case
+    /// insensitive in SQLite, so names are lowercased and should be looked up in lowercase.
     pub fn column_map(&self) -> Result<HashMap<String, usize>> {
         Ok(self
             .column_order()
             .context("retrieving column order")?
             .iter()
             .enumerate()
-            .map(|(ind, col)| (col.clone(), ind))
+            .map(|(ind, col)| (col.to_ascii_lowercase(), ind))
             .collect::<HashMap<_, _>>())
     }
+
+    /// The value of the named column in one of this table's rows. The name is case insensitive.
+    pub fn value<'a>(&self, row: &'a [SerialValue], column: &str) -> Result<&'a SerialValue> {
+        let column_map = self.column_map()?;
+        let index = *column_map
+            .get(&column.to_ascii_lowercase())
+            .ok_or_else(|| no_such_column(column, &column_map))?;
+        row.get(index)
+            .with_context(|| format!("row has no value for column {}", column))
+    }
+}
+
+/// A one line summary such as `index idx_name on users (root page 4)`. The table name is only
+/// shown for objects that belong to a different table, and the root page for objects that have one.
+impl Display for SchemaObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.object_type, self.name)?;
+        if self.table_name != self.name {
+            write!(f, " on {}", self.table_name)?;
+        }
+        if let Some(root_page) = self.root_page {
+            write!(f, " (root page {})", root_page)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_object(
+        object_type: ObjectType,
+        name: &str,
+        table_name: &str,
+        root_page: Option<usize>,
+    ) -> SchemaObject {
+        SchemaObject {
+            object_type,
+            name: String::from(name),
+            table_name: String::from(table_name),
+            root_page,
+            sql: String::new(),
+        }
+    }
+
+    #[test]
+    fn value() {
+        let table = SchemaObject {
+            sql: String::from(
+                "CREATE TABLE apples (id integer primary key, name text, color text)",
+            ),
+            ..schema_object(ObjectType::Table, "apples", "apples", Some(2))
+        };
+        let row = [
+            SerialValue::Null,
+            SerialValue::Text(String::from("Fuji")),
+            SerialValue::Text(String::from("Red")),
+        ];
+        assert_eq!(
+            table.value(&row, "name").unwrap(),
+            &SerialValue::Text(String::from("Fuji"))
+        );
+        assert_eq!(
+            table.value(&row, "COLOR").unwrap(),
+            &SerialValue::Text(String::from("Red"))
+        );
+        assert_eq!(
+            table.value(&row, "size").unwrap_err().to_string(),
+            "no such column: size (valid columns are: id, name, color)"
+        );
+        assert_eq!(
+            table.value(&row[..2], "color").unwrap_err().to_string(),
+            "row has no value for column color"
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            schema_object(ObjectType::Table, "users", "users", Some(4)).to_string(),
+            "table users (root page 4)"
+        );
+        assert_eq!(
+            schema_object(ObjectType::Index, "idx_users_name", "users", Some(5)).to_string(),
+            "index idx_users_name on users (root page 5)"
+        );
+        assert_eq!(
+            schema_object(ObjectType::View, "adults", "adults", None).to_string(),
+            "view adults"
+        );
+        assert_eq!(
+            schema_object(ObjectType::Trigger, "users_insert", "users", None).to_string(),
+            "trigger users_insert on users"
+        );
+    }
 }
@@ -1,10 +1,12 @@
 use anyhow::{bail, Context, Result};
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::io::Read;
 
 use byteorder::{BigEndian, ReadBytesExt};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// https://www.sqlite.org/fileformat2.html#record_format
 pub enum SerialValue {
     Null,
@@ -22,7 +24,7 @@ pub enum SerialValue {
 }
 
 impl SerialValue {
-    pub fn read(serial_type: u64, reader: &mut impl Read) -> Result<Self> {
+    pub fn read(serial_type: u64, encoding: TextEncoding, reader: &mut impl Read) -> Result<Self> {
         match serial_type {
             0 => Ok(Self::Null),
             1 => Ok(Self::Int8(reader.read_i8()?)),
@@ -34,7 +36,7 @@ impl SerialValue {
             7 => Ok(Self::Float64(reader.read_f64::<BigEndian>()?)),
             8 => Ok(Self::Zero),
             9 => Ok(Self::One),
-            10 | 11 => bail!("unexpected reserved serial value"),
+            10 | 11 => bail!("serial type {} is reserved for internal use", serial_type),
             t if t % 2 == 0 => Ok(Self::Blob({
                 let mut bytes = vec![0u8; (t as usize - 12) / 2];
                 reader
@@ -47,22 +49,94 @@ impl SerialValue {
                 reader
                     .read_exact(&mut bytes)
                     .context("reading text bytes")?;
-                String::from_utf8(bytes).context("text bytes into String")?
+                encoding.decode(bytes)?
             })),
             _ => unreachable!(),
         }
     }
 
+    /// Encodes the value for a record, the inverse of `read`: returns the serial type and the bytes
+    /// that follow the record header. Text is encoded as UTF-8.
+    pub fn encode(&self) -> (u64, Vec<u8>) {
+        match self {
+            Self::Null => (0, vec![]),
+            Self::Int8(i) => (1, i.to_be_bytes().to_vec()),
+            Self::Int16(i) => (2, i.to_be_bytes().to_vec()),
+            Self::Int24(i) => (3, i.to_be_bytes()[1..].to_vec()),
+            Self::Int32(i) => (4, i.to_be_bytes().to_vec()),
+            Self::Int48(i) => (5, i.to_be_bytes()[2..].to_vec()),
+            Self::Int64(i) => (6, i.to_be_bytes().to_vec()),
+            Self::Float64(f) => (7, f.to_be_bytes().to_vec()),
+            Self::Zero => (8, vec![]),
+            Self::One => (9, vec![]),
+            Self::Blob(b) => (b.len() as u64 * 2 + 12, b.clone()),
+            Self::Text(t) => (t.len() as u64 * 2 + 13, t.as_bytes().to_vec()),
+        }
+    }
+
+    /// The value as a rowid. Rowids of 0 and 1 are stored with the `Zero` and `One` serial types.
     pub fn as_rowid(&self) -> Option<u64> {
+        self.as_i64().map(|i| i as u64)
+    }
+
+    /// The value of any of the integer serial types, including the `Zero` and `One` constants.
+    pub fn as_i64(&self) -> Option<i64> {
         match self {
-            Self::Int8(i) => Some(*i as u64),
-            Self::Int16(i) => Some(*i as u64),
-            Self::Int24(i) | Self::Int32(i) => Some(*i as u64),
-            Self::Int48(i) | Self::Int64(i) => Some(*i as u64),
+            Self::Zero => Some(0),
+            Self::One => Some(1),
+            Self::Int8(i) => Some(*i as i64),
+            Self::Int16(i) => Some(*i as i64),
+            Self::Int24(i) | Self::Int32(i) => Some(*i as i64),
+            Self::Int48(i) | Self::Int64(i) => Some(*i),
             _ => None,
         }
     }
 
+    /// The value of any integer or real serial type as a float.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Float64(f) => Some(*f),
+            v => v.as_i64().map(|i| i as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_blob(&self) -> Option<&[u8]> {
+        match self {
+            Self::Blob(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes in a text or blob value, or `None` for other types. Text is measured
+    /// in its UTF-8 encoding.
+    pub fn byte_len(&self) -> Option<usize> {
+        self.as_str()
+            .map(str::len)
+            .or_else(|| self.as_blob().map(<[u8]>::len))
+    }
+
+    /// The name of the value's storage class, as returned by SQL's `typeof()`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Float64(_) => "real",
+            Self::Text(_) => "text",
+            Self::Blob(_) => "blob",
+            _ => "integer",
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
     pub fn as_usize(&self) -> Option<usize> {
         match self {
             Self::Int8(i) => Some(*i as usize),
@@ -74,6 +148,229 @@ impl SerialValue {
     }
 }
 
+impl SerialValue {
+    /// Compares two values using SQLite's sort order: NULL sorts first, followed by integers and
+    /// reals compared numerically, then text and finally blobs, both compared byte-wise.
+    pub fn compare(&self, other: &Self) -> Ordering {
+        if let (Some(a), Some(b)) = (self.as_blob(), other.as_blob()) {
+            return a.cmp(b);
+        }
+        match (self, other) {
+            (Self::Text(a), Self::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (a, b) => match (a.as_i64(), b.as_i64(), a.as_f64(), b.as_f64()) {
+                (Some(a), Some(b), _, _) => a.cmp(&b),
+                // partial_cmp treats -0.0 and 0.0 as equal, as SQLite does
+                (_, _, Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or_else(|| a.total_cmp(&b)),
+                _ => a.storage_class_rank().cmp(&b.storage_class_rank()),
+            },
+        }
+    }
+
+    /// Converts the value as it would be when stored in a column with the given affinity. Text
+    /// that looks like a number becomes one in a numeric column and numbers become text in a
+    /// text column. Anything else is left as it is.
+    pub fn apply_affinity(&self, affinity: Affinity) -> Self {
+        match (affinity, self) {
+            (Affinity::Text, Self::Float64(n)) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                Self::Text(format!("{:.1}", n))
+            }
+            (Affinity::Text, v) if v.as_f64().is_some() => Self::Text(v.to_string()),
+            (Affinity::Numeric | Affinity::Integer | Affinity::Real, Self::Text(t)) => {
+                let t = t.trim();
+                match (t.parse::<i64>(), t.parse::<f64>()) {
+                    (Ok(i), _) if affinity != Affinity::Real => Self::Int64(i),
+                    // A real with no fractional part is stored as an integer, except with REAL
+                    (_, Ok(n))
+                        if affinity != Affinity::Real && n.fract() == 0.0 && n.abs() < 9e18 =>
+                    {
+                        Self::Int64(n as i64)
+                    }
+                    (_, Ok(n)) if n.is_finite() => Self::Float64(n),
+                    _ => self.clone(),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// The value written as an SQL literal that evaluates back to it, as `quote()` gives it: text
+    /// is quoted with its quotes doubled, blobs are hex literals and reals keep a decimal point.
+    /// Very large and very small reals are written with an exponent, like `1.0e+20`.
+    pub fn to_sql_literal(&self) -> String {
+        match self {
+            Self::Null => String::from("NULL"),
+            Self::Text(t) => format!("'{}'", t.replace('\'', "''")),
+            // SQLite writes infinities as a literal too large to be anything else
+            Self::Float64(n) if n.is_infinite() => {
+                format!("{}9.0e+999", if *n < 0.0 { "-" } else { "" })
+            }
+            Self::Float64(n) if n.abs() >= 1e15 || (*n != 0.0 && n.abs() < 1e-4) => {
+                let formatted = format!("{:e}", n);
+                let (mantissa, exponent) = formatted
+                    .split_once('e')
+                    .expect("formatted with an exponent");
+                let (sign, digits) = match exponent.strip_prefix('-') {
+                    Some(digits) => ('-', digits),
+                    None => ('+', exponent),
+                };
+                let point = if mantissa.contains('.') { "" } else { ".0" };
+                format!("{}{}e{}{:0>2}", mantissa, point, sign, digits)
+            }
+            Self::Float64(n) if n.fract() == 0.0 => format!("{:.1}", n),
+            v => v.to_string(),
+        }
+    }
+
+    /// Whether two values are the same for GROUP BY and DISTINCT, following SQLite: numbers are
+    /// equal when their values are, whichever integer or real serial type stores them, text and
+    /// blobs are equal when their bytes are, and NULLs are equal to each other (unlike with `=`).
+    pub fn canonical_eq(&self, other: &Self) -> bool {
+        self.compare(other).is_eq()
+    }
+
+    /// Hashes the value consistently with `canonical_eq`.
+    pub fn canonical_hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Self::Null => 0u8.hash(state),
+            Self::Text(t) => (2u8, t).hash(state),
+            Self::Blob(b) => (3u8, b).hash(state),
+            // Integers and reals are compared as floats, so they're hashed that way too. Adding
+            // zero turns -0.0 into 0.0.
+            v => (1u8, (v.as_f64().unwrap_or_default() + 0.0).to_bits()).hash(state),
+        }
+    }
+
+    /// Compares two values like `compare`, but with text compared using the given collating
+    /// sequence.
+    pub fn compare_collated(&self, other: &Self, collation: Collation) -> Ordering {
+        match (self, other, collation) {
+            (Self::Text(a), Self::Text(b), Collation::NoCase) => a
+                .bytes()
+                .map(|b| b.to_ascii_lowercase())
+                .cmp(b.bytes().map(|b| b.to_ascii_lowercase())),
+            (Self::Text(a), Self::Text(b), Collation::RTrim) => a
+                .trim_end_matches(' ')
+                .as_bytes()
+                .cmp(b.trim_end_matches(' ').as_bytes()),
+            _ => self.compare(other),
+        }
+    }
+
+    fn storage_class_rank(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::Text(_) => 2,
+            Self::Blob(_) => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// The built-in collating sequences, which decide how text values are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Collation {
+    /// Byte-wise comparison
+    #[default]
+    Binary,
+    /// Byte-wise comparison after folding ASCII letters to lowercase
+    NoCase,
+    /// Byte-wise comparison ignoring trailing spaces
+    RTrim,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "BINARY" => Ok(Self::Binary),
+            "NOCASE" => Ok(Self::NoCase),
+            "RTRIM" => Ok(Self::RTrim),
+            _ => bail!("no such collation sequence: {}", name),
+        }
+    }
+}
+
+/// A column's preferred storage class, which values compared with it are converted to.
+/// https://www.sqlite.org/datatype3.html#type_affinity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Affinity {
+    Text,
+    Numeric,
+    Integer,
+    Real,
+    Blob,
+}
+
+impl Affinity {
+    /// The affinity of a column declared with this type, following SQLite's rules, which look
+    /// for substrings of the type name in order.
+    pub fn from_type_name(type_name: Option<&str>) -> Self {
+        let Some(type_name) = type_name.map(|t| t.to_ascii_uppercase()) else {
+            return Self::Blob;
+        };
+        let has = |s: &str| type_name.contains(s);
+        if has("INT") {
+            Self::Integer
+        } else if has("CHAR") || has("CLOB") || has("TEXT") {
+            Self::Text
+        } else if has("BLOB") {
+            Self::Blob
+        } else if has("REAL") || has("FLOA") || has("DOUB") {
+            Self::Real
+        } else {
+            Self::Numeric
+        }
+    }
+}
+
+/// The encoding of every text value in a database, set by the database header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl TextEncoding {
+    /// Decodes the text encoding field of the database header.
+    pub fn from_header_value(value: u32) -> Result<Self> {
+        match value {
+            1 => Ok(Self::Utf8),
+            2 => Ok(Self::Utf16Le),
+            3 => Ok(Self::Utf16Be),
+            _ => bail!("unknown text encoding {} in database header", value),
+        }
+    }
+
+    /// Decodes text stored in this encoding. UTF-16 is always read with the endianness the header
+    /// declares, and a leading byte-order mark is dropped rather than becoming part of the text.
+    pub fn decode(self, bytes: Vec<u8>) -> Result<String> {
+        const BYTE_ORDER_MARK: u16 = 0xfeff;
+        let units = |to_u16: fn([u8; 2]) -> u16| {
+            if !bytes.len().is_multiple_of(2) {
+                bail!("UTF-16 text has an odd number of bytes: {}", bytes.len());
+            }
+            let mut code_units = bytes
+                .chunks_exact(2)
+                .map(|pair| to_u16([pair[0], pair[1]]))
+                .peekable();
+            code_units.next_if_eq(&BYTE_ORDER_MARK);
+            Ok(code_units.collect::<Vec<_>>())
+        };
+        match self {
+            Self::Utf8 => String::from_utf8(bytes).context("text bytes into String"),
+            Self::Utf16Le => {
+                String::from_utf16(&units(u16::from_le_bytes)?).context("UTF-16 text into String")
+            }
+            Self::Utf16Be => {
+                String::from_utf16(&units(u16::from_be_bytes)?).context("UTF-16 text into String")
+            }
+        }
+    }
+}
+
+/// Blobs are shown as a hex literal, e.g. `X'CAFE'`. The alternate form (`{:#}`) only gives their
+/// length, e.g. `<blob 2 bytes>`, for places where the full contents would be too wide.
 impl Display for SerialValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -86,7 +383,255 @@ impl Display for SerialValue {
             Self::Int48(i) | Self::Int64(i) => write!(f, "{}", i),
             Self::Float64(n) => write!(f, "{}", n),
             Self::Text(t) => write!(f, "{}", t),
-            Self::Blob(v) => write!(f, "{:?}", v),
+            Self::Blob(v) if f.alternate() => write!(f, "<blob {} bytes>", v.len()),
+            Self::Blob(v) => {
+                write!(f, "X'")?;
+                for byte in v {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_name() {
+        let values = [
+            (SerialValue::Null, "null"),
+            (SerialValue::Zero, "integer"),
+            (SerialValue::One, "integer"),
+            (SerialValue::Int8(1), "integer"),
+            (SerialValue::Int16(1), "integer"),
+            (SerialValue::Int24(1), "integer"),
+            (SerialValue::Int32(1), "integer"),
+            (SerialValue::Int48(1), "integer"),
+            (SerialValue::Int64(1), "integer"),
+            (SerialValue::Float64(1.5), "real"),
+            (SerialValue::Text(String::from("a")), "text"),
+            (SerialValue::Blob(vec![1]), "blob"),
+        ];
+        for (value, type_name) in values {
+            assert_eq!(value.type_name(), type_name);
+            assert_eq!(value.is_null(), type_name == "null");
+        }
+    }
+
+    #[test]
+    fn compare() {
+        let text = |t: &str| SerialValue::Text(String::from(t));
+        assert_eq!(
+            SerialValue::Null.compare(&SerialValue::Zero),
+            Ordering::Less
+        );
+        assert_eq!(
+            SerialValue::Null.compare(&SerialValue::Null),
+            Ordering::Equal
+        );
+        assert_eq!(
+            SerialValue::Int8(2).compare(&SerialValue::Int64(10)),
+            Ordering::Less
+        );
+        assert_eq!(
+            SerialValue::One.compare(&SerialValue::Float64(1.0)),
+            Ordering::Equal
+        );
+        assert_eq!(
+            SerialValue::Float64(2.5).compare(&SerialValue::Int16(2)),
+            Ordering::Greater
+        );
+        assert_eq!(SerialValue::Int64(1000).compare(&text("1")), Ordering::Less);
+        assert_eq!(text("B").compare(&text("a")), Ordering::Less);
+        assert_eq!(
+            text("z").compare(&SerialValue::Blob(vec![0])),
+            Ordering::Less
+        );
+        assert_eq!(
+            SerialValue::Blob(vec![0, 1]).compare(&SerialValue::Blob(vec![1])),
+            Ordering::Less
+        );
+        assert_eq!(
+            SerialValue::Blob(vec![1]).compare(&SerialValue::Blob(vec![1, 0])),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn canonical_eq() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |value: &SerialValue| {
+            let mut hasher = DefaultHasher::new();
+            value.canonical_hash(&mut hasher);
+            hasher.finish()
+        };
+        let equal_groups = [
+            vec![
+                SerialValue::One,
+                SerialValue::Int8(1),
+                SerialValue::Int24(1),
+                SerialValue::Int64(1),
+                SerialValue::Float64(1.0),
+            ],
+            vec![
+                SerialValue::Zero,
+                SerialValue::Int16(0),
+                SerialValue::Float64(0.0),
+                SerialValue::Float64(-0.0),
+            ],
+            vec![SerialValue::Null, SerialValue::Null],
+            vec![SerialValue::Text(String::from("1"))],
+            vec![SerialValue::Float64(1.5)],
+        ];
+        for (i, group) in equal_groups.iter().enumerate() {
+            for a in group {
+                for b in group {
+                    assert!(a.canonical_eq(b), "{:?} == {:?}", a, b);
+                    assert_eq!(hash(a), hash(b), "hash({:?}) == hash({:?})", a, b);
+                }
+                for other in equal_groups.iter().skip(i + 1).flatten() {
+                    assert!(!a.canonical_eq(other), "{:?} != {:?}", a, other);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut values = vec![
+            SerialValue::Null,
+            SerialValue::Zero,
+            SerialValue::One,
+            SerialValue::Text(String::new()),
+            SerialValue::Text(String::from("héllo wörld")),
+            SerialValue::Text("x".repeat(1000)),
+            SerialValue::Blob(vec![]),
+            SerialValue::Blob((0..=255).collect()),
+        ];
+        for n in [
+            0i64, 1, -1, 127, -128, 0x7fff, -0x8000, 0x7f_ffff, -0x80_0000,
+        ] {
+            values.push(SerialValue::Int8(n as i8));
+            values.push(SerialValue::Int16(n as i16));
+            values.push(SerialValue::Int24((n as i32) << 8 >> 8));
+            values.push(SerialValue::Int32(n as i32));
+            values.push(SerialValue::Int48(n << 16 >> 16));
+            values.push(SerialValue::Int64(n));
+            values.push(SerialValue::Float64(n as f64 / 3.0));
+        }
+        values.extend([
+            SerialValue::Int32(i32::MIN),
+            SerialValue::Int48((1 << 47) - 1),
+            SerialValue::Int48(-(1 << 47)),
+            SerialValue::Int64(i64::MAX),
+            SerialValue::Int64(i64::MIN),
+            SerialValue::Float64(f64::MAX),
+            SerialValue::Float64(-0.0),
+        ]);
+
+        for value in values {
+            let (serial_type, bytes) = value.encode();
+            let mut reader = bytes.as_slice();
+            let read = SerialValue::read(serial_type, TextEncoding::Utf8, &mut reader).unwrap();
+            assert_eq!(read, value);
+            assert!(reader.is_empty(), "{:?} left bytes unread", value);
+            assert_eq!(read.type_name(), value.type_name());
+        }
+    }
+
+    #[test]
+    fn utf16_byte_order_mark() {
+        let le = |text: &str| text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be = |text: &str| text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        for (encoding, bytes, expected) in [
+            (TextEncoding::Utf16Le, le("\u{feff}héllo"), "héllo"),
+            (TextEncoding::Utf16Le, le("héllo"), "héllo"),
+            (TextEncoding::Utf16Le, le("\u{feff}"), ""),
+            (TextEncoding::Utf16Be, be("\u{feff}héllo"), "héllo"),
+            // Only the first mark is taken as one; later ones are part of the text
+            (TextEncoding::Utf16Le, le("\u{feff}\u{feff}a"), "\u{feff}a"),
+            (TextEncoding::Utf16Le, le("a\u{feff}"), "a\u{feff}"),
+        ] {
+            assert_eq!(encoding.decode(bytes).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        let text = SerialValue::Text(String::from("héllo"));
+        let blob = SerialValue::Blob(vec![0xca, 0xfe]);
+        assert_eq!(text.as_str(), Some("héllo"));
+        assert_eq!(text.as_blob(), None);
+        assert_eq!(text.byte_len(), Some(6));
+        assert_eq!(blob.as_blob(), Some([0xca, 0xfe].as_slice()));
+        assert_eq!(blob.as_str(), None);
+        assert_eq!(blob.byte_len(), Some(2));
+        assert_eq!(SerialValue::Int8(42).byte_len(), None);
+        assert_eq!(SerialValue::Null.byte_len(), None);
+    }
+
+    #[test]
+    fn affinity() {
+        for (type_name, affinity) in [
+            (Some("INTEGER"), Affinity::Integer),
+            (Some("varchar(16)"), Affinity::Text),
+            (Some("BLOB"), Affinity::Blob),
+            (None, Affinity::Blob),
+            (Some("double precision"), Affinity::Real),
+            (Some("decimal(10, 2)"), Affinity::Numeric),
+            // "INT" is found before "FLOA"
+            (Some("floating point"), Affinity::Integer),
+        ] {
+            assert_eq!(Affinity::from_type_name(type_name), affinity);
+        }
+
+        let text = |t: &str| SerialValue::Text(String::from(t));
+        for (value, affinity, expected) in [
+            (SerialValue::Int8(42), Affinity::Text, text("42")),
+            (SerialValue::Float64(2.0), Affinity::Text, text("2.0")),
+            (text(" 42 "), Affinity::Integer, SerialValue::Int64(42)),
+            (text("2.0"), Affinity::Numeric, SerialValue::Int64(2)),
+            (text("2"), Affinity::Real, SerialValue::Float64(2.0)),
+            (text("4.5"), Affinity::Integer, SerialValue::Float64(4.5)),
+            (text("4x"), Affinity::Integer, text("4x")),
+            (text("42"), Affinity::Blob, text("42")),
+        ] {
+            assert_eq!(value.apply_affinity(affinity), expected);
+        }
+    }
+
+    #[test]
+    fn compare_collated() {
+        let text = |t: &str| SerialValue::Text(String::from(t));
+        assert_eq!(
+            text("abc").compare_collated(&text("abc  "), Collation::RTrim),
+            Ordering::Equal
+        );
+        assert_eq!(
+            text("abc").compare_collated(&text("abc  "), Collation::Binary),
+            Ordering::Less
+        );
+        assert_eq!(
+            text("ABC").compare_collated(&text("abc"), Collation::NoCase),
+            Ordering::Equal
+        );
+        assert_eq!(
+            text(" abc").compare_collated(&text("abc"), Collation::RTrim),
+            Ordering::Less
+        );
+        assert!(Collation::from_name("rtrim").is_ok());
+        assert!(Collation::from_name("unicode").is_err());
+    }
+
+    #[test]
+    fn display_blob() {
+        let blob = SerialValue::Blob(vec![0xAB; 1000]);
+        assert_eq!(blob.to_string(), format!("X'{}'", "AB".repeat(1000)));
+        assert_eq!(format!("{:#}", blob), "<blob 1000 bytes>");
+        assert_eq!(SerialValue::Blob(vec![0xCA, 0xFE]).to_string(), "X'CAFE'");
+    }
+}
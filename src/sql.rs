@@ -1,101 +1,1083 @@
 extern crate peg;
 
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, Result};
+
+use crate::serial_value::{Affinity, SerialValue};
+
 #[derive(Debug, PartialEq)]
 pub enum Statement {
     Select(SelectStatement),
     CreateTable(CreateTableStatement),
     CreateIndex(CreateIndexStatement),
+    CreateView(CreateViewStatement),
+    Drop(DropStatement),
+    Insert(InsertStatement),
+    /// `UPDATE` and `DELETE` can't run against a read-only database, so only the name of the
+    /// table they'd change is kept.
+    Update(String),
+    Delete(String),
+    /// `EXPLAIN QUERY PLAN` describes how the select would be run instead of running it.
+    ExplainQueryPlan(SelectStatement),
 }
 
 #[derive(Debug, PartialEq)]
 pub struct CreateTableStatement {
     pub name: String,
-    pub columns: Vec<String>,
+    pub columns: Vec<ColumnDefinition>,
+    /// Whether the table was declared `STRICT`, which makes SQLite enforce the column types
+    pub strict: bool,
+    /// The columns of each `PRIMARY KEY` and `UNIQUE` constraint, in the order they're declared.
+    /// SQLite backs each one with an index named `sqlite_autoindex_<table>_<n>`, counting from 1
+    /// in this order. An `INTEGER PRIMARY KEY` is the rowid, so it needs no index and isn't listed,
+    /// and neither is a constraint on the same columns as an earlier one.
+    pub unique_keys: Vec<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ColumnDefinition {
+    pub name: String,
+    /// The declared type as written, e.g. `varchar(16)`. SQLite allows columns without a type.
+    pub type_name: Option<String>,
+    /// Whether this column alone is the table's primary key, declared either on the column or
+    /// with a table constraint
+    pub primary_key: bool,
+    /// The collating sequence named with a `COLLATE` constraint, if any
+    pub collation: Option<String>,
+    /// The value given with a `DEFAULT` constraint, when it's a literal. Other defaults, such as
+    /// `CURRENT_TIMESTAMP` or an expression, are left out.
+    pub default: Option<SerialValue>,
+}
+
+/// Names that refer to the rowid, unless the table has a column of the same name.
+pub const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
+
+/// The position of the table's rowid alias column, if it has one.
+pub fn rowid_column(columns: &[ColumnDefinition]) -> Option<usize> {
+    columns.iter().position(ColumnDefinition::is_rowid_alias)
+}
+
+impl ColumnDefinition {
+    pub fn affinity(&self) -> Affinity {
+        Affinity::from_type_name(self.type_name.as_deref())
+    }
+
+    /// Whether the column is another name for the rowid, which is the case for an `INTEGER
+    /// PRIMARY KEY`. Its value isn't stored in the record, only as the rowid.
+    pub fn is_rowid_alias(&self) -> bool {
+        self.primary_key
+            && self
+                .type_name
+                .as_deref()
+                .is_some_and(|t| t.eq_ignore_ascii_case("integer"))
+    }
+}
+
+/// An entry in the body of a `CREATE TABLE` statement.
+enum TableItem {
+    /// A column definition, and whether it has a `UNIQUE` constraint
+    Column(ColumnDefinition, bool),
+    /// A `PRIMARY KEY` table constraint and the columns it lists
+    PrimaryKey(Vec<String>),
+    /// A `UNIQUE` table constraint and the columns it lists
+    Unique(Vec<String>),
+    OtherConstraint,
+}
+
+/// One of the options that can follow the column list of a `CREATE TABLE` statement
+#[derive(PartialEq)]
+enum TableOption {
+    Strict,
+    WithoutRowid,
+}
+
+/// A constraint on a single column. Only the ones that affect reading the table are kept.
+enum ColumnConstraint {
+    PrimaryKey,
+    Unique,
+    Collate(String),
+    Default(SerialValue),
+    Other,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct CreateIndexStatement {
     pub name: String,
     pub table_name: String,
-    pub columns: Vec<String>,
+    pub columns: Vec<IndexColumn>,
+    /// The condition limiting which rows a partial index covers
+    pub where_clause: Option<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IndexColumn {
+    /// The column name, or the expression as written for an expression index
+    pub name: String,
+    /// The indexed expression, if this isn't a plain column
+    pub expr: Option<Expr>,
+    /// Whether the index b-tree stores this column in descending order
+    pub descending: bool,
+    /// The collating sequence named with `COLLATE`, if any
+    pub collation: Option<String>,
+}
+
+impl CreateIndexStatement {
+    /// The names of the indexed columns, in index order.
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// Whether the index only covers some rows, or indexes expressions rather than columns, and
+    /// so can't stand in for a plain lookup on a column.
+    pub fn is_partial_or_expression(&self) -> bool {
+        self.where_clause.is_some() || self.columns.iter().any(|c| c.expr.is_some())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CreateViewStatement {
+    pub name: String,
+    pub select: SelectStatement,
+}
+
+/// An `INSERT INTO ... VALUES` statement. `REPLACE` and `INSERT OR ...` are parsed the same way.
+#[derive(Debug, PartialEq)]
+pub struct InsertStatement {
+    pub table: String,
+    /// The columns given after the table name, or `None` to fill every column in order
+    pub columns: Option<Vec<String>>,
+    pub rows: Vec<Vec<Expr>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DropStatement {
+    pub object_type: DropObjectType,
+    pub name: String,
+    pub if_exists: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DropObjectType {
+    Table,
+    Index,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct SelectStatement {
-    pub select: Vec<String>,
-    pub from: String,
-    pub where_clause: Option<WhereClause>,
+    /// Whether duplicate result rows are removed, as with `SELECT DISTINCT`
+    pub distinct: bool,
+    pub select: Vec<ResultColumn>,
+    /// `None` for a select of constant expressions, e.g. `SELECT 1 + 1`
+    pub from: Option<String>,
+    /// Further tables joined onto `from`, in order
+    pub joins: Vec<Join>,
+    pub where_clause: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    /// The condition each group has to meet, which may use aggregates of the group's rows
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+impl SelectStatement {
+    pub fn is_count_star(&self) -> bool {
+        matches!(
+            self.select.as_slice(),
+            [ResultColumn { expr: Expr::Function { name, args }, .. }]
+                if name == "count" && args == &[Expr::Star]
+        )
+    }
+
+    /// Whether the statement produces one row per group of rows rather than one per row, either
+    /// because it has a GROUP BY or HAVING clause or because it selects an aggregate function.
+    pub fn is_aggregate(&self) -> bool {
+        !self.group_by.is_empty()
+            || self.having.is_some()
+            || self.select.iter().any(|c| c.expr.contains_aggregate())
+    }
+}
+
+/// A selected expression along with the name given to it with `AS`, if any.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResultColumn {
+    pub expr: Expr,
+    pub alias: Option<String>,
+}
+
+impl ResultColumn {
+    /// The name of the output column: the alias if one was given, otherwise the expression as it
+    /// was written.
+    pub fn name(&self) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None => self.expr.to_string(),
+        }
+    }
+}
+
+/// An inner join (or cross join when there's no `ON` constraint) of another table.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Join {
+    pub table: String,
+    pub on: Option<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub descending: bool,
+    /// Whether NULLs sort before all other values. SQLite treats NULL as the smallest value, so
+    /// this defaults to true for ascending and false for descending terms.
+    pub nulls_first: bool,
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct WhereClause {
-    pub column: String,
-    pub value: String,
+pub enum Expr {
+    Literal(SerialValue),
+    Column(String),
+    /// A column qualified with its table name, e.g. `apples.name`
+    QualifiedColumn {
+        table: String,
+        column: String,
+    },
+    /// Only valid as the argument of `count(*)` or as a result column, where it stands for every
+    /// column
+    Star,
+    Unary {
+        op: UnaryOperator,
+        expr: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOperator,
+        right: Box<Expr>,
+    },
+    /// `expr BETWEEN low AND high`, or `NOT BETWEEN` when `negated` is set
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    /// An expression with a collating sequence to use when comparing it, e.g.
+    /// `name COLLATE NOCASE`
+    Collate {
+        expr: Box<Expr>,
+        collation: String,
+    },
+    /// Function names are normalised to lowercase
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// `CASE [operand] WHEN ... THEN ... [ELSE ...] END`. Each branch is a `WHEN` expression with
+    /// the result for it. With an operand, the first branch whose `WHEN` value equals it is taken,
+    /// otherwise the first whose `WHEN` condition is true. Without any, the result is that of
+    /// `ELSE`, or NULL.
+    Case {
+        operand: Option<Box<Expr>>,
+        branches: Vec<(Expr, Expr)>,
+        else_result: Option<Box<Expr>>,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// Case-insensitive pattern matching with `%` and `_` wildcards
+    Like,
+    NotLike,
+    /// Case-sensitive pattern matching with Unix-style `*`, `?` and `[...]` wildcards
+    Glob,
+    NotGlob,
+    And,
+    Or,
+}
+
+impl BinaryOperator {
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::Equal
+            | Self::NotEqual
+            | Self::Like
+            | Self::NotLike
+            | Self::Glob
+            | Self::NotGlob => 4,
+            Self::Less | Self::LessEqual | Self::Greater | Self::GreaterEqual => 5,
+            Self::Add | Self::Subtract => 6,
+            Self::Multiply | Self::Divide | Self::Modulo => 7,
+            Self::Concat => 8,
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Self::Equal
+                | Self::NotEqual
+                | Self::Less
+                | Self::LessEqual
+                | Self::Greater
+                | Self::GreaterEqual
+        )
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Modulo => "%",
+            Self::Concat => "||",
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::Like => "LIKE",
+            Self::NotLike => "NOT LIKE",
+            Self::Glob => "GLOB",
+            Self::NotGlob => "NOT GLOB",
+            Self::And => "AND",
+            Self::Or => "OR",
+        }
+    }
+}
+
+impl Expr {
+    /// Whether this is a call to an aggregate function, which is computed over a group of rows.
+    pub fn is_aggregate(&self) -> bool {
+        matches!(
+            self,
+            Self::Function { name, args }
+                if args.len() == 1
+                    && matches!(name.as_str(), "count" | "sum" | "total" | "avg" | "min" | "max")
+        )
+    }
+
+    /// Whether an aggregate function is called anywhere within this expression.
+    pub fn contains_aggregate(&self) -> bool {
+        let mut found = false;
+        self.visit(&mut |e| found |= e.is_aggregate());
+        found
+    }
+
+    /// Whether a column is referenced anywhere other than in the arguments of an aggregate
+    /// function, so that its value depends on which row of a group it's taken from.
+    pub fn has_bare_column(&self) -> bool {
+        let is_column = |e: &Expr| matches!(e, Self::Column(_) | Self::QualifiedColumn { .. });
+        let (mut columns, mut aggregated) = (0, 0);
+        self.visit(&mut |e| {
+            if is_column(e) {
+                columns += 1;
+            } else if e.is_aggregate() {
+                e.visit(&mut |arg| aggregated += is_column(arg) as usize);
+            }
+        });
+        columns > aggregated
+    }
+
+    /// Rebuilds this expression with `f` applied to each of its direct sub-expressions.
+    pub fn try_map_children(&self, mut f: impl FnMut(&Expr) -> Result<Expr>) -> Result<Expr> {
+        Ok(match self {
+            Self::Unary { op, expr } => Self::Unary {
+                op: *op,
+                expr: Box::new(f(expr)?),
+            },
+            Self::Binary { left, op, right } => Self::Binary {
+                left: Box::new(f(left)?),
+                op: *op,
+                right: Box::new(f(right)?),
+            },
+            Self::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => Self::Between {
+                expr: Box::new(f(expr)?),
+                low: Box::new(f(low)?),
+                high: Box::new(f(high)?),
+                negated: *negated,
+            },
+            Self::Collate { expr, collation } => Self::Collate {
+                expr: Box::new(f(expr)?),
+                collation: collation.clone(),
+            },
+            Self::Function { name, args } => Self::Function {
+                name: name.clone(),
+                args: args.iter().map(f).collect::<Result<_>>()?,
+            },
+            Self::Case {
+                operand,
+                branches,
+                else_result,
+            } => Self::Case {
+                operand: operand.as_deref().map(&mut f).transpose()?.map(Box::new),
+                branches: branches
+                    .iter()
+                    .map(|(when, then)| Ok((f(when)?, f(then)?)))
+                    .collect::<Result<_>>()?,
+                else_result: else_result
+                    .as_deref()
+                    .map(&mut f)
+                    .transpose()?
+                    .map(Box::new),
+            },
+            Self::Literal(_) | Self::Column(_) | Self::QualifiedColumn { .. } | Self::Star => {
+                self.clone()
+            }
+        })
+    }
+
+    /// Calls `f` on this expression and every expression nested inside it.
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Expr)) {
+        f(self);
+        match self {
+            Self::Unary { expr, .. } => expr.visit(f),
+            Self::Binary { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            Self::Between {
+                expr, low, high, ..
+            } => {
+                expr.visit(f);
+                low.visit(f);
+                high.visit(f);
+            }
+            Self::Collate { expr, .. } => expr.visit(f),
+            Self::Function { args, .. } => args.iter().for_each(|arg| arg.visit(f)),
+            Self::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                operand.iter().for_each(|operand| operand.visit(f));
+                for (when, then) in branches {
+                    when.visit(f);
+                    then.visit(f);
+                }
+                else_result.iter().for_each(|result| result.visit(f));
+            }
+            Self::Literal(_) | Self::Column(_) | Self::QualifiedColumn { .. } | Self::Star => {}
+        }
+    }
+}
+
+/// Formats the expression back into SQL. This is used as the output column name for selected
+/// expressions.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal(v) => write!(f, "{}", v.to_sql_literal()),
+            Self::Column(name) => write!(f, "{}", name),
+            Self::QualifiedColumn { table, column } => write!(f, "{}.{}", table, column),
+            Self::Star => write!(f, "*"),
+            Self::Unary {
+                op: UnaryOperator::Negate,
+                expr,
+            } => match expr.as_ref() {
+                Self::Binary { .. } => write!(f, "-({})", expr),
+                _ => write!(f, "-{}", expr),
+            },
+            Self::Unary {
+                op: UnaryOperator::Not,
+                expr,
+            } => match expr.as_ref() {
+                Self::Binary { op, .. } if op.precedence() <= BinaryOperator::And.precedence() => {
+                    write!(f, "NOT ({})", expr)
+                }
+                _ => write!(f, "NOT {}", expr),
+            },
+            Self::Binary { left, op, right } => {
+                // Only parenthesise operands that bind less tightly than this operator
+                match left.as_ref() {
+                    Self::Binary { op: l, .. } if l.precedence() < op.precedence() => {
+                        write!(f, "({})", left)?
+                    }
+                    _ => write!(f, "{}", left)?,
+                }
+                write!(f, " {} ", op.symbol())?;
+                match right.as_ref() {
+                    Self::Binary { op: r, .. } if r.precedence() <= op.precedence() => {
+                        write!(f, "({})", right)
+                    }
+                    _ => write!(f, "{}", right),
+                }
+            }
+            Self::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => {
+                // The operands are parenthesised if they contain a binary operator that binds
+                // less tightly than addition, since `AND` in particular would be ambiguous
+                let operand = |e: &Expr| match e {
+                    Self::Binary { op, .. }
+                        if op.precedence() < BinaryOperator::Add.precedence() =>
+                    {
+                        format!("({})", e)
+                    }
+                    Self::Between { .. } => format!("({})", e),
+                    _ => e.to_string(),
+                };
+                let not = if *negated { "NOT " } else { "" };
+                write!(
+                    f,
+                    "{} {}BETWEEN {} AND {}",
+                    operand(expr),
+                    not,
+                    operand(low),
+                    operand(high)
+                )
+            }
+            Self::Collate { expr, collation } => match expr.as_ref() {
+                Self::Binary { .. } | Self::Unary { .. } | Self::Between { .. } => {
+                    write!(f, "({}) COLLATE {}", expr, collation)
+                }
+                _ => write!(f, "{} COLLATE {}", expr, collation),
+            },
+            Self::Function { name, args } => {
+                write!(f, "{}(", name)?;
+                for (ind, arg) in args.iter().enumerate() {
+                    if ind > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Self::Case {
+                operand,
+                branches,
+                else_result,
+            } => {
+                write!(f, "CASE")?;
+                if let Some(operand) = operand {
+                    write!(f, " {}", operand)?;
+                }
+                for (when, then) in branches {
+                    write!(f, " WHEN {} THEN {}", when, then)?;
+                }
+                if let Some(result) = else_result {
+                    write!(f, " ELSE {}", result)?;
+                }
+                write!(f, " END")
+            }
+        }
+    }
+}
+
+fn binary(left: Expr, op: BinaryOperator, right: Expr) -> Expr {
+    Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    }
+}
+
+/// Decodes the digits of a blob literal such as `X'CAFE'`, two to a byte. `None` if there is an
+/// odd number of digits.
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Parses a single statement. Errors give the offset into `sql` where parsing failed and what
+/// was expected there.
+pub fn parse_statement(sql: &str) -> Result<Statement> {
+    sql::sql_statement(sql).map_err(|err| {
+        anyhow!(
+            "parse error at offset {}: expected {}",
+            err.location.offset,
+            err.expected
+        )
+    })
+}
+
+/// Whether `sql` is a whole statement rather than the start of one that continues on a later
+/// line, which is the case while a quote or parenthesis is left open or when parsing runs out of
+/// input. Other input the parser rejects counts as complete, so that the error can be reported
+/// rather than waiting for more.
+pub fn is_complete_statement(sql: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    for c in sql.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    if quote.is_some() || depth > 0 {
+        return false;
+    }
+
+    match sql::sql_statement(sql) {
+        Ok(_) => true,
+        Err(err) => err.location.offset < sql.trim_end().len(),
+    }
 }
 
 peg::parser! {
     pub grammar sql() for str {
         pub rule sql_statement() -> Statement
-        = _ s:(select_statement() / create_table_statement() / create_index_statement()) _ { s }
+        = _ s:(explain_statement() / select_statement() / create_table_statement() / create_index_statement() / create_view_statement() / drop_statement() / insert_statement() / write_statement()) _ (";" _)? { s }
+
+        rule select_statement() -> Statement = s:select() { Statement::Select(s) }
 
-        rule select_statement() -> Statement
-        = i("SELECT") _ fields:(select() ++ ("," _)) _ i("FROM") _ from:ident() _ w:(where_clause())? {
-            Statement::Select(SelectStatement {
+        rule explain_statement() -> Statement
+        = i("EXPLAIN") _ i("QUERY") _ i("PLAN") _ s:select() { Statement::ExplainQueryPlan(s) }
+
+        rule select() -> SelectStatement
+        = i("SELECT") _ distinct:(i("DISTINCT") !ident_char() _)? fields:(result_column() ++ (_ "," _)) _ from:(i("FROM") _ t:ident() { t })? joins:(_ j:join() { j })* _ w:(where_clause())? _ g:(group_by())? _ h:(having())? _ o:(order_by())? _ l:(limit())? {
+            let (limit, offset) = match l {
+                Some((limit, offset)) => (Some(limit), offset),
+                None => (None, None),
+            };
+            SelectStatement {
+                distinct: distinct.is_some(),
                 select: fields,
                 from,
+                joins,
                 where_clause: w,
-            })
+                group_by: g.unwrap_or_default(),
+                having: h,
+                order_by: o.unwrap_or_default(),
+                limit,
+                offset,
+            }
         }
 
-        rule where_clause() -> WhereClause
-        = i("WHERE") _ column:(ident()) _ "=" _ "'" value:$([^'\'']*) "'" {
-            WhereClause {
-                column,
-                value: String::from(value),
+        rule result_column() -> ResultColumn
+        = "*" { ResultColumn { expr: Expr::Star, alias: None } }
+        / expr:expr() alias:(_ i("AS") !ident_char() _ a:ident() { a })? { ResultColumn { expr, alias } }
+
+        /// Returns the limit and optional offset. Note that in the older `LIMIT <skip>, <count>`
+        /// form the offset comes first.
+        rule limit() -> (u64, Option<u64>)
+        = i("LIMIT") _ offset:integer() _ "," _ limit:integer() { (limit, Some(offset)) }
+        / i("LIMIT") _ limit:integer() offset:(_ i("OFFSET") _ o:integer() { o })? { (limit, offset) }
+
+        rule integer() -> u64
+        = n:$(['0'..='9']+) {? n.parse().or(Err("integer")) }
+
+        rule where_clause() -> Expr
+        = i("WHERE") !ident_char() _ e:expr() { e }
+
+        rule group_by() -> Vec<Expr>
+        = i("GROUP") _ i("BY") _ e:(expr() ++ (_ "," _)) { e }
+
+        rule having() -> Expr
+        = i("HAVING") !ident_char() _ e:expr() { e }
+
+        rule join() -> Join
+        = ("," / (i("INNER") _ / i("CROSS") _)? i("JOIN") !ident_char()) _ table:ident()
+          on:(_ i("ON") !ident_char() _ e:expr() { e })? {
+            Join { table, on }
+        }
+
+        rule order_by() -> Vec<OrderingTerm>
+        = i("ORDER") _ i("BY") _ terms:(ordering_term() ++ (_ "," _)) { terms }
+
+        rule ordering_term() -> OrderingTerm
+        = expr:expr() descending:(_ d:sort_direction() { d })? nulls_first:(_ n:nulls_order() { n })? {
+            let descending = descending.unwrap_or(false);
+            OrderingTerm {
+                expr,
+                descending,
+                nulls_first: nulls_first.unwrap_or(!descending),
             }
         }
 
+        rule sort_direction() -> bool
+        = i("ASC") !ident_char() { false } / i("DESC") !ident_char() { true }
+
+        rule nulls_order() -> bool
+        = i("NULLS") _ first:(i("FIRST") { true } / i("LAST") { false }) { first }
+
         rule create_table_statement() -> Statement
-        = i("CREATE") _ i("TABLE") _ name:(ident()) _ "(" _ c:(column() ++ (_ "," _)) _ ")"  {
+        = i("CREATE") _ i("TABLE") _ if_not_exists() name:(ident()) _ "(" _ c:(table_item() ++ (_ "," _)) _ ")"
+          options:(_ o:(table_option() ++ (_ "," _)) { o })? {
+            let strict = options.unwrap_or_default().contains(&TableOption::Strict);
+            let mut columns = vec![];
+            let mut primary_key = vec![];
+            // Each key is listed along with whether it's the primary key
+            let mut keys = vec![];
+            for item in c {
+                match item {
+                    TableItem::Column(column, unique) => {
+                        if column.primary_key {
+                            keys.push((true, vec![column.name.clone()]));
+                        }
+                        if unique {
+                            keys.push((false, vec![column.name.clone()]));
+                        }
+                        columns.push(column);
+                    }
+                    TableItem::PrimaryKey(names) => {
+                        keys.push((true, names.clone()));
+                        primary_key = names;
+                    }
+                    TableItem::Unique(names) => keys.push((false, names)),
+                    TableItem::OtherConstraint => {}
+                }
+            }
+            if let [key] = primary_key.as_slice() {
+                for column in &mut columns {
+                    column.primary_key |= column.name.eq_ignore_ascii_case(key);
+                }
+            }
+            let is_rowid = |key: &[String]| match key {
+                [name] => columns
+                    .iter()
+                    .any(|c| c.is_rowid_alias() && c.name.eq_ignore_ascii_case(name)),
+                _ => false,
+            };
+            let mut unique_keys: Vec<Vec<String>> = vec![];
+            for (is_primary_key, key) in keys {
+                let is_duplicate = unique_keys.iter().any(|earlier| {
+                    earlier.len() == key.len()
+                        && earlier.iter().zip(&key).all(|(a, b)| a.eq_ignore_ascii_case(b))
+                });
+                if is_duplicate || is_primary_key && is_rowid(&key) {
+                    continue;
+                }
+                unique_keys.push(key);
+            }
             Statement::CreateTable(CreateTableStatement {
                 name,
-                columns: c.into_iter().collect()
+                columns,
+                strict,
+                unique_keys,
             })
         }
 
+        rule table_option() -> TableOption
+        = i("STRICT") !ident_char() { TableOption::Strict }
+        / i("WITHOUT") _ i("ROWID") !ident_char() { TableOption::WithoutRowid }
+
         rule create_index_statement() -> Statement
-        = i("CREATE") _ i("INDEX") _ name:(ident()) _ i("ON") _ table_name:(ident()) _ "(" _ c:(ident() ++ (_ "," _)) _ ")"  {
+        = i("CREATE") _ i("INDEX") _ if_not_exists() name:(ident()) _ i("ON") _ table_name:(ident()) _ "(" _ columns:(index_column() ++ (_ "," _)) _ ")"
+          where_clause:(_ w:where_clause() { w })? {
             Statement::CreateIndex(CreateIndexStatement {
                 name,
                 table_name,
-                columns: c.into_iter().collect()
+                columns,
+                where_clause,
             })
         }
 
-        rule select() -> String = s:(i("COUNT(*)") / ident()) { s }
+        rule index_column() -> IndexColumn
+        = target:index_target() collation:(_ i("COLLATE") _ c:ident() { c })? descending:(_ d:sort_direction() { d })? {
+            let (name, expr) = target;
+            IndexColumn {
+                name,
+                expr,
+                descending: descending.unwrap_or(false),
+                collation,
+            }
+        }
+
+        rule index_target() -> (String, Option<Expr>)
+        = name:ident() &(_ ("," / ")" / i("COLLATE") / sort_direction())) { (name, None) }
+        / e:expr() { (e.to_string(), Some(e)) }
+
+        rule create_view_statement() -> Statement
+        = i("CREATE") _ i("VIEW") _ if_not_exists() name:ident() _ i("AS") _ select:select() {
+            Statement::CreateView(CreateViewStatement { name, select })
+        }
+
+        rule drop_statement() -> Statement
+        = i("DROP") _ object_type:(i("TABLE") { DropObjectType::Table } / i("INDEX") { DropObjectType::Index }) _
+          if_exists:(i("IF") _ i("EXISTS") _)? name:ident() {
+            Statement::Drop(DropStatement {
+                object_type,
+                name,
+                if_exists: if_exists.is_some(),
+            })
+        }
+
+        rule insert_statement() -> Statement
+        = (i("INSERT") / i("REPLACE")) !ident_char() _ (i("OR") _ ident() _)? i("INTO") _ table:ident() _
+          columns:("(" _ c:(ident() ++ (_ "," _)) _ ")" _ { c })?
+          i("VALUES") _ rows:(("(" _ r:(expr() ++ (_ "," _)) _ ")" { r }) ++ (_ "," _)) {
+            Statement::Insert(InsertStatement {
+                table,
+                columns,
+                rows,
+            })
+        }
 
-        rule column() -> String = n:(ident() / quoted_ident()) _ ident() (_ ident())* { n }
+        /// `UPDATE` and `DELETE` are only parsed as far as the table they change; the rest of the
+        /// statement is skipped, since it can never be run.
+        rule write_statement() -> Statement
+        = i("UPDATE") !ident_char() _ (i("OR") _ ident() _)? table:ident() _ i("SET") !ident_char() [_]* {
+            Statement::Update(table)
+        }
+        / i("DELETE") _ i("FROM") _ table:ident() [_]* { Statement::Delete(table) }
+
+        rule if_not_exists()
+        = (i("IF") _ i("NOT") _ i("EXISTS") _)?
 
-        rule ident() -> String
-        = iquote()? chars:$(alpha() [ 'a'..='z' | 'A'..='Z' | '_' | '0'..='9']*) iquote()? { chars.to_string() }
+        rule expr() -> Expr = precedence!{
+            x:(@) _ i("OR") !ident_char() _ y:@ { binary(x, BinaryOperator::Or, y) }
+            --
+            x:(@) _ i("AND") !ident_char() _ y:@ { binary(x, BinaryOperator::And, y) }
+            --
+            i("NOT") !ident_char() _ e:@ { Expr::Unary { op: UnaryOperator::Not, expr: Box::new(e) } }
+            --
+            x:(@) _ ("==" / "=") _ y:@ { binary(x, BinaryOperator::Equal, y) }
+            x:(@) _ ("!=" / "<>") _ y:@ { binary(x, BinaryOperator::NotEqual, y) }
+            x:(@) _ i("LIKE") !ident_char() _ y:@ { binary(x, BinaryOperator::Like, y) }
+            x:(@) _ i("NOT") !ident_char() _ i("LIKE") !ident_char() _ y:@ { binary(x, BinaryOperator::NotLike, y) }
+            x:(@) _ i("GLOB") !ident_char() _ y:@ { binary(x, BinaryOperator::Glob, y) }
+            x:(@) _ i("NOT") !ident_char() _ i("GLOB") !ident_char() _ y:@ { binary(x, BinaryOperator::NotGlob, y) }
+            x:(@) _ negated:(i("NOT") !ident_char() _)? i("BETWEEN") !ident_char() _ low:arithmetic() _ i("AND") !ident_char() _ high:arithmetic() {
+                Expr::Between {
+                    expr: Box::new(x),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                    negated: negated.is_some(),
+                }
+            }
+            --
+            x:(@) _ "<=" _ y:@ { binary(x, BinaryOperator::LessEqual, y) }
+            x:(@) _ "<" _ y:@ { binary(x, BinaryOperator::Less, y) }
+            x:(@) _ ">=" _ y:@ { binary(x, BinaryOperator::GreaterEqual, y) }
+            x:(@) _ ">" _ y:@ { binary(x, BinaryOperator::Greater, y) }
+            --
+            e:arithmetic() { e }
+        }
+
+        /// The operators that bind more tightly than comparisons. These are separate so that the
+        /// bounds of `BETWEEN` can be parsed without consuming its `AND`.
+        rule arithmetic() -> Expr = precedence!{
+            x:(@) _ "+" _ y:@ { binary(x, BinaryOperator::Add, y) }
+            x:(@) _ "-" _ y:@ { binary(x, BinaryOperator::Subtract, y) }
+            --
+            x:(@) _ "*" _ y:@ { binary(x, BinaryOperator::Multiply, y) }
+            x:(@) _ "/" _ y:@ { binary(x, BinaryOperator::Divide, y) }
+            x:(@) _ "%" _ y:@ { binary(x, BinaryOperator::Modulo, y) }
+            --
+            x:(@) _ "||" _ y:@ { binary(x, BinaryOperator::Concat, y) }
+            --
+            "-" _ e:@ { Expr::Unary { op: UnaryOperator::Negate, expr: Box::new(e) } }
+            "+" _ e:@ { e }
+            --
+            x:(@) _ i("COLLATE") !ident_char() _ collation:ident() {
+                Expr::Collate { expr: Box::new(x), collation }
+            }
+            --
+            e:primary() { e }
+        }
 
+        rule primary() -> Expr
+        = v:literal() { Expr::Literal(v) }
+        / case()
+        / function()
+        / table:ident() _ "." _ column:ident() { Expr::QualifiedColumn { table, column } }
+        / c:ident() { Expr::Column(c) }
+        / "(" _ e:expr() _ ")" { e }
+
+        rule function() -> Expr
+        = name:bare_ident() _ "(" _ args:(("*" { Expr::Star }) / expr()) ** (_ "," _) _ ")" {
+            Expr::Function {
+                name: name.to_lowercase(),
+                args,
+            }
+        }
+
+        rule case() -> Expr
+        = i("CASE") !ident_char() _ operand:(!(i("WHEN") !ident_char()) e:expr() _ { e })?
+          branches:(i("WHEN") !ident_char() _ w:expr() _ i("THEN") !ident_char() _ t:expr() _ { (w, t) })+
+          else_result:(i("ELSE") !ident_char() _ e:expr() _ { e })? i("END") !ident_char() {
+            Expr::Case {
+                operand: operand.map(Box::new),
+                branches,
+                else_result: else_result.map(Box::new),
+            }
+        }
+
+        rule exponent() = ['e' | 'E'] ['+' | '-']? ['0'..='9']+
+
+        rule literal() -> SerialValue
+        = n:$(['0'..='9']+ ("." ['0'..='9']* exponent()? / exponent())) {?
+            n.parse().map(SerialValue::Float64).or(Err("real"))
+        }
+        / n:$(['0'..='9']+) {?
+            n.parse()
+                .map(SerialValue::Int64)
+                .or_else(|_| n.parse().map(SerialValue::Float64))
+                .or(Err("integer"))
+        }
+        / s:string_literal() { SerialValue::Text(s) }
+        / i("X") "'" h:$(['0'..='9' | 'a'..='f' | 'A'..='F']*) "'" {?
+            decode_hex(h).map(SerialValue::Blob).ok_or("an even number of hex digits")
+        }
+        / i("NULL") !ident_char() { SerialValue::Null }
+
+        /// Each comma separated item in a CREATE TABLE is either a column definition or a
+        /// table-level constraint. Constraints are skipped so only real columns are returned.
+        rule table_item() -> TableItem
+        = table_constraint() / c:column() { TableItem::Column(c.0, c.1) }
+
+        rule table_constraint() -> TableItem
+        = (i("CONSTRAINT") _ ident() _)? item:(
+              i("PRIMARY") _ i("KEY") _ "(" _ c:(index_column() ++ (_ "," _)) _ ")" {
+                  TableItem::PrimaryKey(c.into_iter().map(|c| c.name).collect())
+              }
+              / i("UNIQUE") _ "(" _ c:(index_column() ++ (_ "," _)) _ ")" {
+                  TableItem::Unique(c.into_iter().map(|c| c.name).collect())
+              }
+              / (i("PRIMARY") _ i("KEY") / i("UNIQUE") / i("CHECK") / i("FOREIGN") _ i("KEY")) !ident_char() {
+                  TableItem::OtherConstraint
+              }
+          ) (_ column_token())* { item }
+
+        /// A column definition is a name followed by an optional type and any number of
+        /// constraints. Constraints other than those recorded are skipped up to the next
+        /// top-level comma or closing paren. Also returns whether the column is `UNIQUE`.
+        rule column() -> (ColumnDefinition, bool)
+        = name:ident() type_name:(_ t:type_name() { t })? constraints:(_ c:column_constraint() { c })* {
+            let mut column = ColumnDefinition {
+                name,
+                type_name,
+                primary_key: false,
+                collation: None,
+                default: None,
+            };
+            let mut unique = false;
+            for constraint in constraints {
+                match constraint {
+                    ColumnConstraint::PrimaryKey => column.primary_key = true,
+                    ColumnConstraint::Unique => unique = true,
+                    ColumnConstraint::Collate(collation) => column.collation = Some(collation),
+                    ColumnConstraint::Default(value) => column.default = Some(value),
+                    ColumnConstraint::Other => {}
+                }
+            }
+            (column, unique)
+        }
+
+        rule column_constraint() -> ColumnConstraint
+        = i("PRIMARY") _ i("KEY") !ident_char() { ColumnConstraint::PrimaryKey }
+        / i("UNIQUE") !ident_char() { ColumnConstraint::Unique }
+        / i("COLLATE") !ident_char() _ c:ident() { ColumnConstraint::Collate(c) }
+        / i("DEFAULT") !ident_char() _ v:default_value() { ColumnConstraint::Default(v) }
+        / column_token() { ColumnConstraint::Other }
+
+        /// A literal default, which may be signed or wrapped in parentheses. It has to end the
+        /// constraint, so the start of a default expression such as `(1 + 2)` doesn't match.
+        rule default_value() -> SerialValue
+        = "(" _ v:default_value() _ ")" { v }
+        / sign:$(['+' | '-'])? _ v:literal() &(_ ("," / ")" / ![_] / bare_ident())) {?
+            match (sign, v) {
+                (Some("-"), SerialValue::Int64(n)) => Ok(SerialValue::Int64(-n)),
+                (Some("-"), SerialValue::Float64(n)) => Ok(SerialValue::Float64(-n)),
+                (Some(_), SerialValue::Text(_) | SerialValue::Null) => Err("numeric literal"),
+                (_, v) => Ok(v),
+            }
+        }
+
+        /// One or more words, optionally followed by a size such as `(16)` or `(10, 2)`. The type
+        /// ends at the first word that starts a column constraint.
+        rule type_name() -> String
+        = t:$((!constraint_keyword() bare_ident()) ++ _ (_ "(" balanced() ")")?) { t.to_string() }
+
+        rule constraint_keyword()
+        = (i("CONSTRAINT") / i("PRIMARY") / i("NOT") / i("NULL") / i("UNIQUE") / i("CHECK")
+          / i("DEFAULT") / i("COLLATE") / i("REFERENCES") / i("GENERATED") / i("AS")) !ident_char()
+
+        rule column_token()
+        = string_literal() {} / "(" balanced() ")" / [^ ',' | '(' | ')' | '\'' | ' ' | '\t' | '\r' | '\n']+
+
+        rule balanced()
+        = (string_literal() {} / "(" balanced() ")" / [^ '(' | ')' | '\'']+)*
+
+        rule string_literal() -> String
+        = "'" s:$(([^ '\''] / "''")*) "'" { s.replace("''", "'") }
+
+        rule ident() -> String = quiet!{quoted_ident() / bare_ident()} / expected!("identifier")
+
+        rule bare_ident() -> String
+        = chars:$((alpha() / "_") [ 'a'..='z' | 'A'..='Z' | '_' | '0'..='9']*) { chars.to_string() }
+
+        /// SQLite accepts "double quoted", `backtick quoted` and [bracketed] identifiers. A quote
+        /// character is included in a quoted name by doubling it.
         rule quoted_ident() -> String
-        = iquote() chars:$(alpha() [ 'a'..='z' | 'A'..='Z' | '_' | '0'..='9' | ' ']*) iquote() { chars.to_string() }
+        = "\"" chars:$(([^'"'] / "\"\"")*) "\"" { chars.replace("\"\"", "\"") }
+        / "`" chars:$(([^'`'] / "``")*) "`" { chars.replace("``", "`") }
+        / "[" chars:$([^']']*) "]" { chars.to_string() }
 
-        rule iquote()
-        = ['"' | '`']
+        rule ident_char() = ['a'..='z' | 'A'..='Z' | '_' | '0'..='9']
 
         rule alpha() -> String
         = chars:$(['a'..='z' | 'A'..='Z']+) { chars.to_string() }
 
+        /// A case-insensitive keyword. A mismatch is reported at the start of the word rather than
+        /// after it, so parse errors point at the offending word.
         rule i(literal: &'static str) -> String
-        = input:$([_]*<{literal.len()}>)
-          {? if input.eq_ignore_ascii_case(literal) { Ok(literal.to_string()) } else { Err(literal) } }
+        = quiet!{
+            input:$([_]*<{literal.len()}>)
+            {? if input.eq_ignore_ascii_case(literal) { Ok(literal.to_string()) } else { Err(literal) } }
+          }
+        / expected!(literal)
+
+        rule _ = quiet!{[' ' | '\t' | '\r' | '\n']*}
+    }
+}
 
-        rule _ = [' ' | '\t' | '\n']*
+#[cfg(test)]
+fn column_definition(name: &str, type_name: Option<&str>) -> ColumnDefinition {
+    ColumnDefinition {
+        name: String::from(name),
+        type_name: type_name.map(String::from),
+        primary_key: false,
+        collation: None,
+        default: None,
     }
 }
 
+#[cfg(test)]
+fn unaliased(expr: Expr) -> ResultColumn {
+    ResultColumn { expr, alias: None }
+}
+
 #[test]
 fn select() {
     let statement = r#"
@@ -108,13 +1090,54 @@ fn select() {
     assert_eq!(
         sql::sql_statement(statement),
         Ok(Statement::Select(SelectStatement {
-            from: String::from("foobar"),
-            select: vec![String::from("id"), String::from("name")],
+            distinct: false,
+            from: Some(String::from("foobar")),
+            joins: vec![],
+            select: vec![
+                unaliased(Expr::Column(String::from("id"))),
+                unaliased(Expr::Column(String::from("name")))
+            ],
             where_clause: None,
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
         }))
     )
 }
 
+#[test]
+fn parse_error_position() {
+    let err = parse_statement("SELECT id FORM apples")
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("parse error at offset 10: expected one of"));
+    assert!(err.contains("FROM"));
+
+    assert_eq!(
+        parse_statement("CREATE TABLE (id integer)")
+            .unwrap_err()
+            .to_string(),
+        "parse error at offset 13: expected one of IF, identifier"
+    );
+}
+
+#[test]
+fn trailing_semicolon() {
+    for statement in [
+        "SELECT 1 FROM t",
+        "SELECT 1 FROM t;",
+        "  SELECT 1 FROM t ; \n",
+    ] {
+        assert!(matches!(
+            sql::sql_statement(statement),
+            Ok(Statement::Select(_))
+        ));
+    }
+    assert!(sql::sql_statement("SELECT 1 FROM t;;").is_err());
+}
+
 #[test]
 fn select_count() {
     let statement = r#"
@@ -126,9 +1149,19 @@ fn select_count() {
     assert_eq!(
         sql::sql_statement(statement),
         Ok(Statement::Select(SelectStatement {
-            from: String::from("foobar"),
-            select: vec![String::from("COUNT(*)")],
+            distinct: false,
+            from: Some(String::from("foobar")),
+            joins: vec![],
+            select: vec![unaliased(Expr::Function {
+                name: String::from("count"),
+                args: vec![Expr::Star],
+            })],
             where_clause: None,
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
         }))
     )
 }
@@ -146,12 +1179,23 @@ fn select_with_where() {
     assert_eq!(
         sql::sql_statement(statement),
         Ok(Statement::Select(SelectStatement {
-            from: String::from("foobar"),
-            select: vec![String::from("id"), String::from("name")],
-            where_clause: Some(WhereClause {
-                column: String::from("name"),
-                value: String::from("Some Guy"),
-            })
+            distinct: false,
+            from: Some(String::from("foobar")),
+            joins: vec![],
+            select: vec![
+                unaliased(Expr::Column(String::from("id"))),
+                unaliased(Expr::Column(String::from("name")))
+            ],
+            where_clause: Some(binary(
+                Expr::Column(String::from("name")),
+                BinaryOperator::Equal,
+                Expr::Literal(SerialValue::Text(String::from("Some Guy"))),
+            )),
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
         }))
     );
 
@@ -160,12 +1204,23 @@ fn select_with_where() {
     assert_eq!(
         sql::sql_statement(statement),
         Ok(Statement::Select(SelectStatement {
-            from: String::from("superheroes"),
-            select: vec![String::from("id"), String::from("name")],
-            where_clause: Some(WhereClause {
-                column: String::from("eye_color"),
-                value: String::from("Pink Eyes"),
-            })
+            distinct: false,
+            from: Some(String::from("superheroes")),
+            joins: vec![],
+            select: vec![
+                unaliased(Expr::Column(String::from("id"))),
+                unaliased(Expr::Column(String::from("name")))
+            ],
+            where_clause: Some(binary(
+                Expr::Column(String::from("eye_color")),
+                BinaryOperator::Equal,
+                Expr::Literal(SerialValue::Text(String::from("Pink Eyes"))),
+            )),
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
         }))
     );
 }
@@ -183,7 +1238,12 @@ fn create_table() {
         sql::sql_statement(statement),
         Ok(Statement::CreateTable(CreateTableStatement {
             name: String::from("foobar"),
-            columns: vec![String::from("id"), String::from("name")]
+            columns: vec![
+                column_definition("id", Some("integer autoincrement")),
+                column_definition("name", Some("varchar"))
+            ],
+            strict: false,
+            unique_keys: vec![],
         }))
     );
 
@@ -198,18 +1258,865 @@ fn create_table() {
         sql::sql_statement(statement),
         Ok(Statement::CreateTable(CreateTableStatement {
             name: String::from("companies"),
-            columns: vec![
-                String::from("id"),
-                String::from("name"),
-                String::from("domain"),
-                String::from("year_founded"),
-                String::from("industry"),
-                String::from("size range"),
-                String::from("locality"),
-                String::from("country"),
-                String::from("current_employees"),
-                String::from("total_employees"),
+            columns: [
+                "id",
+                "name",
+                "domain",
+                "year_founded",
+                "industry",
+                "size range",
+                "locality",
+                "country",
+                "current_employees",
+                "total_employees",
             ]
+            .into_iter()
+            .map(|name| ColumnDefinition {
+                primary_key: name == "id",
+                ..column_definition(name, Some(if name == "id" { "integer" } else { "text" }))
+            })
+            .collect(),
+            strict: false,
+            unique_keys: vec![],
         }))
     );
 }
+
+#[test]
+fn create_table_with_options() {
+    assert_eq!(
+        sql::sql_statement("CREATE TABLE t (id integer primary key, name text) strict"),
+        Ok(Statement::CreateTable(CreateTableStatement {
+            name: String::from("t"),
+            columns: vec![
+                ColumnDefinition {
+                    primary_key: true,
+                    ..column_definition("id", Some("integer"))
+                },
+                column_definition("name", Some("text")),
+            ],
+            strict: true,
+            unique_keys: vec![],
+        }))
+    );
+
+    for (statement, strict) in [
+        ("CREATE TABLE t (id text primary key) WITHOUT ROWID", false),
+        (
+            "CREATE TABLE t (id text primary key) WITHOUT ROWID, STRICT",
+            true,
+        ),
+        (
+            "CREATE TABLE t (id text primary key) STRICT , without  rowid;",
+            true,
+        ),
+    ] {
+        let Ok(Statement::CreateTable(table)) = sql::sql_statement(statement) else {
+            panic!("failed to parse {}", statement)
+        };
+        assert_eq!(table.strict, strict, "{}", statement);
+    }
+    assert!(sql::sql_statement("CREATE TABLE t (id integer) STRICTLY").is_err());
+}
+
+#[test]
+fn column_defaults() {
+    let Ok(Statement::CreateTable(table)) = sql::sql_statement(
+        "CREATE TABLE t (a integer DEFAULT -1 NOT NULL, b text default 'x', c real DEFAULT (2.5), \
+         d DEFAULT NULL, e DEFAULT (1 + 2), f text DEFAULT CURRENT_TIMESTAMP, g)",
+    ) else {
+        panic!("expected a create table statement")
+    };
+    assert_eq!(
+        table
+            .columns
+            .into_iter()
+            .map(|c| c.default)
+            .collect::<Vec<_>>(),
+        [
+            Some(SerialValue::Int64(-1)),
+            Some(SerialValue::Text(String::from("x"))),
+            Some(SerialValue::Float64(2.5)),
+            Some(SerialValue::Null),
+            None,
+            None,
+            None,
+        ]
+    );
+}
+
+#[test]
+fn create_table_if_not_exists() {
+    let statement = "CREATE TABLE IF NOT EXISTS foobar (id integer, name text)";
+
+    assert_eq!(
+        sql::sql_statement(statement),
+        Ok(Statement::CreateTable(CreateTableStatement {
+            name: String::from("foobar"),
+            columns: vec![
+                column_definition("id", Some("integer")),
+                column_definition("name", Some("text"))
+            ],
+            strict: false,
+            unique_keys: vec![],
+        }))
+    );
+}
+
+#[test]
+fn create_index_if_not_exists() {
+    let statement = "create index if not exists idx_foobar_name on foobar (name)";
+
+    assert_eq!(
+        sql::sql_statement(statement),
+        Ok(Statement::CreateIndex(CreateIndexStatement {
+            name: String::from("idx_foobar_name"),
+            table_name: String::from("foobar"),
+            columns: vec![IndexColumn {
+                name: String::from("name"),
+                expr: None,
+                descending: false,
+                collation: None,
+            }],
+            where_clause: None,
+        }))
+    );
+}
+
+#[test]
+fn create_index_column_order() {
+    let Ok(Statement::CreateIndex(index)) = sql::sql_statement(
+        "CREATE INDEX idx ON people (last_name DESC, first_name COLLATE NOCASE ASC, age)",
+    ) else {
+        panic!("expected a create index statement")
+    };
+    assert_eq!(index.column_names(), vec!["last_name", "first_name", "age"]);
+    assert_eq!(
+        index
+            .columns
+            .iter()
+            .map(|c| (c.descending, c.collation.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![(true, None), (false, Some("NOCASE")), (false, None)]
+    );
+}
+
+#[test]
+fn partial_and_expression_indexes() {
+    for (sql, partial_or_expression) in [
+        ("CREATE INDEX idx ON people (name)", false),
+        ("CREATE INDEX idx ON people (name) WHERE age > 18", true),
+        ("CREATE INDEX idx ON people (lower(name) DESC)", true),
+    ] {
+        let Ok(Statement::CreateIndex(index)) = sql::sql_statement(sql) else {
+            panic!("expected a create index statement: {}", sql)
+        };
+        assert_eq!(
+            index.is_partial_or_expression(),
+            partial_or_expression,
+            "{}",
+            sql
+        );
+    }
+}
+
+#[test]
+fn create_view() {
+    let Ok(Statement::CreateView(view)) = sql::sql_statement(
+        "CREATE VIEW IF NOT EXISTS red_apples AS SELECT id, name FROM apples WHERE color = 'Red'",
+    ) else {
+        panic!("expected a create view statement")
+    };
+    assert_eq!(view.name, "red_apples");
+    assert_eq!(view.select.from, Some(String::from("apples")));
+    assert_eq!(
+        view.select
+            .select
+            .iter()
+            .map(|c| c.name())
+            .collect::<Vec<_>>(),
+        ["id", "name"]
+    );
+    assert!(view.select.where_clause.is_some());
+}
+
+#[test]
+fn drop_statements() {
+    assert_eq!(
+        sql::sql_statement("DROP TABLE foo"),
+        Ok(Statement::Drop(DropStatement {
+            object_type: DropObjectType::Table,
+            name: String::from("foo"),
+            if_exists: false,
+        }))
+    );
+    assert_eq!(
+        sql::sql_statement("drop index if exists idx"),
+        Ok(Statement::Drop(DropStatement {
+            object_type: DropObjectType::Index,
+            name: String::from("idx"),
+            if_exists: true,
+        }))
+    );
+}
+
+#[test]
+fn write_statements() {
+    for (statement, expected) in [
+        (
+            "UPDATE apples SET color = 'Red' WHERE id = 1",
+            Statement::Update(String::from("apples")),
+        ),
+        (
+            "update or ignore apples set color = null",
+            Statement::Update(String::from("apples")),
+        ),
+        (
+            "DELETE FROM apples",
+            Statement::Delete(String::from("apples")),
+        ),
+    ] {
+        assert_eq!(sql::sql_statement(statement), Ok(expected), "{}", statement);
+    }
+}
+
+#[test]
+fn explain_query_plan() {
+    let Ok(Statement::Select(select)) = sql::sql_statement("SELECT name FROM apples WHERE id = 1")
+    else {
+        panic!("expected a select statement");
+    };
+    assert_eq!(
+        sql::sql_statement("explain query plan SELECT name FROM apples WHERE id = 1;"),
+        Ok(Statement::ExplainQueryPlan(select))
+    );
+    assert!(sql::sql_statement("EXPLAIN QUERY PLAN DELETE FROM apples").is_err());
+}
+
+#[test]
+fn insert_statements() {
+    assert_eq!(
+        sql::sql_statement("INSERT INTO apples (name, color) VALUES ('Fuji', 'Red')"),
+        Ok(Statement::Insert(InsertStatement {
+            table: String::from("apples"),
+            columns: Some(vec![String::from("name"), String::from("color")]),
+            rows: vec![vec![
+                Expr::Literal(SerialValue::Text(String::from("Fuji"))),
+                Expr::Literal(SerialValue::Text(String::from("Red"))),
+            ]],
+        }))
+    );
+    assert_eq!(
+        sql::sql_statement("insert or replace into \"red apples\" values (1, 'a;b'), (2, NULL);"),
+        Ok(Statement::Insert(InsertStatement {
+            table: String::from("red apples"),
+            columns: None,
+            rows: vec![
+                vec![
+                    Expr::Literal(SerialValue::Int64(1)),
+                    Expr::Literal(SerialValue::Text(String::from("a;b"))),
+                ],
+                vec![
+                    Expr::Literal(SerialValue::Int64(2)),
+                    Expr::Literal(SerialValue::Null),
+                ],
+            ],
+        }))
+    );
+    let Ok(Statement::Insert(insert)) =
+        sql::sql_statement("REPLACE INTO t(a) VALUES (-1), (2 * 3),(4)")
+    else {
+        panic!("expected an insert statement")
+    };
+    assert_eq!(insert.columns, Some(vec![String::from("a")]));
+    assert_eq!(
+        insert
+            .rows
+            .iter()
+            .map(|row| row[0].to_string())
+            .collect::<Vec<_>>(),
+        ["-1", "2 * 3", "4"]
+    );
+    for statement in [
+        "INSERT apples VALUES (1)",
+        "INSERT INTO apples VALUES",
+        "INSERT INTO apples () VALUES (1)",
+        "INSERT INTO apples VALUES (1), ",
+    ] {
+        assert!(sql::sql_statement(statement).is_err(), "{}", statement);
+    }
+}
+
+#[test]
+fn insert_values() {
+    let int = |n| Expr::Literal(SerialValue::Int64(n));
+    let Ok(Statement::Insert(insert)) = sql::sql_statement("INSERT INTO t VALUES (1,2),(3,4)")
+    else {
+        panic!("expected an insert statement")
+    };
+    assert_eq!(insert.rows, [[int(1), int(2)], [int(3), int(4)]]);
+
+    let Ok(Statement::Insert(insert)) = sql::sql_statement(
+        "INSERT INTO t (a, b, c) VALUES (1 + 2 * 3, upper('x') || 'y', (4 - 1) / 2)",
+    ) else {
+        panic!("expected an insert statement")
+    };
+    let [a, b, c] = &insert.rows[0][..] else {
+        panic!("expected three values")
+    };
+    assert_eq!(
+        *a,
+        Expr::Binary {
+            left: Box::new(int(1)),
+            op: BinaryOperator::Add,
+            right: Box::new(Expr::Binary {
+                left: Box::new(int(2)),
+                op: BinaryOperator::Multiply,
+                right: Box::new(int(3)),
+            }),
+        }
+    );
+    assert_eq!(b.to_string(), "upper('x') || 'y'");
+    assert_eq!(c.to_string(), "(4 - 1) / 2");
+}
+
+#[test]
+fn having() {
+    let Ok(Statement::Select(select)) = sql::sql_statement(
+        "SELECT country FROM companies GROUP BY country HAVING sum(employees) >= 1000 ORDER BY 1",
+    ) else {
+        panic!("expected a select statement")
+    };
+    assert_eq!(
+        select.having.map(|e| e.to_string()).as_deref(),
+        Some("sum(employees) >= 1000")
+    );
+    assert_eq!(select.order_by.len(), 1);
+
+    let Ok(Statement::Select(select)) =
+        sql::sql_statement("SELECT count(*) FROM t HAVING count(*) > 1")
+    else {
+        panic!("expected a select statement")
+    };
+    assert!(select.group_by.is_empty());
+    assert!(select.is_aggregate());
+}
+
+#[test]
+fn case_expressions() {
+    let Ok(Statement::Select(select)) = sql::sql_statement(
+        "SELECT case when a > 1 then 'big' when a = 1 then 'one' end, \
+         CASE a WHEN 1 THEN 'x' ELSE b || 'y' END AS kind, \
+         CASE WHEN case_count THEN endpoint END FROM t",
+    ) else {
+        panic!("expected a select statement")
+    };
+    assert_eq!(
+        select.select[0].expr,
+        Expr::Case {
+            operand: None,
+            branches: vec![
+                (
+                    binary(
+                        Expr::Column(String::from("a")),
+                        BinaryOperator::Greater,
+                        Expr::Literal(SerialValue::Int64(1))
+                    ),
+                    Expr::Literal(SerialValue::Text(String::from("big")))
+                ),
+                (
+                    binary(
+                        Expr::Column(String::from("a")),
+                        BinaryOperator::Equal,
+                        Expr::Literal(SerialValue::Int64(1))
+                    ),
+                    Expr::Literal(SerialValue::Text(String::from("one")))
+                ),
+            ],
+            else_result: None,
+        }
+    );
+    assert_eq!(
+        select.select.iter().map(|c| c.name()).collect::<Vec<_>>(),
+        [
+            "CASE WHEN a > 1 THEN 'big' WHEN a = 1 THEN 'one' END",
+            "kind",
+            "CASE WHEN case_count THEN endpoint END"
+        ]
+    );
+    assert_eq!(
+        select.select[1].expr.to_string(),
+        "CASE a WHEN 1 THEN 'x' ELSE b || 'y' END"
+    );
+
+    assert!(sql::sql_statement("SELECT CASE END FROM t").is_err());
+    assert!(sql::sql_statement("SELECT CASE WHEN a THEN 1 FROM t").is_err());
+}
+
+#[test]
+fn blob_literals() {
+    let Ok(Statement::Select(select)) =
+        sql::sql_statement("SELECT x'CAfe', X'' FROM t WHERE data = X'00'")
+    else {
+        panic!("expected a select statement")
+    };
+    assert_eq!(
+        select.select,
+        [
+            unaliased(Expr::Literal(SerialValue::Blob(vec![0xca, 0xfe]))),
+            unaliased(Expr::Literal(SerialValue::Blob(vec![]))),
+        ]
+    );
+    assert_eq!(
+        select.where_clause.unwrap().to_string(),
+        "data = X'00'",
+        "blobs are written back as literals"
+    );
+    assert!(sql::sql_statement("SELECT X'ABC'").is_err());
+    assert!(sql::sql_statement("SELECT X'GG'").is_err());
+    // Only a quote directly after the X starts a blob
+    assert_eq!(
+        sql::sql_statement("SELECT x FROM t"),
+        Ok(Statement::Select(SelectStatement {
+            distinct: false,
+            from: Some(String::from("t")),
+            joins: vec![],
+            select: vec![unaliased(Expr::Column(String::from("x")))],
+            where_clause: None,
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
+        }))
+    );
+}
+
+#[test]
+fn escaped_quotes_in_identifiers() {
+    let Ok(Statement::CreateTable(table)) = sql::sql_statement(
+        r#"CREATE TABLE "say ""hi""" ("a""b" text, `c``d` integer, "" text, """" text)"#,
+    ) else {
+        panic!("expected a create table statement")
+    };
+    assert_eq!(table.name, r#"say "hi""#);
+    assert_eq!(
+        table
+            .columns
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>(),
+        [r#"a"b"#, "c`d", "", r#"""#]
+    );
+
+    assert_eq!(
+        sql::sql_statement(r#"SELECT "a""b" FROM t"#),
+        sql::sql_statement(r#"SELECT `a"b` FROM t"#)
+    );
+    assert!(sql::sql_statement(r#"SELECT "a"b" FROM t"#).is_err());
+}
+
+#[test]
+fn quoted_identifiers() {
+    for (table, column) in [
+        ("\"foo bar\"", "\"first name\""),
+        ("`foo bar`", "`first name`"),
+        ("[foo bar]", "[first name]"),
+    ] {
+        let statement = format!("CREATE TABLE {table} (id integer, {column} text)");
+        assert_eq!(
+            sql::sql_statement(&statement),
+            Ok(Statement::CreateTable(CreateTableStatement {
+                name: String::from("foo bar"),
+                columns: vec![
+                    column_definition("id", Some("integer")),
+                    column_definition("first name", Some("text"))
+                ],
+                strict: false,
+                unique_keys: vec![],
+            }))
+        );
+
+        let statement = format!("SELECT {column} FROM {table}");
+        assert_eq!(
+            sql::sql_statement(&statement),
+            Ok(Statement::Select(SelectStatement {
+                distinct: false,
+                from: Some(String::from("foo bar")),
+                joins: vec![],
+                select: vec![unaliased(Expr::Column(String::from("first name")))],
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }))
+        );
+    }
+}
+
+#[test]
+fn create_table_with_constraints() {
+    let statement = r#"
+    CREATE TABLE orders (
+        id integer primary key,
+        customer_id integer not null references customers(id) on delete cascade,
+        quantity integer check (quantity > 0 and (quantity < 100)),
+        status varchar(16) default 'new, unpaid',
+        note
+    )
+    "#;
+
+    assert_eq!(
+        sql::sql_statement(statement),
+        Ok(Statement::CreateTable(CreateTableStatement {
+            name: String::from("orders"),
+            columns: vec![
+                ColumnDefinition {
+                    primary_key: true,
+                    ..column_definition("id", Some("integer"))
+                },
+                column_definition("customer_id", Some("integer")),
+                column_definition("quantity", Some("integer")),
+                ColumnDefinition {
+                    default: Some(SerialValue::Text(String::from("new, unpaid"))),
+                    ..column_definition("status", Some("varchar(16)"))
+                },
+                column_definition("note", None),
+            ],
+            strict: false,
+            unique_keys: vec![],
+        }))
+    );
+}
+
+#[test]
+fn create_table_with_table_constraints() {
+    let statement = r#"
+    CREATE TABLE enrolments (
+        student_id integer,
+        course_id integer,
+        unique_code text,
+        PRIMARY KEY (student_id, course_id),
+        CONSTRAINT code_unique UNIQUE (unique_code),
+        FOREIGN KEY (course_id) REFERENCES courses(id)
+    )
+    "#;
+
+    assert_eq!(
+        sql::sql_statement(statement),
+        Ok(Statement::CreateTable(CreateTableStatement {
+            name: String::from("enrolments"),
+            columns: vec![
+                column_definition("student_id", Some("integer")),
+                column_definition("course_id", Some("integer")),
+                column_definition("unique_code", Some("text")),
+            ],
+            strict: false,
+            unique_keys: vec![
+                vec![String::from("student_id"), String::from("course_id")],
+                vec![String::from("unique_code")],
+            ],
+        }))
+    );
+}
+
+#[test]
+fn unique_keys() {
+    for (statement, expected) in [
+        (
+            "CREATE TABLE t (id integer primary key, email text unique, a, b, UNIQUE (a, b))",
+            vec![vec!["email"], vec!["a", "b"]],
+        ),
+        (
+            "CREATE TABLE t (code text PRIMARY KEY, label text UNIQUE NOT NULL)",
+            vec![vec!["code"], vec!["label"]],
+        ),
+        // A table-level primary key on one integer column is still the rowid
+        (
+            "CREATE TABLE t (id integer, name text, PRIMARY KEY (id), UNIQUE (name))",
+            vec![vec!["name"]],
+        ),
+        // Repeating a key doesn't create another index
+        (
+            "CREATE TABLE t (a text primary key unique, b, UNIQUE (A), UNIQUE (b), UNIQUE (b))",
+            vec![vec!["a"], vec!["b"]],
+        ),
+    ] {
+        let Ok(Statement::CreateTable(table)) = sql::sql_statement(statement) else {
+            panic!("failed to parse {}", statement)
+        };
+        assert_eq!(table.unique_keys, expected, "{}", statement);
+    }
+}
+
+#[test]
+fn between() {
+    let Ok(Statement::Select(select)) =
+        sql::sql_statement("SELECT a NOT BETWEEN 1 AND 2 + 3 AND b FROM t")
+    else {
+        panic!("expected a select statement")
+    };
+    assert!(matches!(
+        &select.select[0].expr,
+        Expr::Binary { left, op: BinaryOperator::And, .. }
+            if matches!(left.as_ref(), Expr::Between { negated: true, .. })
+    ));
+    assert_eq!(select.select[0].name(), "a NOT BETWEEN 1 AND 2 + 3 AND b");
+}
+
+#[test]
+fn like_and_glob() {
+    let Ok(Statement::Select(select)) = sql::sql_statement(
+        "SELECT a like 'x%' AND b NOT LIKE 'y', c GLOB '*z' OR d not glob '?' FROM t",
+    ) else {
+        panic!("expected a select statement")
+    };
+    assert!(matches!(
+        &select.select[0].expr,
+        Expr::Binary { left, op: BinaryOperator::And, .. }
+            if matches!(left.as_ref(), Expr::Binary { op: BinaryOperator::Like, .. })
+    ));
+    assert_eq!(select.select[0].name(), "a LIKE 'x%' AND b NOT LIKE 'y'");
+    assert_eq!(select.select[1].name(), "c GLOB '*z' OR d NOT GLOB '?'");
+}
+
+#[test]
+fn complete_statements() {
+    for sql in [
+        "SELECT name FROM apples",
+        "SELECT name FROM apples;",
+        "SELECT name FROM apples WHERE name = 'a\nb'",
+        "SELECT name FROM FROM apples",
+    ] {
+        assert!(is_complete_statement(sql), "{}", sql);
+    }
+    for sql in [
+        "SELECT name FROM",
+        "SELECT name FROM apples WHERE name = 'a",
+        "SELECT count(name FROM apples",
+        "SELECT name FROM apples WHERE\n",
+    ] {
+        assert!(!is_complete_statement(sql), "{}", sql);
+    }
+}
+
+#[test]
+fn rowid_alias() {
+    for (sql, alias) in [
+        (
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, name text)",
+            Some("id"),
+        ),
+        (
+            "CREATE TABLE t (name text, id integer, PRIMARY KEY (id))",
+            Some("id"),
+        ),
+        ("CREATE TABLE t (id int primary key, name text)", None),
+        (
+            "CREATE TABLE t (a integer, b integer, PRIMARY KEY (a, b))",
+            None,
+        ),
+        ("CREATE TABLE t (id integer, name text)", None),
+    ] {
+        let Ok(Statement::CreateTable(table)) = sql::sql_statement(sql) else {
+            panic!("expected a create table statement: {}", sql)
+        };
+        assert_eq!(
+            table
+                .columns
+                .iter()
+                .find(|c| c.is_rowid_alias())
+                .map(|c| c.name.as_str()),
+            alias,
+            "{}",
+            sql
+        );
+    }
+}
+
+#[test]
+fn select_constant_expression() {
+    assert_eq!(
+        sql::sql_statement("SELECT 1 + 2 * -3, 'it''s', NULL"),
+        Ok(Statement::Select(SelectStatement {
+            distinct: false,
+            from: None,
+            joins: vec![],
+            select: vec![
+                unaliased(binary(
+                    Expr::Literal(SerialValue::Int64(1)),
+                    BinaryOperator::Add,
+                    binary(
+                        Expr::Literal(SerialValue::Int64(2)),
+                        BinaryOperator::Multiply,
+                        Expr::Unary {
+                            op: UnaryOperator::Negate,
+                            expr: Box::new(Expr::Literal(SerialValue::Int64(3))),
+                        },
+                    ),
+                )),
+                unaliased(Expr::Literal(SerialValue::Text(String::from("it's")))),
+                unaliased(Expr::Literal(SerialValue::Null)),
+            ],
+            where_clause: None,
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
+        }))
+    );
+
+    assert_eq!(
+        sql::sql_statement("select typeof(4.5)"),
+        Ok(Statement::Select(SelectStatement {
+            distinct: false,
+            from: None,
+            joins: vec![],
+            select: vec![unaliased(Expr::Function {
+                name: String::from("typeof"),
+                args: vec![Expr::Literal(SerialValue::Float64(4.5))],
+            })],
+            where_clause: None,
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
+        }))
+    );
+}
+
+#[test]
+fn display_expression() {
+    let sql = "SELECT (1 + 2) * -(3 - a), count(*), 'it''s' || name, NULL";
+    let Ok(Statement::Select(select)) = sql::sql_statement(sql) else {
+        panic!("expected a select statement")
+    };
+    assert_eq!(
+        select.select.iter().map(|c| c.name()).collect::<Vec<_>>(),
+        ["(1 + 2) * -(3 - a)", "count(*)", "'it''s' || name", "NULL"]
+    );
+}
+
+#[test]
+fn select_with_order_by() {
+    let ordering_term = |name: &str, descending, nulls_first| OrderingTerm {
+        expr: Expr::Column(String::from(name)),
+        descending,
+        nulls_first,
+    };
+
+    assert_eq!(
+        sql::sql_statement(
+            "SELECT a FROM t ORDER BY a, b DESC, c ASC NULLS LAST, d DESC NULLS FIRST, e nulls last"
+        ),
+        Ok(Statement::Select(SelectStatement {
+            distinct: false,
+            from: Some(String::from("t")),
+            joins: vec![],
+            select: vec![unaliased(Expr::Column(String::from("a")))],
+            where_clause: None,
+            group_by: vec![],
+            having: None,
+            order_by: vec![
+                ordering_term("a", false, true),
+                ordering_term("b", true, false),
+                ordering_term("c", false, false),
+                ordering_term("d", true, true),
+                ordering_term("e", false, false),
+            ],
+            limit: None,
+            offset: None,
+        }))
+    );
+}
+
+#[test]
+fn select_with_joins() {
+    let statement =
+        "SELECT apples.name, o.name FROM apples JOIN oranges ON apples.id = oranges.id + 1, pears \
+        WHERE color = 'Red'";
+    let Ok(Statement::Select(select)) = sql::sql_statement(statement) else {
+        panic!("expected a select statement")
+    };
+
+    assert_eq!(select.from, Some(String::from("apples")));
+    assert_eq!(
+        select.joins,
+        vec![
+            Join {
+                table: String::from("oranges"),
+                on: Some(binary(
+                    Expr::QualifiedColumn {
+                        table: String::from("apples"),
+                        column: String::from("id"),
+                    },
+                    BinaryOperator::Equal,
+                    binary(
+                        Expr::QualifiedColumn {
+                            table: String::from("oranges"),
+                            column: String::from("id"),
+                        },
+                        BinaryOperator::Add,
+                        Expr::Literal(SerialValue::Int64(1)),
+                    ),
+                )),
+            },
+            Join {
+                table: String::from("pears"),
+                on: None,
+            },
+        ]
+    );
+    assert_eq!(select.select[1].name(), "o.name");
+    assert!(select.where_clause.is_some());
+}
+
+#[test]
+fn logical_operator_precedence() {
+    let Ok(Statement::Select(select)) =
+        sql::sql_statement("SELECT NOT a = 1 OR b < 2 AND c <> 'x' FROM t ORDER BY a")
+    else {
+        panic!("expected a select statement")
+    };
+    assert_eq!(select.select[0].name(), "NOT a = 1 OR b < 2 AND c != 'x'");
+    let Expr::Binary {
+        op: BinaryOperator::Or,
+        left,
+        ..
+    } = &select.select[0].expr
+    else {
+        panic!("expected OR at the top of the expression")
+    };
+    assert!(matches!(
+        left.as_ref(),
+        Expr::Unary {
+            op: UnaryOperator::Not,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn select_with_limit() {
+    let limit_and_offset = |sql: &str| {
+        let Ok(Statement::Select(select)) = sql::sql_statement(sql) else {
+            panic!("expected a select statement")
+        };
+        (select.limit, select.offset)
+    };
+
+    assert_eq!(limit_and_offset("SELECT a FROM t LIMIT 5"), (Some(5), None));
+    assert_eq!(
+        limit_and_offset("SELECT a FROM t ORDER BY a LIMIT 5 OFFSET 10"),
+        (Some(5), Some(10))
+    );
+    assert_eq!(
+        limit_and_offset("SELECT a FROM t limit 10, 5"),
+        (Some(5), Some(10))
+    );
+    assert_eq!(limit_and_offset("SELECT a FROM t"), (None, None));
+}
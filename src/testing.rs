@@ -0,0 +1,42 @@
+//! Builders for records and cells, so tests can decode synthetic data without a database file.
+
+use crate::serial_value::SerialValue;
+use crate::WriteVarint;
+
+/// Encodes a varint, the inverse of `ReadVarint::read_varint`.
+pub fn varint(value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes
+        .write_varint(value)
+        .expect("writing to a Vec can't fail");
+    bytes
+}
+
+/// Assembles a record: the header size, the serial type of each value, then the values' bytes.
+pub fn record(values: &[SerialValue]) -> Vec<u8> {
+    let (serial_types, bodies): (Vec<_>, Vec<_>) = values.iter().map(SerialValue::encode).unzip();
+    let types = serial_types
+        .into_iter()
+        .flat_map(varint)
+        .collect::<Vec<_>>();
+
+    // The header size counts its own varint, which can make that varint longer
+    let mut header_size = types.len() + 1;
+    while varint(header_size as u64).len() + types.len() != header_size {
+        header_size = varint(header_size as u64).len() + types.len();
+    }
+
+    let mut record = varint(header_size as u64);
+    record.extend(types);
+    record.extend(bodies.concat());
+    record
+}
+
+/// Assembles a cell for a table leaf page: the payload size, the rowid, then the record.
+pub fn table_leaf_cell(rowid: u64, values: &[SerialValue]) -> Vec<u8> {
+    let record = record(values);
+    let mut cell = varint(record.len() as u64);
+    cell.extend(varint(rowid));
+    cell.extend(record);
+    cell
+}